@@ -0,0 +1,195 @@
+/* regex-capiの正規表現エンジンのC言語向けバインディング */
+
+#ifndef REGEX_CAPI_H
+#define REGEX_CAPI_H
+
+/* tests/header.rsでREGEX_CAPI_BLESS=1を指定し、cbindgenで生成する。直接編集しないこと */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// 大文字と小文字を区別しない（`(?i)`）
+#define REGEX_IGNORE_CASE 1
+
+// ^と$が行頭と行末にマッチ（`(?m)`）
+#define REGEX_MULTI_LINE (1 << 1)
+
+// .が改行にもマッチ（`(?s)`）
+#define REGEX_DOT_NL (1 << 2)
+
+// \w、\d、\s、\b、\BをASCIIの範囲で判定
+#define REGEX_ASCII (1 << 3)
+
+// POSIXの基本正規表現（BRE）として解釈
+#define REGEX_BASIC (1 << 4)
+
+// POSIXの拡張正規表現（ERE）として解釈
+#define REGEX_EXTENDED (1 << 5)
+
+// 深さ優先探索の代わりに幅優先探索で評価
+#define REGEX_BFS (1 << 6)
+
+// コンパイル済みの正規表現
+typedef struct Regex Regex;
+
+// マッチ範囲と、キャプチャグループの範囲
+typedef struct RegexCaptures RegexCaptures;
+
+// エラーの内容
+typedef struct RegexError RegexError;
+
+// 重ならないマッチ範囲を先頭から順に返すイテレータ
+typedef struct RegexIter RegexIter;
+
+// バイト単位の範囲
+typedef struct {
+  size_t start;
+  size_t end;
+} RegexSpan;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// パターンをコンパイル。
+//
+// flagsは`REGEX_*`の論理和。失敗した場合はNULLを返し、errorにエラーを書き込む。
+// 返したハンドルは[regex_free]で解放すること。
+//
+// # Safety
+//
+// patternはNUL終端の文字列、errorはNULLまたは書き込み可能なポインタであること。
+Regex *regex_compile(const char *pattern,
+                     uint32_t flags,
+                     RegexError **error);
+
+// [regex_compile]で生成したハンドルを解放。NULLの場合は何もしない
+//
+// # Safety
+//
+// reはNULLまたは[regex_compile]が返した未解放のポインタであること。
+void regex_free(Regex *re);
+
+// 入力全体のうち最初にマッチする範囲を探し、マッチした場合にtrueを返す。
+//
+// outがNULLでなければ、マッチ範囲を書き込む。
+// 入力が不正なUTF-8の場合や評価に失敗した場合はfalseを返し、errorにエラーを書き込む。
+//
+// # Safety
+//
+// reは有効なハンドル、haystackはlenバイト読み込めるポインタ（lenが0の場合はNULLも可）、
+// outとerrorはNULLまたは書き込み可能なポインタであること。
+bool regex_find(const Regex *re,
+                const uint8_t *haystack,
+                size_t len,
+                RegexSpan *out,
+                RegexError **error);
+
+// 重ならないマッチ範囲を先頭から順に返すイテレータを生成。
+//
+// 入力は生成時に複製するため、haystackは呼び出し後に解放してよい。
+// 失敗した場合はNULLを返し、errorにエラーを書き込む。
+// 返したハンドルは[regex_iter_free]で解放すること。
+//
+// # Safety
+//
+// reは有効なハンドルで、イテレータを解放するまで解放しないこと。
+// haystackとerrorは[regex_find]と同じ条件を満たすこと。
+RegexIter *regex_iter_new(const Regex *re, const uint8_t *haystack, size_t len, RegexError **error);
+
+// 次のマッチ範囲をoutに書き込み、trueを返す。
+//
+// マッチがもうない場合はfalseを返す。
+// 評価に失敗した場合はfalseを返し、errorにエラーを書き込む。以降はfalseを返し続ける。
+//
+// # Safety
+//
+// itは有効なハンドル、outは書き込み可能なポインタ、
+// errorはNULLまたは書き込み可能なポインタであること。
+bool regex_iter_next(RegexIter *it,
+                     RegexSpan *out,
+                     RegexError **error);
+
+// [regex_iter_new]で生成したハンドルを解放。NULLの場合は何もしない
+//
+// # Safety
+//
+// itはNULLまたは[regex_iter_new]が返した未解放のポインタであること。
+void regex_iter_free(RegexIter *it);
+
+// 入力全体のうち最初にマッチする範囲と、そのキャプチャグループの範囲を返す。
+//
+// マッチしない場合はNULLを返す。
+// 入力が不正なUTF-8の場合や評価に失敗した場合もNULLを返し、errorにエラーを書き込む。
+// 返したハンドルは[regex_captures_free]で解放すること。
+//
+// # Safety
+//
+// 引数は[regex_find]と同じ条件を満たすこと。
+RegexCaptures *regex_captures(const Regex *re,
+                              const uint8_t *haystack,
+                              size_t len,
+                              RegexError **error);
+
+// グループの数。0番目のマッチ全体を含む
+//
+// # Safety
+//
+// capsは有効なハンドルであること。
+size_t regex_captures_len(const RegexCaptures *caps);
+
+// i番目のグループの範囲をoutに書き込み、trueを返す。
+//
+// iが範囲外の場合や、グループがマッチに関与しなかった場合はfalseを返す。
+//
+// # Safety
+//
+// capsは有効なハンドル、outは書き込み可能なポインタであること。
+bool regex_captures_get(const RegexCaptures *caps,
+                        size_t i,
+                        RegexSpan *out);
+
+// [regex_captures]で生成したハンドルを解放。NULLの場合は何もしない
+//
+// # Safety
+//
+// capsはNULLまたは[regex_captures]が返した未解放のポインタであること。
+void regex_captures_free(RegexCaptures *caps);
+
+// i番目のグループの名前。名前のない場合や範囲外の場合はNULLを返す。
+//
+// 文字列はreが解放されるまで有効。
+//
+// # Safety
+//
+// reは有効なハンドルであること。
+const char *regex_capture_name(const Regex *re, size_t i);
+
+// エラーの内容を表すNUL終端の文字列。文字列はerrが解放されるまで有効
+//
+// # Safety
+//
+// errは有効なハンドルであること。
+const char *regex_error_message(const RegexError *err);
+
+// パースエラーの場合は、原因となったパターン中の範囲をoutに書き込み、trueを返す
+//
+// # Safety
+//
+// errは有効なハンドル、outは書き込み可能なポインタであること。
+bool regex_error_span(const RegexError *err,
+                      RegexSpan *out);
+
+// エラーのハンドルを解放。NULLの場合は何もしない
+//
+// # Safety
+//
+// errはNULLまたはいずれかの関数がerrorに書き込んだ未解放のポインタであること。
+void regex_error_free(RegexError *err);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* REGEX_CAPI_H */
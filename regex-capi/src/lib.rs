@@ -0,0 +1,473 @@
+//! # 正規表現エンジンのC言語向けバインディング
+//!
+//! コンパイル済みの正規表現やマッチ結果は不透明なポインタ（ハンドル）として返し、
+//! それぞれ対応する`*_free`関数で解放する。
+//! ヘッダファイル`include/regex_capi.h`はcbindgenで生成し、リポジトリに含める。
+//! `src/lib.rs`を変更した場合は、`REGEX_CAPI_BLESS=1 cargo test -p regex-capi --test header`で更新する。
+//!
+//! 入力文字列はUTF-8のバイト列とその長さで渡し、範囲はバイト単位のオフセットで返す。
+//! 失敗しうる関数は最後の引数`error`にエラーのハンドルを書き込む。
+//! `error`がNULLの場合は書き込まない。
+//!
+//! ```c
+//! RegexError *error = NULL;
+//! Regex *re = regex_compile("a(b|c)+", 0, &error);
+//! RegexSpan span;
+//! if (regex_find(re, (const uint8_t *)"xabcb", 5, &span, &error)) {
+//!     printf("%zu..%zu\n", span.start, span.end); // 1..5
+//! }
+//! regex_free(re);
+//! ```
+use regex::{Error, Flags, Syntax};
+use std::{
+    ffi::{c_char, CStr, CString},
+    ops::Range,
+    ptr, slice,
+};
+
+/// 大文字と小文字を区別しない（`(?i)`）
+pub const REGEX_IGNORE_CASE: u32 = 1;
+/// ^と$が行頭と行末にマッチ（`(?m)`）
+pub const REGEX_MULTI_LINE: u32 = 1 << 1;
+/// .が改行にもマッチ（`(?s)`）
+pub const REGEX_DOT_NL: u32 = 1 << 2;
+/// \w、\d、\s、\b、\BをASCIIの範囲で判定
+pub const REGEX_ASCII: u32 = 1 << 3;
+/// POSIXの基本正規表現（BRE）として解釈
+pub const REGEX_BASIC: u32 = 1 << 4;
+/// POSIXの拡張正規表現（ERE）として解釈
+pub const REGEX_EXTENDED: u32 = 1 << 5;
+/// 深さ優先探索の代わりに幅優先探索で評価
+pub const REGEX_BFS: u32 = 1 << 6;
+
+/// バイト単位の範囲
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegexSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// コンパイル済みの正規表現
+pub struct Regex {
+    re: regex::Regex,
+    names: Vec<Option<CString>>, // キャプチャグループの名前
+}
+
+/// エラーの内容
+pub struct RegexError {
+    message: CString,
+    span: Option<RegexSpan>, // パースエラーの場合は、原因となったパターン中の範囲
+}
+
+/// 重ならないマッチ範囲を先頭から順に返すイテレータ
+pub struct RegexIter {
+    re: *const Regex,
+    haystack: String,
+    pos: Option<usize>, // 次の探索開始位置。探索終了時はNone
+}
+
+/// マッチ範囲と、キャプチャグループの範囲
+pub struct RegexCaptures {
+    spans: Vec<Option<RegexSpan>>,
+}
+
+/// ptrからlenバイトをUTF-8の文字列として借用する
+unsafe fn to_str<'a>(ptr: *const u8, len: usize) -> Result<&'a str, RegexError> {
+    let bytes = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(ptr, len)
+    };
+    std::str::from_utf8(bytes)
+        .map_err(|e| RegexError::new(&format!("invalid UTF-8 in input: {e}"), None))
+}
+
+impl From<Range<usize>> for RegexSpan {
+    fn from(r: Range<usize>) -> Self {
+        RegexSpan {
+            start: r.start,
+            end: r.end,
+        }
+    }
+}
+
+impl RegexError {
+    fn new(message: &str, span: Option<RegexSpan>) -> Self {
+        // メッセージにNULは含まれないが、念のため置き換える
+        let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+        RegexError { message, span }
+    }
+
+    /// パターンのコンパイルで起きたエラーから生成。範囲はパターン中のバイト位置
+    fn from_error(e: &Error) -> Self {
+        RegexError::new(&e.to_string(), e.span().map(RegexSpan::from))
+    }
+}
+
+/// errorがNULLでなければ、エラーのハンドルを書き込む
+unsafe fn set_error(error: *mut *mut RegexError, e: RegexError) {
+    if !error.is_null() {
+        *error = Box::into_raw(Box::new(e));
+    }
+}
+
+/// `REGEX_*`の論理和をフラグに変換
+fn to_flags(flags: u32) -> Flags {
+    Flags {
+        ignore_case: flags & REGEX_IGNORE_CASE != 0,
+        multi_line: flags & REGEX_MULTI_LINE != 0,
+        dot_nl: flags & REGEX_DOT_NL != 0,
+        ascii: flags & REGEX_ASCII != 0,
+        syntax: if flags & REGEX_BASIC != 0 {
+            Syntax::Basic
+        } else if flags & REGEX_EXTENDED != 0 {
+            Syntax::Extended
+        } else {
+            Syntax::Native
+        },
+    }
+}
+
+/// パターンをコンパイル。
+///
+/// flagsは`REGEX_*`の論理和。失敗した場合はNULLを返し、errorにエラーを書き込む。
+/// 返したハンドルは[regex_free]で解放すること。
+///
+/// # Safety
+///
+/// patternはNUL終端の文字列、errorはNULLまたは書き込み可能なポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_compile(
+    pattern: *const c_char,
+    flags: u32,
+    error: *mut *mut RegexError,
+) -> *mut Regex {
+    let pattern = match CStr::from_ptr(pattern).to_str() {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            let msg = format!("invalid UTF-8 in pattern: {e}");
+            set_error(error, RegexError::new(&msg, None));
+            return ptr::null_mut();
+        }
+    };
+    match regex::Regex::with_flags(pattern, to_flags(flags), flags & REGEX_BFS == 0) {
+        Ok(re) => {
+            let names = re
+                .capture_names()
+                .iter()
+                .map(|name| name.as_deref().and_then(|n| CString::new(n).ok()))
+                .collect();
+            Box::into_raw(Box::new(Regex { re, names }))
+        }
+        Err(e) => {
+            set_error(error, RegexError::from_error(&e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// [regex_compile]で生成したハンドルを解放。NULLの場合は何もしない
+///
+/// # Safety
+///
+/// reはNULLまたは[regex_compile]が返した未解放のポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_free(re: *mut Regex) {
+    if !re.is_null() {
+        drop(Box::from_raw(re));
+    }
+}
+
+/// 入力全体のうち最初にマッチする範囲を探し、マッチした場合にtrueを返す。
+///
+/// outがNULLでなければ、マッチ範囲を書き込む。
+/// 入力が不正なUTF-8の場合や評価に失敗した場合はfalseを返し、errorにエラーを書き込む。
+///
+/// # Safety
+///
+/// reは有効なハンドル、haystackはlenバイト読み込めるポインタ（lenが0の場合はNULLも可）、
+/// outとerrorはNULLまたは書き込み可能なポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_find(
+    re: *const Regex,
+    haystack: *const u8,
+    len: usize,
+    out: *mut RegexSpan,
+    error: *mut *mut RegexError,
+) -> bool {
+    let re = &*re;
+    let text = match to_str(haystack, len) {
+        Ok(text) => text,
+        Err(e) => {
+            set_error(error, e);
+            return false;
+        }
+    };
+    match re.re.find_at(text, 0) {
+        Ok(Some(m)) => {
+            if !out.is_null() {
+                *out = m.into();
+            }
+            true
+        }
+        Ok(None) => false,
+        Err(e) => {
+            set_error(error, RegexError::new(&e.to_string(), None));
+            false
+        }
+    }
+}
+
+/// 重ならないマッチ範囲を先頭から順に返すイテレータを生成。
+///
+/// 入力は生成時に複製するため、haystackは呼び出し後に解放してよい。
+/// 失敗した場合はNULLを返し、errorにエラーを書き込む。
+/// 返したハンドルは[regex_iter_free]で解放すること。
+///
+/// # Safety
+///
+/// reは有効なハンドルで、イテレータを解放するまで解放しないこと。
+/// haystackとerrorは[regex_find]と同じ条件を満たすこと。
+#[no_mangle]
+pub unsafe extern "C" fn regex_iter_new(
+    re: *const Regex,
+    haystack: *const u8,
+    len: usize,
+    error: *mut *mut RegexError,
+) -> *mut RegexIter {
+    match to_str(haystack, len) {
+        Ok(text) => Box::into_raw(Box::new(RegexIter {
+            re,
+            haystack: text.to_string(),
+            pos: Some(0),
+        })),
+        Err(e) => {
+            set_error(error, e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// 次のマッチ範囲をoutに書き込み、trueを返す。
+///
+/// マッチがもうない場合はfalseを返す。
+/// 評価に失敗した場合はfalseを返し、errorにエラーを書き込む。以降はfalseを返し続ける。
+///
+/// # Safety
+///
+/// itは有効なハンドル、outは書き込み可能なポインタ、
+/// errorはNULLまたは書き込み可能なポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_iter_next(
+    it: *mut RegexIter,
+    out: *mut RegexSpan,
+    error: *mut *mut RegexError,
+) -> bool {
+    let it = &mut *it;
+    let Some(pos) = it.pos else {
+        return false;
+    };
+    let text = it.haystack.as_str();
+    match (*it.re).re.find_at(text, pos) {
+        Ok(Some(m)) => {
+            // 空文字列にマッチした場合は、同じ位置で再度マッチしないよう1文字進める
+            it.pos = if m.is_empty() {
+                text[m.end..].chars().next().map(|c| m.end + c.len_utf8())
+            } else {
+                Some(m.end)
+            };
+            *out = m.into();
+            true
+        }
+        Ok(None) => {
+            it.pos = None;
+            false
+        }
+        Err(e) => {
+            it.pos = None;
+            set_error(error, RegexError::new(&e.to_string(), None));
+            false
+        }
+    }
+}
+
+/// [regex_iter_new]で生成したハンドルを解放。NULLの場合は何もしない
+///
+/// # Safety
+///
+/// itはNULLまたは[regex_iter_new]が返した未解放のポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_iter_free(it: *mut RegexIter) {
+    if !it.is_null() {
+        drop(Box::from_raw(it));
+    }
+}
+
+/// 入力全体のうち最初にマッチする範囲と、そのキャプチャグループの範囲を返す。
+///
+/// マッチしない場合はNULLを返す。
+/// 入力が不正なUTF-8の場合や評価に失敗した場合もNULLを返し、errorにエラーを書き込む。
+/// 返したハンドルは[regex_captures_free]で解放すること。
+///
+/// # Safety
+///
+/// 引数は[regex_find]と同じ条件を満たすこと。
+#[no_mangle]
+pub unsafe extern "C" fn regex_captures(
+    re: *const Regex,
+    haystack: *const u8,
+    len: usize,
+    error: *mut *mut RegexError,
+) -> *mut RegexCaptures {
+    let re = &*re;
+    let text = match to_str(haystack, len) {
+        Ok(text) => text,
+        Err(e) => {
+            set_error(error, e);
+            return ptr::null_mut();
+        }
+    };
+    match re.re.find_captures_at(text, 0) {
+        Ok(Some(caps)) => {
+            let spans = caps.iter().map(|s| s.map(RegexSpan::from)).collect();
+            Box::into_raw(Box::new(RegexCaptures { spans }))
+        }
+        Ok(None) => ptr::null_mut(),
+        Err(e) => {
+            set_error(error, RegexError::new(&e.to_string(), None));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// グループの数。0番目のマッチ全体を含む
+///
+/// # Safety
+///
+/// capsは有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_captures_len(caps: *const RegexCaptures) -> usize {
+    (*caps).spans.len()
+}
+
+/// i番目のグループの範囲をoutに書き込み、trueを返す。
+///
+/// iが範囲外の場合や、グループがマッチに関与しなかった場合はfalseを返す。
+///
+/// # Safety
+///
+/// capsは有効なハンドル、outは書き込み可能なポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_captures_get(
+    caps: *const RegexCaptures,
+    i: usize,
+    out: *mut RegexSpan,
+) -> bool {
+    let caps = &*caps;
+    match caps.spans.get(i) {
+        Some(Some(span)) => {
+            *out = *span;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// [regex_captures]で生成したハンドルを解放。NULLの場合は何もしない
+///
+/// # Safety
+///
+/// capsはNULLまたは[regex_captures]が返した未解放のポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_captures_free(caps: *mut RegexCaptures) {
+    if !caps.is_null() {
+        drop(Box::from_raw(caps));
+    }
+}
+
+/// i番目のグループの名前。名前のない場合や範囲外の場合はNULLを返す。
+///
+/// 文字列はreが解放されるまで有効。
+///
+/// # Safety
+///
+/// reは有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_capture_name(re: *const Regex, i: usize) -> *const c_char {
+    let re = &*re;
+    match re.names.get(i) {
+        Some(Some(name)) => name.as_ptr(),
+        _ => ptr::null(),
+    }
+}
+
+/// エラーの内容を表すNUL終端の文字列。文字列はerrが解放されるまで有効
+///
+/// # Safety
+///
+/// errは有効なハンドルであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_error_message(err: *const RegexError) -> *const c_char {
+    (*err).message.as_ptr()
+}
+
+/// パースエラーの場合は、原因となったパターン中の範囲をoutに書き込み、trueを返す
+///
+/// # Safety
+///
+/// errは有効なハンドル、outは書き込み可能なポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_error_span(err: *const RegexError, out: *mut RegexSpan) -> bool {
+    match (*err).span {
+        Some(span) => {
+            *out = span;
+            true
+        }
+        None => false,
+    }
+}
+
+/// エラーのハンドルを解放。NULLの場合は何もしない
+///
+/// # Safety
+///
+/// errはNULLまたはいずれかの関数がerrorに書き込んだ未解放のポインタであること。
+#[no_mangle]
+pub unsafe extern "C" fn regex_error_free(err: *mut RegexError) {
+    if !err.is_null() {
+        drop(Box::from_raw(err));
+    }
+}
+
+#[test]
+fn test() {
+    let mut error = ptr::null_mut();
+    unsafe {
+        let re = regex_compile(c"(?<k>é+)|x".as_ptr(), 0, &mut error);
+        assert!(!re.is_null());
+        let text = "aéé x";
+        let mut span = RegexSpan { start: 0, end: 0 };
+        assert!(regex_find(
+            re,
+            text.as_ptr(),
+            text.len(),
+            &mut span,
+            &mut error
+        ));
+        assert_eq!(span, RegexSpan { start: 1, end: 5 });
+        regex_free(re);
+
+        let re = regex_compile(c"aé(".as_ptr(), 0, &mut error);
+        assert!(re.is_null());
+        assert!(regex_error_span(error, &mut span));
+        assert_eq!(span, RegexSpan { start: 4, end: 4 });
+        regex_error_free(error);
+
+        let re = regex_compile(c"éé)".as_ptr(), 0, &mut error);
+        assert!(re.is_null());
+        assert!(regex_error_span(error, &mut span));
+        assert_eq!(span, RegexSpan { start: 4, end: 5 });
+        regex_error_free(error);
+    }
+}
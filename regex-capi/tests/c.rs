@@ -0,0 +1,36 @@
+//! tests/c/test.cをシステムのCコンパイラ（cc）でビルドし、共有ライブラリとリンクして実行する
+#![cfg(target_os = "linux")]
+use std::{env, path::PathBuf, process::Command};
+
+#[test]
+fn test_c() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // 共有ライブラリlibregex_capi.soは、テストの実行ファイルと同じディレクトリに生成される
+    let exe = env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap();
+    let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("regex_capi_test");
+
+    let status = Command::new("cc")
+        .args(["-std=c99", "-Wall", "-Wextra", "-Werror", "-I"])
+        .arg(root.join("include"))
+        .arg(root.join("tests/c/test.c"))
+        .arg("-o")
+        .arg(&out)
+        .arg("-L")
+        .arg(lib_dir)
+        .arg("-lregex_capi")
+        .status()
+        .expect("failed to run cc");
+    assert!(status.success(), "failed to compile test.c");
+
+    let output = Command::new(&out)
+        .env("LD_LIBRARY_PATH", lib_dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ok\n");
+}
@@ -0,0 +1,156 @@
+/* regex_capi.hの関数をCから呼び出し、結果を検証する */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "regex_capi.h"
+
+#define CHECK(cond)                                                   \
+    do {                                                              \
+        if (!(cond)) {                                                \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,    \
+                    __LINE__, #cond);                                 \
+            exit(1);                                                  \
+        }                                                             \
+    } while (0)
+
+static Regex *compile(const char *pattern, uint32_t flags) {
+    RegexError *error = NULL;
+    Regex *re = regex_compile(pattern, flags, &error);
+    if (re == NULL) {
+        fprintf(stderr, "%s: %s\n", pattern, regex_error_message(error));
+        regex_error_free(error);
+        exit(1);
+    }
+    return re;
+}
+
+static bool find(const Regex *re, const char *text, RegexSpan *span) {
+    return regex_find(re, (const uint8_t *)text, strlen(text), span, NULL);
+}
+
+static void test_find(void) {
+    RegexSpan span;
+    Regex *re = compile("a(b|c)+", 0);
+    CHECK(find(re, "xabcb", &span));
+    CHECK(span.start == 1 && span.end == 5);
+    CHECK(!find(re, "xyz", &span));
+    CHECK(regex_find(re, NULL, 0, NULL, NULL) == false);
+    regex_free(re);
+
+    /* 範囲はバイト単位 */
+    re = compile("b+", REGEX_BFS);
+    CHECK(find(re, "\xc3\xa9\xc3\xa9" "bb", &span));
+    CHECK(span.start == 4 && span.end == 6);
+    regex_free(re);
+
+    re = compile("ABC", REGEX_IGNORE_CASE);
+    CHECK(find(re, "xabc", NULL));
+    regex_free(re);
+
+    re = compile("a\\{2\\}", REGEX_BASIC);
+    CHECK(find(re, "baa", &span));
+    CHECK(span.start == 1 && span.end == 3);
+    regex_free(re);
+}
+
+static void test_iter(void) {
+    const char *text = "abbcb";
+    size_t expected[][2] = {{1, 3}, {4, 5}};
+    size_t n = 0;
+    RegexSpan span;
+    Regex *re = compile("b+", 0);
+    RegexIter *it = regex_iter_new(re, (const uint8_t *)text, strlen(text), NULL);
+    CHECK(it != NULL);
+    while (regex_iter_next(it, &span, NULL)) {
+        CHECK(n < 2);
+        CHECK(span.start == expected[n][0] && span.end == expected[n][1]);
+        n++;
+    }
+    CHECK(n == 2);
+    CHECK(!regex_iter_next(it, &span, NULL));
+    regex_iter_free(it);
+
+    /* 空文字列へのマッチは1文字ずつ進む */
+    n = 0;
+    it = regex_iter_new(re, (const uint8_t *)"", 0, NULL);
+    while (regex_iter_next(it, &span, NULL)) {
+        n++;
+    }
+    CHECK(n == 0);
+    regex_iter_free(it);
+    regex_free(re);
+
+    re = compile("x*", 0);
+    it = regex_iter_new(re, (const uint8_t *)"ab", 2, NULL);
+    while (regex_iter_next(it, &span, NULL)) {
+        CHECK(span.start == n && span.end == n);
+        n++;
+    }
+    CHECK(n == 3);
+    regex_iter_free(it);
+    regex_free(re);
+}
+
+static void test_captures(void) {
+    const char *text = "x: ab=";
+    RegexSpan span;
+    Regex *re = compile("(?<key>[a-z]+)=([0-9]+)?", 0);
+    RegexCaptures *caps = regex_captures(re, (const uint8_t *)text, strlen(text), NULL);
+    CHECK(caps != NULL);
+    CHECK(regex_captures_len(caps) == 3);
+    CHECK(regex_captures_get(caps, 0, &span));
+    CHECK(span.start == 3 && span.end == 6);
+    CHECK(regex_captures_get(caps, 1, &span));
+    CHECK(span.start == 3 && span.end == 5);
+    CHECK(!regex_captures_get(caps, 2, &span));
+    CHECK(!regex_captures_get(caps, 3, &span));
+    regex_captures_free(caps);
+
+    CHECK(strcmp(regex_capture_name(re, 1), "key") == 0);
+    CHECK(regex_capture_name(re, 2) == NULL);
+    CHECK(regex_capture_name(re, 3) == NULL);
+
+    CHECK(regex_captures(re, (const uint8_t *)"xyz", 3, NULL) == NULL);
+    regex_free(re);
+}
+
+static void test_error(void) {
+    RegexError *error = NULL;
+    RegexSpan span;
+    CHECK(regex_compile("ab)", 0, &error) == NULL);
+    CHECK(error != NULL);
+    CHECK(strstr(regex_error_message(error), "ParseError") != NULL);
+    CHECK(regex_error_span(error, &span));
+    CHECK(span.start == 2 && span.end == 3);
+    regex_error_free(error);
+
+    /* アトミックグループは幅優先探索では評価できない */
+    error = NULL;
+    CHECK(regex_compile("(?>a)", REGEX_BFS, &error) == NULL);
+    CHECK(!regex_error_span(error, &span));
+    regex_error_free(error);
+
+    /* 不正なUTF-8の入力 */
+    error = NULL;
+    Regex *re = compile("a", 0);
+    CHECK(!regex_find(re, (const uint8_t *)"\xff", 1, NULL, &error));
+    CHECK(error != NULL);
+    regex_error_free(error);
+    regex_free(re);
+
+    /* NULLの解放は何もしない */
+    regex_free(NULL);
+    regex_iter_free(NULL);
+    regex_captures_free(NULL);
+    regex_error_free(NULL);
+}
+
+int main(void) {
+    test_find();
+    test_iter();
+    test_captures();
+    test_error();
+    printf("ok\n");
+    return 0;
+}
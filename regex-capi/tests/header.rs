@@ -0,0 +1,28 @@
+//! include/regex_capi.hが、src/lib.rsからcbindgenで生成した内容と一致するか確かめる
+//!
+//! 環境変数REGEX_CAPI_BLESSを指定して実行した場合は、生成した内容でヘッダファイルを更新する。
+use std::{env, fs, path::PathBuf};
+
+#[test]
+fn test_header() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml")).unwrap();
+    let mut generated = Vec::new();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(root.join("src/lib.rs"))
+        .generate()
+        .expect("failed to generate header")
+        .write(&mut generated);
+
+    let path = root.join("include/regex_capi.h");
+    if env::var_os("REGEX_CAPI_BLESS").is_some() {
+        fs::write(&path, &generated).unwrap();
+        return;
+    }
+    let current = fs::read(&path).unwrap();
+    assert!(
+        current == generated,
+        "include/regex_capi.h is stale; run `REGEX_CAPI_BLESS=1 cargo test -p regex-capi --test header`"
+    );
+}
@@ -0,0 +1,126 @@
+//! # コンパイル時に正規表現を検証する`regex!`マクロ
+//!
+//! パターンのパースとコード生成をコンパイル時に行い、
+//! 生成した命令列を[regex::Regex::to_bytes]の形式でstaticな配列として埋め込む。
+//! パターンに誤りがある場合は、実行時ではなく`cargo build`の時点でエラーとなる。
+//!
+//! ## 利用例
+//!
+//! ```
+//! use regex_macros::regex;
+//! let re = regex!("a(b|c)+"); // 深さ優先探索
+//! let line = "xabcb".chars().collect::<Vec<char>>();
+//! assert_eq!(re.find_at(&line, 0).unwrap(), Some(1..5));
+//!
+//! let re = regex!(r"(?i)[a-z]+\.rs", bfs); // 幅優先探索
+//! let line = "MAIN.RS".chars().collect::<Vec<char>>();
+//! assert_eq!(re.match_at(&line, 0).unwrap(), Some(7));
+//! ```
+//!
+//! 誤ったパターンはコンパイルエラーとなる。
+//! パースエラーの場合はパターン中の位置をメッセージに含め、
+//! コンパイラが対応していればリテラル中の該当する文字の位置を示す（それ以外はリテラル全体）。
+//!
+//! ```compile_fail
+//! use regex_macros::regex;
+//! let re = regex!("a(b|c");
+//! ```
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use std::ops::Range;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, Ident, LitStr, Token,
+};
+
+/// マクロの引数。`"パターン"`または`"パターン", dfs|bfs`
+struct Input {
+    expr: LitStr,
+    is_depth: bool,
+}
+
+impl Parse for Input {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let expr: LitStr = input.parse()?;
+        let mut is_depth = true;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let method: Ident = input.parse()?;
+            is_depth = match method.to_string().as_str() {
+                "dfs" => true,
+                "bfs" => false,
+                _ => return Err(syn::Error::new(method.span(), "expected `dfs` or `bfs`")),
+            };
+            input.parse::<Option<Token![,]>>()?;
+        }
+        Ok(Input { expr, is_depth })
+    }
+}
+
+/// 正規表現をコンパイル時に検証し、`&'static regex::Regex`を返す式に展開する。
+///
+/// 2番目の引数に`dfs`（省略時）または`bfs`を指定して探索方法を選ぶ。
+/// 実行時には埋め込んだ命令列を初回の評価時に一度だけ読み込み、パースは行わない。
+#[proc_macro]
+pub fn regex(input: TokenStream) -> TokenStream {
+    let Input { expr, is_depth } = parse_macro_input!(input as Input);
+    let re = match regex::Regex::new(&expr.value(), is_depth) {
+        Ok(re) => re,
+        Err(e) => {
+            let Some(span) = e.span() else {
+                return error(expr.span(), &e.to_string());
+            };
+            // リテラル中の位置を示せない場合はリテラル全体とする
+            let lit = expr.token();
+            let at = source_range(&lit.to_string(), span.clone())
+                .and_then(|r| lit.subspan(r))
+                .unwrap_or_else(|| expr.span());
+            let msg = format!("{e} (at {}..{} in the pattern)", span.start, span.end);
+            return error(at, &msg);
+        }
+    };
+    let bytes = re.to_bytes();
+    let len = bytes.len();
+    quote! {
+        {
+            static PROGRAM: [u8; #len] = [#(#bytes),*];
+            static REGEX: ::std::sync::OnceLock<::regex::Regex> = ::std::sync::OnceLock::new();
+            REGEX.get_or_init(|| {
+                ::regex::Regex::from_bytes(&PROGRAM)
+                    .expect("regex!: 埋め込んだ命令列の読み込みに失敗（regexクレートのバージョン不一致）")
+            })
+        }
+    }
+    .into()
+}
+
+/// パターン中のバイト位置の範囲spanを、文字列リテラルのソースsource中のバイト位置の範囲に変換。
+///
+/// エスケープを含む通常の文字列リテラルは位置が対応しないため、Noneを返す。
+fn source_range(source: &str, span: Range<usize>) -> Option<Range<usize>> {
+    let prefix = if let Some(rest) = source.strip_prefix('r') {
+        // r"..."とr#"..."#
+        1 + rest.find('"')? + 1
+    } else if source.starts_with('"') && !source.contains('\\') {
+        1
+    } else {
+        return None;
+    };
+    Some(prefix + span.start..prefix + span.end)
+}
+
+/// spanの位置を示すコンパイルエラーを生成
+fn error(span: Span, msg: &str) -> TokenStream {
+    syn::Error::new(span, format!("invalid regex: {msg}"))
+        .to_compile_error()
+        .into()
+}
+
+#[test]
+fn test_source_range() {
+    assert_eq!(source_range(r#""a(b""#, 3..3), Some(4..4));
+    // エスケープを含む場合は対応しない
+    assert_eq!(source_range(r#""a\\(""#, 2..3), None);
+    assert_eq!(source_range(r##"r#"é("#"##, 2..3), Some(5..6));
+    assert_eq!(source_range(r#"r"[z-a]""#, 1..4), Some(3..6));
+}
@@ -0,0 +1,8 @@
+//! regex!マクロの展開結果とコンパイルエラーを、trybuildで実際にコンパイルして確かめる
+#[test]
+fn test_ui() {
+    let t = trybuild::TestCases::new();
+    t.pass("tests/ui/pass.rs");
+    t.compile_fail("tests/ui/invalid_pattern.rs");
+    t.compile_fail("tests/ui/invalid_method.rs");
+}
@@ -0,0 +1,5 @@
+use regex_macros::regex;
+
+fn main() {
+    let _ = regex!("a", dfa);
+}
@@ -0,0 +1,7 @@
+use regex_macros::regex;
+
+fn main() {
+    let _ = regex!("a(b|c");
+    let _ = regex!(r"ab[z-a]");
+    let _ = regex!("(?>a)b", bfs);
+}
@@ -0,0 +1,8 @@
+use regex_macros::regex;
+
+fn main() {
+    let re = regex!("a(b|c)+");
+    assert_eq!(re.find_at("xabcb", 0).unwrap(), Some(1..5));
+    let re = regex!(r"(?i)[a-z]+\.rs", bfs);
+    assert_eq!(re.match_at("MAIN.RS", 0).unwrap(), Some(7));
+}
@@ -1,5 +1,5 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use regex::do_matching;
+use regex::{do_matching, Regex};
 use std::time::Duration;
 
 /// （計測のid、a?^n a^n という正規表現、文字列）というタプル。
@@ -31,5 +31,49 @@ fn with_first(c: &mut Criterion) {
         });
     }
 }
-criterion_group!(benches, with_first, depth_first);
+
+/// コンパイル済みの正規表現で、インタプリタとJITを比較する。
+///
+/// JITは`cargo bench --features jit`の場合のみ有効となり、
+/// それ以外ではインタプリタで評価される。
+fn jit(c: &mut Criterion) {
+    let mut g = c.benchmark_group("JIT");
+    g.measurement_time(Duration::from_secs(12));
+
+    for i in INPUTS {
+        let line = i.2.chars().collect::<Vec<char>>();
+        let dfs = Regex::new(i.1, true).unwrap();
+        let bfs = Regex::new(i.1, false).unwrap();
+        let jit = Regex::new(i.1, true).unwrap().with_jit();
+        for (name, re) in [("dfs", &dfs), ("bfs", &bfs), ("jit", &jit)] {
+            g.bench_with_input(format!("{} {name}", i.0), &line, |b, line| {
+                b.iter(|| re.match_at(line, 0).unwrap())
+            });
+        }
+    }
+}
+
+/// 同じ入力を`&str`のまま評価する場合と、`Vec<char>`に変換してから評価する場合を比較する。
+///
+/// `&str`ではUTF-8を直接辿り、範囲をバイト単位で返すため、入力ごとの変換と確保が不要となる。
+fn haystack(c: &mut Criterion) {
+    let mut g = c.benchmark_group("Haystack");
+    g.measurement_time(Duration::from_secs(12));
+
+    let text = "正規表現エンジンでfoo123とbar456を探す。".repeat(64);
+    for (name, is_depth) in [("dfs", true), ("bfs", false)] {
+        let re = Regex::new("[a-z]+[0-9]+", is_depth).unwrap();
+        g.bench_with_input(format!("{name} str"), &text, |b, text| {
+            b.iter(|| re.find_iter(text.as_str()).count())
+        });
+        g.bench_with_input(format!("{name} chars"), &text, |b, text| {
+            b.iter(|| {
+                let line = text.chars().collect::<Vec<char>>();
+                re.find_iter(&line).count()
+            })
+        });
+    }
+}
+
+criterion_group!(benches, with_first, depth_first, jit, haystack);
 criterion_main!(benches);
\ No newline at end of file
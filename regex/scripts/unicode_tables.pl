@@ -0,0 +1,74 @@
+#!/usr/bin/env perl
+# src/engine/unicode_tables.rs を生成する。
+#
+# PerlのUnicode::UCDから、一般カテゴリ、用字（Script）、および\w、\d、\sに用いる範囲を出力する。
+# Unicodeのバージョンは、実行するPerlが対応するものとなる。
+#
+#   perl scripts/unicode_tables.pl > src/engine/unicode_tables.rs
+use strict;
+use warnings;
+use Unicode::UCD qw(prop_values prop_value_aliases prop_invlist);
+
+# 反転リストを、サロゲート領域を除いた範囲（両端を含む）の一覧に変換
+sub ranges {
+    my @list = prop_invlist($_[0]);
+    push @list, 0x110000 if @list % 2;
+    my @ranges;
+    while (my ($lo, $end) = splice @list, 0, 2) {
+        my $hi = $end - 1;
+        if ($lo <= 0xDFFF && $hi >= 0xD800) {
+            push @ranges, [$lo, 0xD7FF] if $lo < 0xD800;
+            push @ranges, [0xE000, $hi] if $hi > 0xDFFF;
+        } else {
+            push @ranges, [$lo, $hi];
+        }
+    }
+    return @ranges;
+}
+
+sub table {
+    my ($name, @ranges) = @_;
+    my $out = "const $name: &[(char, char)] = &[\n";
+    while (my @line = splice @ranges, 0, 3) {
+        $out .= "   " . join("", map { sprintf " ('\\u{%x}', '\\u{%x}'),", @$_ } @line) . "\n";
+    }
+    return $out . "];\n";
+}
+
+my $version = Unicode::UCD::UnicodeVersion();
+print <<"HEADER";
+// 自動生成したファイル。編集しないこと
+//
+//   perl scripts/unicode_tables.pl > src/engine/unicode_tables.rs
+//
+// Unicodeのバージョン: $version
+
+/// プロパティの値。略称、正式名、範囲（両端を含む）の一覧
+pub type Property = (&'static str, &'static str, &'static [(char, char)]);
+
+HEADER
+
+my @tables;
+for my $prop (["gc", "GENERAL_CATEGORY", "一般カテゴリ"], ["sc", "SCRIPT", "用字"]) {
+    my ($short, $const, $doc) = @$prop;
+    print "/// $doc\n";
+    print "pub const $const: &[Property] = &[\n";
+    for my $value (sort { $a cmp $b } prop_values($short)) {
+        my ($abbr, $long) = prop_value_aliases($short, $value);
+        next unless defined $long;
+        my $name = uc "${short}_$abbr";
+        print "    (\"$abbr\", \"$long\", $name),\n";
+        push @tables, table($name, ranges("$short=$abbr"));
+    }
+    print "];\n\n";
+}
+
+for my $prop (["WORD", "Word", "\\w（単語を構成する文字）"],
+              ["DIGIT", "gc=Nd", "\\d（10進数字）"],
+              ["SPACE", "White_Space", "\\s（空白文字）"]) {
+    my ($name, $ucd, $doc) = @$prop;
+    print "/// $doc\n";
+    print "pub " . table($name, ranges($ucd)) . "\n";
+}
+
+print join("\n", @tables);
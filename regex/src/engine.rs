@@ -1,61 +1,512 @@
 //! 正規表現エンジン
+pub mod aot;
+mod class;
 mod codegen;
+mod dfa;
+mod error;
 mod evaluator;
+pub mod generate;
+pub mod glob;
+mod jit;
+pub mod lang;
+pub mod lexer;
 mod parser;
+mod serialize;
+mod stream;
+mod unicode;
+#[rustfmt::skip]
+mod unicode_tables;
 
-use crate::helper::DynError;
-use std::fmt::{self, Display};
+use evaluator::Cache;
+use std::{
+    fmt::{self, Display},
+    io::Read,
+    ops::Range,
+    sync::Mutex,
+};
+
+pub use class::Class;
+pub use codegen::CodeGenError;
+pub use dfa::DfaError;
+pub use error::Error;
+pub use evaluator::{EvalError, Haystack, Stats};
+pub use parser::{Assertion, Flags, ParseError, Syntax, AST};
+pub use serialize::{LoadError, FORMAT_VERSION};
+pub use stream::{StreamError, StreamMatches};
+pub use unicode::is_word_char;
 
 /// 命令列
 #[derive(Debug)]
 pub enum Instruction {
     Char(char),
-    Match,
+    AnyChar(bool),
+    Class(Class),
+    Assert(Assertion),
+    Save(usize),
+    Match(usize), // マッチ成功。値は複数のパターンを1つにまとめた場合の、パターンの番号
     Jump(usize),
     Split(usize, usize),
+    Atomic(usize), // 次の命令から値の位置の直前までを、アトミックグループとして評価する
 }
 
 impl Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Instruction::Char(c) => write!(f, "char {}", c),
-            Instruction::Match => write!(f, "match"),
+            Instruction::Char(c) => write!(f, "char {}", c.escape_debug()),
+            Instruction::AnyChar(false) => write!(f, "any"),
+            Instruction::AnyChar(true) => write!(f, "any newline"),
+            Instruction::Class(class) => write!(f, "class {class}"),
+            Instruction::Assert(a) => write!(f, "assert {a}"),
+            Instruction::Save(n) => write!(f, "save {n}"),
+            Instruction::Match(0) => write!(f, "match"),
+            Instruction::Match(tag) => write!(f, "match {tag}"),
             Instruction::Jump(addr) => write!(f, "jump {:>04}", addr),
             Instruction::Split(addr1, addr2) => write!(f, "split {:>04}, {:>04}", addr1, addr2),
+            Instruction::Atomic(addr) => write!(f, "atomic {:>04}", addr),
         }
     }
 }
 
-/// 正規表現をパースしてコード生成し、
-/// ASTと命令列を標準出力に表示。
+/// 正規表現をパースし、ASTを返す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::{Flags, AST};
+/// let ast = regex::parse("ab", Flags::default()).unwrap();
+/// assert_eq!(ast, AST::Seq(vec![AST::Char('a'), AST::Char('b')]));
+/// ```
+pub fn parse(expr: &str, flags: Flags) -> Result<AST, Error> {
+    parser::parse_with_flags(expr, flags).map_err(|e| Error::parse(expr, e))
+}
+
+/// 正規表現をパースしてコード生成し、命令列を1行に1命令ずつ、アドレスを付けて返す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::Flags;
+/// let listing = regex::listing("a|b", Flags::default()).unwrap();
+/// assert_eq!(listing.lines().next(), Some("0000: split 0001, 0003"));
+/// ```
+pub fn listing(expr: &str, flags: Flags) -> Result<String, Error> {
+    let code = codegen::get_code(&parse(expr, flags)?)?;
+    Ok(code
+        .iter()
+        .enumerate()
+        .map(|(n, c)| format!("{:>04}: {c}\n", n))
+        .collect())
+}
+
+/// 行中で正規表現に最初にマッチする範囲と、そのキャプチャグループの範囲を返す。
+///
+/// 範囲はlineのバイト単位のオフセットで、`&line[range]`でマッチした部分を取り出せる。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::Flags;
+/// let line = "éabcbcd";
+/// let caps = regex::find("a(bc)+", line, Flags::default(), true).unwrap().unwrap();
+/// assert_eq!(caps.get(0), Some(2..7));
+/// assert_eq!(&line[caps.get(1).unwrap()], "bc");
+/// ```
+pub fn find(
+    expr: &str,
+    line: &str,
+    flags: Flags,
+    is_depth: bool,
+) -> Result<Option<Captures>, Error> {
+    Regex::with_flags(expr, flags, is_depth)?.find_captures_at(line, 0)
+}
+
+/// 正規表現を行頭からマッチングし、最長のマッチ文字列を返す。
+///
+/// 返す文字列はlineの一部を指すスライスで、マッチングの際に文字列の確保や複製は行わない。
 ///
 /// # 利用例
 ///
 /// ```
 /// use regex;
-/// regex::print("abc|(de|cd)+");
+/// assert_eq!(regex::do_matching("a(bc)+", "abcbcd", true).unwrap(), "abcbc");
 /// ```
 ///
 /// # 返り値
 ///
-/// 入力された正規表現にエラーがあったり、内部的な実装エラーがある場合はErrを返す。
-pub fn print(expr: &str) -> Result<(), DynError> {
-    println!("expr: {expr}");
-    let ast = parser::parse(expr)?;
-    println!("AST: {:?}", ast);
-
-    println!("code:");
+/// 入力された正規表現にエラーがあったり、マッチしなかった場合はErrを返す。
+pub fn do_matching<'a>(expr: &str, line: &'a str, is_depth: bool) -> Result<&'a str, Error> {
+    let ast = parser::parse(expr).map_err(|e| Error::parse(expr, e))?;
     let code = codegen::get_code(&ast)?;
-    for (n, c) in code.iter().enumerate() {
-        println!("{:>04}: {c}", n);
+    match evaluator::eval(&code, line, 0, is_depth, &mut Cache::default())? {
+        Some(end) => Ok(&line[..end]),
+        None => Err(Error::Eval(EvalError::NoMatch)),
     }
+}
 
-    Ok(())
+/// コンパイル済みの正規表現
+///
+/// パースとコード生成は生成時に一度だけ行い、
+/// 以降のマッチングでは生成した命令列を使い回す。
+/// 評価に用いるスタックなどの作業領域も保持して使い回すため、
+/// マッチングのたびにメモリを確保することはない。
+///
+/// マッチングの入力は[Haystack]を実装する型で、位置と範囲の単位は入力の型による。
+/// `&str`の場合はUTF-8のまま辿り、バイト単位のオフセットを返す。
+/// `&[char]`と`&Vec<char>`の場合は文字単位のインデックスを返す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::Regex;
+/// let re = Regex::new("b+", true).unwrap();
+/// let spans = re.find_iter("äbbcb").collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(spans, vec![2..4, 5..6]);
+///
+/// let line = "äbbcb".chars().collect::<Vec<char>>();
+/// let spans = re.find_iter(&line).collect::<Result<Vec<_>, _>>().unwrap();
+/// assert_eq!(spans, vec![1..3, 4..5]);
+/// ```
+#[derive(Debug)]
+pub struct Regex {
+    code: Vec<Instruction>,
+    is_depth: bool,
+    flags: Flags,               // コンパイル時に指定したフラグの初期値
+    names: Vec<Option<String>>, // キャプチャグループの名前。0番目はマッチ全体
+    jit: Option<jit::Jit>,      // JITコンパイルした場合の機械語
+    cache: Mutex<Vec<Cache>>,   // 評価の作業領域。同時に評価するスレッドの数だけ保持する
 }
 
-pub fn do_matching(expr: &str, line: &str, is_depth: bool) -> Result<String, DynError> {
-    let ast = parser::parse(expr)?;
-    let code = codegen::get_code(&ast)?;
-    let line = line.chars().collect::<Vec<char>>();
-    Ok(evaluator::eval(&code, &line, is_depth)?)
+impl Regex {
+    /// 正規表現をコンパイル。
+    /// is_depthがtrueの場合に深さ優先探索を、falseの場合に幅優先探索を行う。
+    /// アトミックグループ（`(?>...)`）と強欲な量指定子（`*+`など）は深さ優先探索でのみ評価でき、
+    /// 幅優先探索の場合はエラーとなる。
+    pub fn new(expr: &str, is_depth: bool) -> Result<Self, Error> {
+        Self::with_flags(expr, Flags::default(), is_depth)
+    }
+
+    /// フラグの初期値を指定して正規表現をコンパイル
+    pub fn with_flags(expr: &str, flags: Flags, is_depth: bool) -> Result<Self, Error> {
+        let ast = parse(expr, flags)?;
+        let code = codegen::get_code(&ast)?;
+        if !is_depth {
+            evaluator::check_width(&code)?;
+        }
+        let names = parser::capture_names(&ast);
+        Ok(Regex {
+            code,
+            is_depth,
+            flags,
+            names,
+            jit: None,
+            cache: Mutex::default(),
+        })
+    }
+
+    /// 命令列をx86-64の機械語にJITコンパイルし、以降の[Regex::match_at]と[Regex::find_at]で用いる。
+    ///
+    /// フィーチャ`jit`が無効な場合やLinux/x86-64以外の環境、
+    /// およびDFAで表現できない場合（状態数が多すぎる場合やアトミックグループを含む場合）は何もせず、
+    /// インタプリタで評価する。
+    /// キャプチャグループの範囲や統計情報を求める場合は、常にインタプリタで評価する。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::Regex;
+    /// let re = Regex::new("a(b|c)+", true).unwrap().with_jit();
+    /// let line = "xabcb".chars().collect::<Vec<char>>();
+    /// assert_eq!(re.find_at(&line, 0).unwrap(), Some(1..5));
+    /// assert_eq!(re.find_at("éabcb", 0).unwrap(), Some(2..6));
+    /// ```
+    pub fn with_jit(mut self) -> Self {
+        self.jit = jit::Jit::new(&self.code);
+        self
+    }
+
+    /// JITコンパイルした機械語で評価するか
+    pub fn is_jit(&self) -> bool {
+        self.jit.is_some()
+    }
+
+    /// コンパイル済みの命令列を、フラグなどのメタデータとともにバイナリ形式に変換。
+    ///
+    /// [Regex::from_bytes]で読み込むと、パースとコード生成を行わずに同じ正規表現を復元できる。
+    /// 形式は[FORMAT_VERSION]が同じ間は互換性を保つ。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::Regex;
+    /// let bytes = Regex::new("a(b|c)+", true).unwrap().to_bytes();
+    /// let re = Regex::from_bytes(&bytes).unwrap();
+    /// let line = "xabcb".chars().collect::<Vec<char>>();
+    /// assert_eq!(re.find_at(&line, 0).unwrap(), Some(1..5));
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        serialize::to_bytes(self)
+    }
+
+    /// [Regex::to_bytes]で変換したバイナリ形式から読み込む。
+    ///
+    /// ジャンプ先などを検証し、破損した入力や異なるバージョンの入力は[LoadError]とする。
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Ok(serialize::from_bytes(bytes)?)
+    }
+
+    /// [Regex::to_bytes]と同じ内容を、人が読み書きできるテキスト形式に変換
+    pub fn to_text(&self) -> String {
+        serialize::to_text(self)
+    }
+
+    /// [Regex::to_text]で変換したテキスト形式から読み込む
+    pub fn from_text(text: &str) -> Result<Self, Error> {
+        Ok(serialize::from_text(text)?)
+    }
+
+    /// コンパイル時に指定したフラグの初期値
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    /// キャプチャグループの名前の一覧。
+    ///
+    /// 添字はグループの番号で、0番目はマッチ全体を表す。名前のないグループはNoneとなる。
+    pub fn capture_names(&self) -> &[Option<String>] {
+        &self.names
+    }
+
+    /// 作業領域を1つ取り出してfを呼び出し、終了後に戻す。
+    ///
+    /// 複数のスレッドから同時に評価する場合は、それぞれ異なる作業領域を用いる。
+    fn with_cache<T>(&self, f: impl FnOnce(&mut Cache) -> T) -> T {
+        let mut cache = self.cache.lock().unwrap().pop().unwrap_or_default();
+        let ret = f(&mut cache);
+        self.cache.lock().unwrap().push(cache);
+        ret
+    }
+
+    /// 位置startからマッチさせ、最長のマッチの終了位置を返す。
+    ///
+    /// [Regex::find_at]と異なり、開始位置はstartに固定される。
+    /// JITコンパイルした機械語は、`&[char]`と`&str`の入力で用いる。
+    pub fn match_at<H>(&self, line: &H, start: usize) -> Result<Option<usize>, Error>
+    where
+        H: Haystack + ?Sized,
+    {
+        self.with_cache(|cache| self.match_with_cache(line, start, cache))
+    }
+
+    /// 作業領域cacheを用いる[Regex::match_at]
+    fn match_with_cache<H>(
+        &self,
+        line: &H,
+        start: usize,
+        cache: &mut Cache,
+    ) -> Result<Option<usize>, Error>
+    where
+        H: Haystack + ?Sized,
+    {
+        if let Some(jit) = &self.jit {
+            if let Some(chars) = line.as_chars() {
+                return Ok(jit.longest_match(chars, start));
+            }
+            if let Some(text) = line.as_str() {
+                return Ok(jit.longest_match_str(text, start));
+            }
+        }
+        Ok(evaluator::eval(&self.code, line, start, self.is_depth, cache)?)
+    }
+
+    /// 位置start以降で最初にマッチする範囲を返す。
+    ///
+    /// 同じ開始位置では最長のものを返す。
+    /// インタプリタでは入力を一度だけ辿るため、マッチしない場合も計算量は命令数×文字数となる。
+    pub fn find_at<H>(&self, line: &H, start: usize) -> Result<Option<Range<usize>>, Error>
+    where
+        H: Haystack + ?Sized,
+    {
+        if self.jit.is_none() {
+            return self.find_at_with_stats(line, start).map(|(m, _)| m);
+        }
+        // 全ての開始位置で同じ作業領域を用いる
+        self.with_cache(|cache| {
+            let mut begin = start;
+            loop {
+                if let Some(end) = self.match_with_cache(line, begin, cache)? {
+                    return Ok(Some(begin..end));
+                }
+                match line.char_at(begin) {
+                    Some((_, next)) => begin = next,
+                    None => return Ok(None),
+                }
+            }
+        })
+    }
+
+    /// [Regex::match_at]と同じく位置startからマッチさせ、評価中の統計情報も返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::Regex;
+    /// let re = Regex::new("a+b", true).unwrap();
+    /// let line = "aab".chars().collect::<Vec<char>>();
+    /// let (end, stats) = re.match_with_stats(&line, 0).unwrap();
+    /// assert_eq!(end, Some(3));
+    /// assert!(stats.instructions > 0);
+    /// ```
+    pub fn match_with_stats<H: Haystack + ?Sized>(
+        &self,
+        line: &H,
+        start: usize,
+    ) -> Result<(Option<usize>, Stats), Error> {
+        self.with_cache(|cache| {
+            Ok(evaluator::eval_stats(&self.code, line, start, self.is_depth, cache)?)
+        })
+    }
+
+    /// [Regex::find_at]と同じく位置start以降で最初にマッチする範囲を探し、
+    /// 全ての開始位置での評価の統計情報を合算して返す。
+    ///
+    /// 命令数やキャッシュヒット数が入力長に対して極端に大きいパターンは、
+    /// 実運用で性能問題を起こしやすい。
+    pub fn find_at_with_stats<H: Haystack + ?Sized>(
+        &self,
+        line: &H,
+        start: usize,
+    ) -> Result<(Option<Range<usize>>, Stats), Error> {
+        self.with_cache(|cache| {
+            Ok(evaluator::eval_find(&self.code, line, start, self.is_depth, cache)?)
+        })
+    }
+
+    /// 位置startからマッチさせ、最長のマッチ範囲とキャプチャグループの範囲を返す。
+    ///
+    /// [Regex::match_at]と同じく、開始位置はstartに固定される。
+    pub fn captures_at<H>(&self, line: &H, start: usize) -> Result<Option<Captures>, Error>
+    where
+        H: Haystack + ?Sized,
+    {
+        let nslots = self.names.len() * 2;
+        let ret = self.with_cache(|cache| {
+            evaluator::eval_captures(&self.code, line, start, self.is_depth, nslots, cache)
+        })?;
+        Ok(ret.map(|(end, slots)| {
+            let mut spans = vec![Some(start..end)];
+            spans.extend(slots.chunks(2).skip(1).map(|s| match s {
+                [Some(b), Some(e)] => Some(*b..*e),
+                _ => None,
+            }));
+            Captures { spans }
+        }))
+    }
+
+    /// 位置start以降で最初にマッチする範囲と、そのキャプチャグループの範囲を返す。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::Regex;
+    /// let re = Regex::new("(?<key>[a-z]+)=([0-9]+)?", true).unwrap();
+    /// let line = "x: ab=12".chars().collect::<Vec<char>>();
+    /// let caps = re.find_captures_at(&line, 0).unwrap().unwrap();
+    /// assert_eq!(caps.get(0), Some(3..8));
+    /// assert_eq!(caps.get(1), Some(3..5));
+    /// assert_eq!(caps.get(2), Some(6..8));
+    /// assert_eq!(re.capture_names()[1].as_deref(), Some("key"));
+    /// ```
+    pub fn find_captures_at<H: Haystack + ?Sized>(
+        &self,
+        line: &H,
+        start: usize,
+    ) -> Result<Option<Captures>, Error> {
+        // 開始位置はキャプチャなしで求め、その位置で改めてグループの範囲を求める
+        match self.find_at(line, start)? {
+            Some(m) => self.captures_at(line, m.start),
+            None => Ok(None),
+        }
+    }
+
+    /// readerから読み込みながら、重ならないマッチ範囲を先頭から順に返すイテレータを生成。
+    ///
+    /// 入力全体をメモリに読み込まないため、巨大なファイルやソケットにも利用できる。
+    /// 範囲はストリーム先頭からのバイト単位の位置で、
+    /// チャンクの境界をまたぐマッチも`&str`に対する[Regex::find_iter]と同じ結果となる。
+    ///
+    /// # 利用例
+    ///
+    /// ```
+    /// use regex::Regex;
+    /// let re = Regex::new("b+", true).unwrap();
+    /// let spans = re.stream_find("abbcb".as_bytes()).collect::<Result<Vec<_>, _>>().unwrap();
+    /// assert_eq!(spans, vec![1..3, 4..5]);
+    /// ```
+    pub fn stream_find<R: Read>(&self, reader: R) -> StreamMatches<'_, R> {
+        StreamMatches::new(self, reader)
+    }
+
+    /// 重ならないマッチ範囲を先頭から順に返すイテレータを生成
+    pub fn find_iter<'a, H: Haystack + ?Sized>(&'a self, line: &'a H) -> Matches<'a, H> {
+        Matches {
+            regex: self,
+            line,
+            pos: Some(0),
+        }
+    }
+}
+
+/// マッチ範囲と、キャプチャグループの範囲
+///
+/// 範囲の単位は入力の型による（[Regex]を参照）。0番目はマッチ全体を表す。
+/// マッチに関与しなかったグループはNoneとなる。
+#[derive(Debug, PartialEq, Clone)]
+pub struct Captures {
+    spans: Vec<Option<Range<usize>>>,
+}
+
+impl Captures {
+    /// i番目のグループの範囲
+    pub fn get(&self, i: usize) -> Option<Range<usize>> {
+        self.spans.get(i).cloned().flatten()
+    }
+
+    /// 全てのグループの範囲を、番号の順に返すイテレータ
+    pub fn iter(&self) -> impl Iterator<Item = Option<Range<usize>>> + '_ {
+        self.spans.iter().cloned()
+    }
+}
+
+/// [Regex::find_iter]が返すイテレータ
+pub struct Matches<'a, H: ?Sized = [char]> {
+    regex: &'a Regex,
+    line: &'a H,
+    pos: Option<usize>, // 次の探索開始位置。探索終了時はNone
+}
+
+impl<H: Haystack + ?Sized> Iterator for Matches<'_, H> {
+    type Item = Result<Range<usize>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.pos?;
+        match self.regex.find_at(self.line, pos) {
+            Ok(Some(m)) => {
+                // 空文字列にマッチした場合は、同じ位置で再度マッチしないよう1文字進める
+                self.pos = if m.is_empty() {
+                    self.line.char_at(m.end).map(|(_, next)| next)
+                } else {
+                    Some(m.end)
+                };
+                Some(Ok(m))
+            }
+            Ok(None) => {
+                self.pos = None;
+                None
+            }
+            Err(e) => {
+                self.pos = None;
+                Some(Err(e))
+            }
+        }
+    }
 }
@@ -0,0 +1,315 @@
+//! 正規表現を、専用のマッチング関数のRustのソースコードに変換する（事前コンパイル）
+//!
+//! 命令列から構成したDFAを、状態ごとの`match`式として出力する。
+//! 生成した関数はインタプリタを介さずに評価でき、結果は[Regex::match_at](crate::Regex::match_at)と同じとなる。
+//!
+//! ビルドスクリプトから利用することを想定している。
+//! アトミックグループと強欲な量指定子はDFAで表せないため、これらを含む正規表現はエラーとする。
+//!
+//! ```no_run
+//! // build.rs
+//! regex::aot::write_out_dir(
+//!     "patterns.rs",
+//!     &[("is_ident", "[a-zA-Z_][a-zA-Z0-9_]*"), ("is_number", "[0-9]+(\\.[0-9]+)?")],
+//! )
+//! .unwrap();
+//! ```
+//!
+//! ```ignore
+//! // main.rs
+//! include!(concat!(env!("OUT_DIR"), "/patterns.rs"));
+//!
+//! let line = "x1 = 3.14".chars().collect::<Vec<char>>();
+//! assert_eq!(is_ident(&line, 0), Some(2));
+//! ```
+use super::{
+    codegen,
+    dfa::{Dfa, Prev, State, Target},
+    Error as RegexError, Flags,
+};
+use std::{
+    error::Error,
+    fmt::{self, Display, Write},
+    path::PathBuf,
+};
+
+/// ソースコード生成時のエラーを表す型
+#[derive(Debug)]
+pub enum AotError {
+    InvalidName(String), // 関数名が識別子として不正
+    NoOutDir,            // 環境変数OUT_DIRが設定されていない
+}
+
+impl Display for AotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AotError::InvalidName(name) => write!(f, "AotError: invalid function name: {name}"),
+            AotError::NoOutDir => write!(f, "AotError: OUT_DIR is not set"),
+        }
+    }
+}
+
+impl Error for AotError {}
+
+/// 正規表現exprにマッチする関数nameのソースコードを生成。
+///
+/// 生成する関数のシグネチャは`pub fn name(line: &[char], start: usize) -> Option<usize>`で、
+/// 位置startからマッチさせ、最長のマッチの終了位置を返す。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::{aot, Flags};
+/// let src = aot::to_rust("is_hex", "0x[0-9a-f]+", Flags::default()).unwrap();
+/// assert!(src.contains("pub fn is_hex(line: &[char], start: usize) -> Option<usize>"));
+/// ```
+pub fn to_rust(name: &str, expr: &str, flags: Flags) -> Result<String, RegexError> {
+    if !is_ident(name) {
+        return Err(AotError::InvalidName(name.to_string()).into());
+    }
+    let ast = super::parse(expr, flags)?;
+    let code = codegen::get_code(&ast)?;
+    let dfa = Dfa::new(&code)?;
+    let mut out = String::new();
+    write_fn(&mut out, name, expr, &dfa).expect("Stringへの書き込みは失敗しない");
+    Ok(out)
+}
+
+/// 関数名と正規表現の組の一覧から、全ての関数のソースコードを生成し、
+/// 環境変数OUT_DIRのディレクトリにfileという名前で書き込む。
+///
+/// 書き込んだファイルのパスを返す。ビルドスクリプトから呼び出すこと。
+pub fn write_out_dir(file: &str, patterns: &[(&str, &str)]) -> Result<PathBuf, RegexError> {
+    let dir = std::env::var_os("OUT_DIR").ok_or(AotError::NoOutDir)?;
+    let mut src = String::from("// regex::aotが生成したコード。編集しないこと\n");
+    for (name, expr) in patterns {
+        src.push('\n');
+        src.push_str(&to_rust(name, expr, Flags::default())?);
+    }
+    let path = PathBuf::from(dir).join(file);
+    std::fs::write(&path, src)?;
+    Ok(path)
+}
+
+/// Rustの識別子として正しいか判定（ASCIIのみ）
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "_"
+}
+
+/// DFAを関数として出力
+fn write_fn(out: &mut String, name: &str, expr: &str, dfa: &Dfa) -> fmt::Result {
+    writeln!(
+        out,
+        "/// 正規表現`{}`に、位置startからマッチする最長の範囲の終了位置を返す。",
+        expr.escape_debug()
+    )?;
+    writeln!(out, "#[allow(clippy::all, unreachable_patterns, unused)]")?;
+    writeln!(
+        out,
+        "pub fn {name}(line: &[char], start: usize) -> Option<usize> {{"
+    )?;
+    writeln!(out, "    const DEAD: usize = usize::MAX;")?;
+    writeln!(
+        out,
+        "    let mut state = match start.checked_sub(1).map(|i| line.get(i)) {{"
+    )?;
+    // 直前の文字の種類ごとの初期状態。Otherと同じ場合は`_`の腕にまとめる
+    let other = dfa.start[Prev::Other as usize];
+    writeln!(out, "        None => {},", dfa.start[Prev::Start as usize])?;
+    for prev in [Prev::Newline, Prev::Word, Prev::UnicodeWord] {
+        let state = dfa.start[prev as usize];
+        if state != other {
+            let pats = patterns(&dfa.prev_ranges(prev));
+            writeln!(out, "        Some(Some({})) => {state},", pats.join(" | "))?;
+        }
+    }
+    writeln!(out, "        _ => {other},")?;
+    writeln!(out, "    }};")?;
+    writeln!(out, "    let mut last = None;")?;
+    writeln!(out, "    let mut pos = start;")?;
+    writeln!(out, "    loop {{")?;
+    writeln!(out, "        let Some(&c) = line.get(pos) else {{")?;
+    let accepts = dfa
+        .states
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.accept_end)
+        .map(|(i, _)| i.to_string())
+        .collect::<Vec<_>>();
+    if !accepts.is_empty() {
+        writeln!(
+            out,
+            "            if matches!(state, {}) {{",
+            accepts.join(" | ")
+        )?;
+        writeln!(out, "                last = Some(pos);")?;
+        writeln!(out, "            }}")?;
+    }
+    writeln!(out, "            return last;")?;
+    writeln!(out, "        }};")?;
+    writeln!(out, "        let (accept, next) = match state {{")?;
+    for (i, state) in dfa.states.iter().enumerate() {
+        writeln!(out, "            {i} => match c {{")?;
+        write_arms(out, &dfa.alphabet, state)?;
+        writeln!(out, "            }},")?;
+    }
+    writeln!(out, "            _ => unreachable!(),")?;
+    writeln!(out, "        }};")?;
+    writeln!(out, "        if accept {{")?;
+    writeln!(out, "            last = Some(pos);")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        if next == DEAD {{")?;
+    writeln!(out, "            return last;")?;
+    writeln!(out, "        }}")?;
+    writeln!(out, "        state = next;")?;
+    writeln!(out, "        pos += 1;")?;
+    writeln!(out, "    }}")?;
+    writeln!(out, "}}")
+}
+
+/// 1つの状態の遷移を、文字のパターンごとの`match`の腕として出力。
+///
+/// 同じ遷移となる範囲は1つの腕にまとめ、最も多くの範囲で共通する遷移を`_`とする。
+fn write_arms(out: &mut String, alphabet: &[(char, char)], state: &State) -> fmt::Result {
+    let arms = state.arms(alphabet);
+    let default = arms
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, ranges))| ranges.len())
+        .map(|(i, _)| i);
+    let target = |(accept, next): Target| match next {
+        Some(n) => format!("({accept}, {n})"),
+        None => format!("({accept}, DEAD)"),
+    };
+    for (i, (key, ranges)) in arms.iter().enumerate() {
+        if Some(i) == default {
+            continue;
+        }
+        let pats = patterns(ranges);
+        writeln!(
+            out,
+            "                {} => {},",
+            pats.join(" | "),
+            target(*key)
+        )?;
+    }
+    if let Some(i) = default {
+        writeln!(out, "                _ => {},", target(arms[i].0))?;
+    }
+    Ok(())
+}
+
+/// 文字の範囲を、`match`のパターンとして出力
+fn patterns(ranges: &[(char, char)]) -> Vec<String> {
+    ranges
+        .iter()
+        .map(|(lo, hi)| {
+            if lo == hi {
+                format!("{lo:?}")
+            } else {
+                format!("{lo:?}..={hi:?}")
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test() {
+    use super::{
+        evaluator::{self, Cache},
+        parser,
+    };
+    use std::process::Command;
+
+    let patterns = [
+        ("p_alt", "a(bc)+|c(def)*"),
+        ("p_class", "(?i)[a-c]x?[^b]"),
+        ("p_star", "(a*)*b?"),
+        ("p_anchor", "^a|b$"),
+        ("p_multi", "(?m)^b\n?$|.c"),
+        ("p_dot", "(?s)a.+c"),
+        ("p_empty", "x?"),
+        ("p_word", "\\bb|c\\B|(?-u:\\bé)"),
+    ];
+    let inputs = [
+        "",
+        "a",
+        "abc",
+        "bcbc",
+        "cdefdef",
+        "aab",
+        "b\nb",
+        "ab\nbc",
+        "Ax\nc",
+        "xyz",
+        "éb_c éé",
+    ];
+
+    // DFAによる評価と、命令列の評価が一致する
+    let mut expected = String::new();
+    for (_, expr) in patterns {
+        let ast = parser::parse(expr).unwrap();
+        let code = codegen::get_code(&ast).unwrap();
+        let dfa = Dfa::new(&code).unwrap();
+        for input in inputs {
+            let line = input.chars().collect::<Vec<char>>();
+            for start in 0..=line.len() + 1 {
+                let m = evaluator::eval(&code, &line, start, true, &mut Cache::default()).unwrap();
+                assert_eq!(
+                    dfa.longest_match(&line, start),
+                    m,
+                    "{expr} {input:?} {start}"
+                );
+                writeln!(expected, "{m:?}").unwrap();
+            }
+        }
+    }
+
+    // 生成したソースコードをコンパイルして実行し、命令列の評価と比較する
+    let dir = std::env::temp_dir().join(format!("regex-aot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut src = String::new();
+    for (name, expr) in patterns {
+        src.push_str(&to_rust(name, expr, Flags::default()).unwrap());
+    }
+    writeln!(src, "fn main() {{").unwrap();
+    writeln!(src, "    let inputs: &[&str] = &{inputs:?};").unwrap();
+    for (name, _) in patterns {
+        writeln!(src, "    for input in inputs {{").unwrap();
+        writeln!(
+            src,
+            "        let line = input.chars().collect::<Vec<char>>();"
+        )
+        .unwrap();
+        writeln!(src, "        for start in 0..=line.len() + 1 {{").unwrap();
+        writeln!(
+            src,
+            "            println!(\"{{:?}}\", {name}(&line, start));"
+        )
+        .unwrap();
+        writeln!(src, "        }}").unwrap();
+        writeln!(src, "    }}").unwrap();
+    }
+    writeln!(src, "}}").unwrap();
+    let main = dir.join("main.rs");
+    let bin = dir.join("main");
+    std::fs::write(&main, &src).unwrap();
+    let status = Command::new("rustc")
+        .args(["--edition", "2021", "-o"])
+        .args([&bin, &main])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = Command::new(&bin).output().unwrap();
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(to_rust("1st", "a", Flags::default()).is_err());
+    assert!(to_rust("ok", "a(", Flags::default()).is_err());
+    assert!(to_rust("ok", "a*+", Flags::default()).is_err());
+}
@@ -0,0 +1,207 @@
+//! 文字クラス（文字の集合）
+use std::fmt::{self, Display};
+
+/// 文字クラスを表す型
+///
+/// 文字の範囲（両端を含む）の集合で表現する。
+/// 範囲は昇順に並び、重なりや隣接のないよう正規化して保持する。
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Class {
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    /// 範囲の一覧から文字クラスを生成
+    pub fn new(ranges: Vec<(char, char)>) -> Self {
+        let mut class = Class { ranges };
+        class.normalize();
+        class
+    }
+
+    /// 1文字のみからなる文字クラスを生成
+    pub fn from_char(c: char) -> Self {
+        Class {
+            ranges: vec![(c, c)],
+        }
+    }
+
+    /// 範囲の一覧。昇順に並び、重なりや隣接はない
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    /// 文字cが含まれるか判定
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges
+            .binary_search_by(|(lo, hi)| {
+                if *hi < c {
+                    std::cmp::Ordering::Less
+                } else if *lo > c {
+                    std::cmp::Ordering::Greater
+                } else {
+                    std::cmp::Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+
+    /// 補集合
+    pub fn negate(&mut self) {
+        let mut ranges = Vec::new();
+        let mut next = Some('\0');
+        for (lo, hi) in &self.ranges {
+            if let Some(n) = next {
+                if n < *lo {
+                    ranges.push((n, prev_char(*lo)));
+                }
+            }
+            next = next_char(*hi);
+        }
+        if let Some(n) = next {
+            ranges.push((n, char::MAX));
+        }
+        self.ranges = ranges;
+    }
+
+    /// 和集合
+    pub fn union(&mut self, other: &Class) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.normalize();
+    }
+
+    /// 積集合
+    pub fn intersect(&mut self, other: &Class) {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while let (Some((lo1, hi1)), Some((lo2, hi2))) = (self.ranges.get(i), other.ranges.get(j)) {
+            let (lo, hi) = (*lo1.max(lo2), *hi1.min(hi2));
+            if lo <= hi {
+                ranges.push((lo, hi));
+            }
+            // 先に終わる範囲を進める
+            if hi1 < hi2 {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        self.ranges = ranges;
+    }
+
+    /// 差集合
+    pub fn difference(&mut self, other: &Class) {
+        let mut other = other.clone();
+        other.negate();
+        self.intersect(&other);
+    }
+
+    /// 大文字と小文字を同一視するよう、各文字の大文字と小文字を追加
+    pub fn case_fold(&mut self) {
+        let mut ranges = self.ranges.clone();
+        for (lo, hi) in &self.ranges {
+            for c in *lo..=*hi {
+                for f in case_variants(c) {
+                    ranges.push((f, f));
+                }
+            }
+        }
+        self.ranges = ranges;
+        self.normalize();
+    }
+
+    /// 範囲を昇順に並べ、重なるものと隣接するものを結合
+    fn normalize(&mut self) {
+        self.ranges.sort();
+        let mut ranges: Vec<(char, char)> = Vec::with_capacity(self.ranges.len());
+        for (lo, hi) in self.ranges.drain(..) {
+            match ranges.last_mut() {
+                Some((_, last)) if next_char(*last).is_none_or(|n| lo <= n) => {
+                    *last = (*last).max(hi);
+                }
+                _ => ranges.push((lo, hi)),
+            }
+        }
+        self.ranges = ranges;
+    }
+}
+
+/// 文字cの大文字と小文字のうち、1文字で表せるもの（c自身を除く）
+pub fn case_variants(c: char) -> Vec<char> {
+    let mut v = Vec::new();
+    for s in [
+        c.to_lowercase().collect::<Vec<char>>(),
+        c.to_uppercase().collect(),
+    ] {
+        if let [f] = s[..] {
+            if f != c && !v.contains(&f) {
+                v.push(f);
+            }
+        }
+    }
+    v
+}
+
+/// 次の文字。サロゲート領域は飛ばす
+pub fn next_char(c: char) -> Option<char> {
+    match c {
+        '\u{D7FF}' => Some('\u{E000}'),
+        char::MAX => None,
+        _ => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// 前の文字。サロゲート領域は飛ばす。'\0'には使用しないこと
+pub fn prev_char(c: char) -> char {
+    match c {
+        '\u{E000}' => '\u{D7FF}',
+        _ => char::from_u32(c as u32 - 1).unwrap(),
+    }
+}
+
+impl Display for Class {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (lo, hi) in &self.ranges {
+            if lo == hi {
+                write!(f, "{}", lo.escape_debug())?;
+            } else {
+                write!(f, "{}-{}", lo.escape_debug(), hi.escape_debug())?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+#[test]
+fn test() {
+    let class = Class::new(vec![('d', 'f'), ('a', 'c'), ('x', 'x'), ('e', 'g')]);
+    assert_eq!(class.ranges, &[('a', 'g'), ('x', 'x')]);
+    assert!(class.contains('a') && class.contains('g') && class.contains('x'));
+    assert!(!class.contains('h') && !class.contains('y'));
+
+    let mut negated = class.clone();
+    negated.negate();
+    assert_eq!(negated.ranges, &[('\0', '`'), ('h', 'w'), ('y', char::MAX)]);
+    negated.negate();
+    assert_eq!(negated, class);
+
+    let mut folded = Class::new(vec![('a', 'c'), ('X', 'X')]);
+    folded.case_fold();
+    assert_eq!(
+        folded.ranges,
+        &[('A', 'C'), ('X', 'X'), ('a', 'c'), ('x', 'x')]
+    );
+
+    assert_eq!(format!("{class}"), "[a-gx]");
+
+    let other = Class::new(vec![('b', 'e'), ('w', 'z')]);
+    let mut set = class.clone();
+    set.union(&other);
+    assert_eq!(set.ranges, &[('a', 'g'), ('w', 'z')]);
+    let mut set = class.clone();
+    set.intersect(&other);
+    assert_eq!(set.ranges, &[('b', 'e'), ('x', 'x')]);
+    let mut set = class.clone();
+    set.difference(&other);
+    assert_eq!(set.ranges, &[('a', 'a'), ('f', 'g')]);
+}
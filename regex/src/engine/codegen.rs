@@ -1,5 +1,9 @@
 //! ASTからコード生成を行う
-use super::{parser::AST, Instruction};
+use super::{
+    class::Class,
+    parser::{Assertion, AST},
+    Instruction,
+};
 use crate::helper::safe_add;
 use std::{
     error::Error,
@@ -13,6 +17,7 @@ pub enum CodeGenError {
     FailStar,
     FailOr,
     FailQuestion,
+    FailAtomic,
 }
 
 impl Display for CodeGenError {
@@ -37,13 +42,62 @@ pub fn get_code(ast: &AST) -> Result<Vec<Instruction>, CodeGenError> {
     Ok(generator.insts)
 }
 
+/// 複数のASTから、1つの命令列を生成する関数。
+///
+/// i番目のASTにマッチした場合は、番号iのmatch命令を実行する。先に並ぶASTほど優先度が高い。
+pub fn get_code_tagged(asts: &[AST]) -> Result<Vec<Instruction>, CodeGenError> {
+    let mut generator = Generator::default();
+    generator.gen_tagged(asts)?;
+    Ok(generator.insts)
+}
+
 /// コード生成器のメソッド定義
 impl Generator {
     /// コード生成を行う関数の入り口
     fn gen_code(&mut self, ast: &AST) -> Result<(), CodeGenError> {
         self.gen_expr(ast)?;
         self.inc_pc()?;
-        self.insts.push(Instruction::Match);
+        self.insts.push(Instruction::Match(0));
+        Ok(())
+    }
+
+    /// 番号付きのmatch命令を持つコード生成器。
+    ///
+    /// 以下のようなコードを生成。
+    ///
+    /// ```text
+    ///     split L1, L2
+    /// L1: e1のコード
+    ///     match 0
+    /// L2: split L3, L4
+    /// L3: e2のコード
+    ///     match 1
+    /// L4: ...
+    ///     enのコード
+    ///     match n-1
+    /// ```
+    fn gen_tagged(&mut self, asts: &[AST]) -> Result<(), CodeGenError> {
+        for (tag, ast) in asts.iter().enumerate() {
+            let is_last = tag + 1 == asts.len();
+            let split_addr = self.pc;
+            if !is_last {
+                self.insts.push(Instruction::Split(self.pc + 1, 0)); // L2を0で仮置き
+                self.inc_pc()?;
+            }
+
+            self.gen_expr(ast)?;
+            self.insts.push(Instruction::Match(tag));
+            self.inc_pc()?;
+
+            // L2を再設定
+            if !is_last {
+                if let Some(Instruction::Split(_, l2)) = self.insts.get_mut(split_addr) {
+                    *l2 = self.pc;
+                } else {
+                    return Err(CodeGenError::FailOr);
+                }
+            }
+        }
         Ok(())
     }
 
@@ -51,11 +105,16 @@ impl Generator {
     fn gen_expr(&mut self, ast: &AST) -> Result<(), CodeGenError> {
         match ast {
             AST::Char(c) => self.gen_char(*c)?,
+            AST::AnyChar(nl) => self.gen_any_char(*nl)?,
+            AST::Class(class) => self.gen_class(class)?,
+            AST::Assert(a) => self.gen_assert(*a)?,
             AST::Or(e1, e2) => self.gen_or(e1, e2)?,
             AST::Question(e) => self.gen_question(e)?,
             AST::Plus(e) => self.gen_plus(e)?,
             AST::Star(e) => self.gen_star(e)?,
             AST::Seq(e) => self.gen_seq(e)?,
+            AST::Capture(n, _, e) => self.gen_capture(*n, e)?,
+            AST::Atomic(e) => self.gen_atomic(e)?,
         }
         // match ast {
         //     AST::Char(c) => self.gen_char(*c)?,
@@ -90,6 +149,27 @@ impl Generator {
         self.inc_pc()
     }
 
+    /// any命令生成関数
+    fn gen_any_char(&mut self, nl: bool) -> Result<(), CodeGenError> {
+        let inst = Instruction::AnyChar(nl);
+        self.insts.push(inst);
+        self.inc_pc()
+    }
+
+    /// class命令生成関数
+    fn gen_class(&mut self, class: &Class) -> Result<(), CodeGenError> {
+        let inst = Instruction::Class(class.clone());
+        self.insts.push(inst);
+        self.inc_pc()
+    }
+
+    /// assert命令生成関数
+    fn gen_assert(&mut self, a: Assertion) -> Result<(), CodeGenError> {
+        let inst = Instruction::Assert(a);
+        self.insts.push(inst);
+        self.inc_pc()
+    }
+
     /// OR演算子のコード生成器。
     ///
     /// 以下のようなコードを生成。
@@ -210,6 +290,53 @@ impl Generator {
         Ok(())
     }
 
+    /// キャプチャグループのコード生成器。
+    ///
+    /// n番目のグループの開始位置をスロット2n、終了位置をスロット2n+1に保存する。
+    ///
+    /// ```text
+    ///     save 2n
+    ///     eのコード
+    ///     save 2n+1
+    /// ```
+    fn gen_capture(&mut self, n: usize, e: &AST) -> Result<(), CodeGenError> {
+        let slot = n.checked_mul(2).ok_or(CodeGenError::PCOverFlow)?;
+        self.insts.push(Instruction::Save(slot));
+        self.inc_pc()?;
+        self.gen_expr(e)?;
+        self.insts.push(Instruction::Save(slot + 1));
+        self.inc_pc()
+    }
+
+    /// アトミックグループのコード生成器。
+    ///
+    /// 以下のようなコードを生成。
+    /// 深さ優先探索では、eのコードのうち優先度の最も高い経路でL1に到達した時点で、
+    /// グループ内の残りの分岐を破棄する。
+    ///
+    /// ```text
+    ///     atomic L1
+    ///     eのコード
+    /// L1:
+    /// ```
+    fn gen_atomic(&mut self, e: &AST) -> Result<(), CodeGenError> {
+        // atomic L1
+        let atomic_addr = self.pc;
+        self.insts.push(Instruction::Atomic(0)); // L1を0で仮置き
+        self.inc_pc()?;
+
+        // eのコード
+        self.gen_expr(e)?;
+
+        // L1を再設定
+        if let Some(Instruction::Atomic(l1)) = self.insts.get_mut(atomic_addr) {
+            *l1 = self.pc;
+        } else {
+            return Err(CodeGenError::FailAtomic);
+        }
+        Ok(())
+    }
+
     /// 連続する正規表現のコード生成
     fn gen_seq(&mut self, exprs: &[AST]) -> Result<(), CodeGenError> {
         for e in exprs {
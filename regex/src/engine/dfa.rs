@@ -0,0 +1,408 @@
+//! 命令列から決定性有限オートマトン（DFA）を構成する
+//!
+//! DFAの状態は、NFAとしての命令列の状態（pcの集合）と、直前の文字の種類の組とする。
+//! 表明（`^`、`$`、`\b`など）の判定には前後の文字が必要なため、
+//! 直前の文字は状態に持たせ、直後の文字は遷移ごとに判定する。
+//! 単語境界の表明を含まない場合は、単語の文字とそれ以外を区別しない。
+//!
+//! マッチの判定は[super::evaluator::eval]と同じく、開始位置を固定した最長一致とする。
+#[cfg(any(test, feature = "jit"))]
+use super::evaluator::Haystack;
+use super::{
+    class::{next_char, prev_char},
+    parser::Assertion,
+    unicode::{self, is_word},
+    Instruction,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt::{self, Display},
+};
+
+/// 構成するDFAの状態数の上限
+pub const MAX_STATES: usize = 10_000;
+
+/// DFAの構成時のエラーを表す型
+#[derive(Debug)]
+pub enum DfaError {
+    TooManyStates(usize), // 状態数が上限を超えた。値は上限
+    PCOverFlow(usize),    // 命令列の範囲外を参照した。値はpc
+    Atomic,               // アトミックグループと強欲な量指定子はDFAで表現できない
+}
+
+impl Display for DfaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DfaError::TooManyStates(limit) => {
+                write!(f, "DfaError: too many states: limit = {limit}")
+            }
+            DfaError::PCOverFlow(pc) => write!(f, "DfaError: pc overflow: pc = {pc}"),
+            DfaError::Atomic => write!(
+                f,
+                "DfaError: atomic groups and possessive quantifiers are not supported"
+            ),
+        }
+    }
+}
+
+impl Error for DfaError {}
+
+/// 直前の文字の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Prev {
+    Start,       // 入力の先頭
+    Newline,     // 改行
+    Word,        // ASCIIの単語の文字（Unicodeでも単語の文字）
+    UnicodeWord, // ASCII以外のUnicodeの単語の文字
+    Other,       // それ以外
+}
+
+impl Prev {
+    /// 全ての種類。[Dfa::start]の順
+    pub const ALL: [Prev; 5] = [
+        Prev::Start,
+        Prev::Newline,
+        Prev::Word,
+        Prev::UnicodeWord,
+        Prev::Other,
+    ];
+
+    /// 位置startの直前の文字の種類
+    #[cfg(any(test, feature = "jit"))]
+    pub fn at<H: Haystack + ?Sized>(line: &H, start: usize) -> Self {
+        if start == 0 {
+            return Prev::Start;
+        }
+        line.char_before(start).map_or(Prev::Other, Prev::after)
+    }
+
+    /// 文字cの直後での種類
+    pub fn after(c: char) -> Self {
+        if c == '\n' {
+            Prev::Newline
+        } else if is_word(c, true) {
+            Prev::Word
+        } else if is_word(c, false) {
+            Prev::UnicodeWord
+        } else {
+            Prev::Other
+        }
+    }
+
+    /// 単語の文字か。asciiがtrueの場合はASCIIの範囲で判定
+    fn is_word(self, ascii: bool) -> bool {
+        self == Prev::Word || (!ascii && self == Prev::UnicodeWord)
+    }
+}
+
+/// 直後の文字の種類
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Next {
+    End,
+    Newline,
+    Word,
+    UnicodeWord,
+    Other,
+}
+
+impl Next {
+    /// 文字cの直前での種類
+    fn before(c: char) -> Self {
+        match Prev::after(c) {
+            Prev::Newline => Next::Newline,
+            Prev::Word => Next::Word,
+            Prev::UnicodeWord => Next::UnicodeWord,
+            _ => Next::Other,
+        }
+    }
+
+    /// 単語の文字か。asciiがtrueの場合はASCIIの範囲で判定
+    fn is_word(self, ascii: bool) -> bool {
+        self == Next::Word || (!ascii && self == Next::UnicodeWord)
+    }
+}
+
+/// DFAの状態
+#[derive(Debug, PartialEq)]
+pub struct State {
+    pub next: Vec<Option<usize>>, // alphabet[i]の文字を読んだ場合の遷移先。Noneはマッチ失敗
+    pub accept: Vec<bool>,        // alphabet[i]の文字の直前の位置でマッチが成立するか
+    pub accept_end: bool,         // 入力の終わりでマッチが成立するか
+}
+
+/// 遷移。文字の直前でマッチが成立するかと、遷移先
+pub type Target = (bool, Option<usize>);
+
+/// 同じ遷移となる文字の範囲をまとめたもの
+pub type Arm = (Target, Vec<(char, char)>);
+
+impl State {
+    /// 同じ遷移となる文字の範囲をまとめた一覧。遷移が最初に現れた順に並ぶ
+    pub fn arms(&self, alphabet: &[(char, char)]) -> Vec<Arm> {
+        let mut arms: Vec<Arm> = Vec::new();
+        for (i, range) in alphabet.iter().enumerate() {
+            let key = (self.accept[i], self.next[i]);
+            match arms.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, ranges)) => ranges.push(*range),
+                None => arms.push((key, vec![*range])),
+            }
+        }
+        arms
+    }
+}
+
+/// 決定性有限オートマトン
+#[derive(Debug)]
+pub struct Dfa {
+    pub alphabet: Vec<(char, char)>, // 文字の範囲（両端を含む）の一覧。同じ範囲の文字は同じ遷移となる
+    pub states: Vec<State>,
+    pub start: [usize; 5], // 直前の文字の種類ごとの初期状態。Prev::ALLの順
+}
+
+impl Dfa {
+    /// 命令列からDFAを構成する。
+    ///
+    /// 状態数が[MAX_STATES]を超える場合はエラーとなる。
+    pub fn new(insts: &[Instruction]) -> Result<Self, DfaError> {
+        let has_word = insts
+            .iter()
+            .any(|inst| matches!(inst, Instruction::Assert(a) if a.word_ascii().is_some()));
+        let alphabet = alphabet(insts, has_word);
+        let mut builder = Builder {
+            insts,
+            alphabet: &alphabet,
+            has_word,
+            ids: HashMap::new(),
+            todo: Vec::new(),
+        };
+        let mut start = [0; 5];
+        for (i, prev) in Prev::ALL.into_iter().enumerate() {
+            start[i] = builder.id(vec![0], builder.canonical(prev))?;
+        }
+
+        let mut states = Vec::new();
+        while let Some((pcs, prev)) = builder.todo.get(states.len()).cloned() {
+            states.push(builder.state(&pcs, prev)?);
+        }
+        Ok(Dfa {
+            alphabet,
+            states,
+            start,
+        })
+    }
+
+    /// 直前の文字の種類がprevとなる文字の範囲の一覧。隣接する範囲は結合する
+    ///
+    /// 単語境界の表明を含まない場合、単語の文字の範囲は区別されないため、正しく求まらない。
+    pub fn prev_ranges(&self, prev: Prev) -> Vec<(char, char)> {
+        let mut ranges: Vec<(char, char)> = Vec::new();
+        for &(lo, hi) in self
+            .alphabet
+            .iter()
+            .filter(|(lo, _)| Prev::after(*lo) == prev)
+        {
+            match ranges.last_mut() {
+                Some((_, last)) if next_char(*last) == Some(lo) => *last = hi,
+                _ => ranges.push((lo, hi)),
+            }
+        }
+        ranges
+    }
+
+    /// 文字cを含む範囲の、alphabet中の添字
+    #[cfg(test)]
+    pub fn class_of(&self, c: char) -> usize {
+        // 範囲は'\0'から始まり隙間なく並ぶため、c以下の先頭を持つ最後の範囲となる
+        self.alphabet.partition_point(|(lo, _)| *lo <= c) - 1
+    }
+
+    /// 位置startからマッチさせ、最長のマッチの終了位置を返す
+    #[cfg(test)]
+    pub fn longest_match(&self, line: &[char], start: usize) -> Option<usize> {
+        let mut state = &self.states[self.start[Prev::at(line, start) as usize]];
+        let mut last = None;
+        let mut pos = start;
+        while let Some(&c) = line.get(pos) {
+            let i = self.class_of(c);
+            if state.accept[i] {
+                last = Some(pos);
+            }
+            match state.next[i] {
+                Some(next) => state = &self.states[next],
+                None => return last,
+            }
+            pos += 1;
+        }
+        if state.accept_end {
+            last = Some(pos);
+        }
+        last
+    }
+}
+
+/// DFAの構成中の情報
+struct Builder<'a> {
+    insts: &'a [Instruction],
+    alphabet: &'a [(char, char)],
+    has_word: bool,                          // 単語境界の表明を含むか
+    ids: HashMap<(Vec<usize>, Prev), usize>, // 状態から番号への対応
+    todo: Vec<(Vec<usize>, Prev)>,           // 番号順の状態。遷移を求めていないものも含む
+}
+
+impl Builder<'_> {
+    /// 単語境界の表明を含まない場合は、単語の文字をそれ以外の文字と同一視する
+    fn canonical(&self, prev: Prev) -> Prev {
+        match prev {
+            Prev::Word | Prev::UnicodeWord if !self.has_word => Prev::Other,
+            _ => prev,
+        }
+    }
+
+    /// 状態の番号を返す。未登録の場合は登録する
+    fn id(&mut self, pcs: Vec<usize>, prev: Prev) -> Result<usize, DfaError> {
+        if let Some(id) = self.ids.get(&(pcs.clone(), prev)) {
+            return Ok(*id);
+        }
+        let id = self.todo.len();
+        if id >= MAX_STATES {
+            return Err(DfaError::TooManyStates(MAX_STATES));
+        }
+        self.ids.insert((pcs.clone(), prev), id);
+        self.todo.push((pcs, prev));
+        Ok(id)
+    }
+
+    /// 状態の遷移を求める
+    fn state(&mut self, pcs: &[usize], prev: Prev) -> Result<State, DfaError> {
+        let mut next = Vec::with_capacity(self.alphabet.len());
+        let mut accept = Vec::with_capacity(self.alphabet.len());
+        let (accept_end, _) = self.closure(pcs, prev, Next::End)?;
+        // 直後の文字の種類ごとに、文字を消費しない命令をたどった結果
+        let mut closures: [Option<(bool, Vec<usize>)>; 5] = Default::default();
+        for &(lo, _) in self.alphabet {
+            let next_kind = match Next::before(lo) {
+                Next::Word | Next::UnicodeWord if !self.has_word => Next::Other,
+                kind => kind,
+            };
+            let closure = &mut closures[next_kind as usize];
+            if closure.is_none() {
+                *closure = Some(self.closure(pcs, prev, next_kind)?);
+            }
+            let (acc, threads) = closures[next_kind as usize].as_ref().unwrap();
+            // 範囲の文字は全て同じ遷移となるため、先頭の文字で判定すれば良い
+            let pcs = threads
+                .iter()
+                .filter(|pc| match &self.insts[**pc] {
+                    Instruction::Char(c) => *c == lo,
+                    Instruction::AnyChar(nl) => *nl || lo != '\n',
+                    Instruction::Class(class) => class.contains(lo),
+                    _ => false,
+                })
+                .map(|pc| pc + 1)
+                .collect::<Vec<_>>();
+            accept.push(*acc);
+            next.push(if pcs.is_empty() {
+                None
+            } else {
+                Some(self.id(pcs, self.canonical(Prev::after(lo)))?)
+            });
+        }
+        Ok(State {
+            next,
+            accept,
+            accept_end,
+        })
+    }
+
+    /// pcの集合から、文字を消費せずに到達できる命令をたどる。
+    ///
+    /// Matchに到達するかと、到達した文字を消費する命令のpcの集合を返す。
+    fn closure(
+        &self,
+        pcs: &[usize],
+        prev: Prev,
+        next: Next,
+    ) -> Result<(bool, Vec<usize>), DfaError> {
+        let mut stack = pcs.to_vec();
+        let mut visited = BTreeSet::new();
+        let mut matched = false;
+        let mut threads = BTreeSet::new();
+        while let Some(pc) = stack.pop() {
+            if !visited.insert(pc) {
+                continue;
+            }
+            match self.insts.get(pc).ok_or(DfaError::PCOverFlow(pc))? {
+                Instruction::Match(_) => matched = true,
+                Instruction::Jump(addr) => stack.push(*addr),
+                Instruction::Split(addr1, addr2) => {
+                    stack.push(*addr2);
+                    stack.push(*addr1);
+                }
+                Instruction::Save(_) => stack.push(pc + 1),
+                Instruction::Assert(a) => {
+                    let ok = match a {
+                        Assertion::StartText => prev == Prev::Start,
+                        Assertion::StartLine => matches!(prev, Prev::Start | Prev::Newline),
+                        Assertion::EndText => next == Next::End,
+                        Assertion::EndLine => matches!(next, Next::End | Next::Newline),
+                        Assertion::WordBoundary => prev.is_word(false) != next.is_word(false),
+                        Assertion::NotWordBoundary => prev.is_word(false) == next.is_word(false),
+                        Assertion::AsciiWordBoundary => prev.is_word(true) != next.is_word(true),
+                        Assertion::AsciiNotWordBoundary => prev.is_word(true) == next.is_word(true),
+                    };
+                    if ok {
+                        stack.push(pc + 1);
+                    }
+                }
+                Instruction::Char(_) | Instruction::AnyChar(_) | Instruction::Class(_) => {
+                    threads.insert(pc);
+                }
+                Instruction::Atomic(_) => return Err(DfaError::Atomic),
+            }
+        }
+        Ok((matched, threads.into_iter().collect()))
+    }
+}
+
+/// 命令列中の文字の範囲の境界で、全ての文字を分割する。
+///
+/// 同じ範囲の文字は、どの命令でも同じ判定となる。改行は表明の判定に用いるため、単独の範囲とする。
+/// has_wordがtrueの場合は、単語境界の判定のため、ASCIIとUnicodeの単語の文字の範囲の境界でも分割する。
+fn alphabet(insts: &[Instruction], has_word: bool) -> Vec<(char, char)> {
+    let mut starts = BTreeSet::from(['\0', '\n', '\u{b}']);
+    let mut add = |lo: char, hi: char| {
+        starts.insert(lo);
+        if let Some(n) = next_char(hi) {
+            starts.insert(n);
+        }
+    };
+    if has_word {
+        for (lo, hi) in [('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')] {
+            add(lo, hi);
+        }
+        for (lo, hi) in unicode::word().ranges() {
+            add(*lo, *hi);
+        }
+    }
+    for inst in insts {
+        match inst {
+            Instruction::Char(c) => add(*c, *c),
+            Instruction::Class(class) => {
+                for (lo, hi) in class.ranges() {
+                    add(*lo, *hi);
+                }
+            }
+            _ => (),
+        }
+    }
+    let starts = starts.into_iter().collect::<Vec<_>>();
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, lo)| {
+            let hi = starts.get(i + 1).map_or(char::MAX, |n| prev_char(*n));
+            (*lo, hi)
+        })
+        .collect()
+}
@@ -0,0 +1,178 @@
+//! ライブラリの公開APIが返すエラー
+use super::{
+    aot::AotError, codegen::CodeGenError, dfa::DfaError, evaluator::EvalError, lexer::LexError,
+    parser::ParseError, serialize::LoadError, stream::StreamError,
+};
+use std::{
+    fmt::{self, Display},
+    io,
+    ops::Range,
+};
+
+/// パース、コード生成、評価の各段階と、読み込みや字句解析などの各機能のエラーをまとめた型
+///
+/// # 利用例
+///
+/// ```
+/// use regex::{Error, ParseError, Regex};
+/// match Regex::new("ab(c", true) {
+///     Err(Error::Parse(ParseError::NoRightParen, span)) => assert_eq!(span, 4..4),
+///     _ => unreachable!(),
+/// }
+/// ```
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseError, Range<usize>), // パースエラーと、原因となったパターン中のバイト位置の範囲
+    CodeGen(CodeGenError),
+    Eval(EvalError),
+    Dfa(DfaError),       // DFAへの変換（aot、lang）
+    Load(LoadError),     // 保存した形式からの読み込み
+    Lex(LexError),       // 字句解析
+    Aot(AotError),       // ソースコード生成
+    Stream(StreamError), // ストリームでのマッチング
+    Io(io::Error),       // 入出力
+}
+
+impl Error {
+    /// パターンexprのパースエラーから生成。
+    ///
+    /// ParseErrorの位置は文字単位のため、範囲はその文字のexpr中のバイト位置の範囲に変換する。
+    /// 位置を持たないエラー（右閉じ括弧無しなど）の範囲は、パターンの末尾の空範囲とする。
+    pub(crate) fn parse(expr: &str, e: ParseError) -> Self {
+        let len = expr.len();
+        let span = match e.pos().and_then(|pos| expr.char_indices().nth(pos)) {
+            Some((i, c)) => i..i + c.len_utf8(),
+            None => len..len,
+        };
+        Error::Parse(e, span)
+    }
+
+    /// パースエラーの場合は、原因となったパターン中のバイト位置の範囲。
+    ///
+    /// `&pattern[span]`で原因となった部分を取り出せる。
+    pub fn span(&self) -> Option<Range<usize>> {
+        match self {
+            Error::Parse(_, span) => Some(span.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse(e, _) => write!(f, "{e}"),
+            Error::CodeGen(e) => write!(f, "{e}"),
+            Error::Eval(e) => write!(f, "{e}"),
+            Error::Dfa(e) => write!(f, "{e}"),
+            Error::Load(e) => write!(f, "{e}"),
+            Error::Lex(e) => write!(f, "{e}"),
+            Error::Aot(e) => write!(f, "{e}"),
+            Error::Stream(e) => write!(f, "{e}"),
+            Error::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Parse(e, _) => Some(e),
+            Error::CodeGen(e) => Some(e),
+            Error::Eval(e) => Some(e),
+            Error::Dfa(e) => Some(e),
+            Error::Load(e) => Some(e),
+            Error::Lex(e) => Some(e),
+            Error::Aot(e) => Some(e),
+            Error::Stream(e) => Some(e),
+            Error::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<CodeGenError> for Error {
+    fn from(e: CodeGenError) -> Self {
+        Error::CodeGen(e)
+    }
+}
+
+impl From<EvalError> for Error {
+    fn from(e: EvalError) -> Self {
+        Error::Eval(e)
+    }
+}
+
+impl From<DfaError> for Error {
+    fn from(e: DfaError) -> Self {
+        Error::Dfa(e)
+    }
+}
+
+impl From<LoadError> for Error {
+    fn from(e: LoadError) -> Self {
+        Error::Load(e)
+    }
+}
+
+impl From<LexError> for Error {
+    fn from(e: LexError) -> Self {
+        Error::Lex(e)
+    }
+}
+
+impl From<AotError> for Error {
+    fn from(e: AotError) -> Self {
+        Error::Aot(e)
+    }
+}
+
+impl From<StreamError> for Error {
+    fn from(e: StreamError) -> Self {
+        Error::Stream(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[test]
+fn test_error() {
+    use super::{generate::Generator, lang, Regex};
+    // 末尾の\は、その位置のエスケープの誤りとなる
+    let e = Regex::new("a\\", true).unwrap_err();
+    assert!(matches!(
+        e,
+        Error::Parse(ParseError::InvalidEscape(1, '\\'), _)
+    ));
+    assert_eq!(e.span(), Some(1..2));
+    assert_eq!(
+        e.to_string(),
+        "ParseError: invalid escape: pos = 1, char = '\\'"
+    );
+    // 範囲はバイト単位
+    let e = Regex::new("éé)", true).unwrap_err();
+    assert!(matches!(
+        e,
+        Error::Parse(ParseError::InvalidRightParen(2), _)
+    ));
+    assert_eq!(e.span(), Some(4..5));
+    assert_eq!(&"éé)"[e.span().unwrap()], ")");
+    assert_eq!(Regex::new("é(", true).unwrap_err().span(), Some(3..3));
+    let e = Error::parse("", ParseError::Empty);
+    assert_eq!(e.span(), Some(0..0));
+    assert_eq!(Error::from(EvalError::NoMatch).span(), None);
+
+    // 公開APIはいずれもErrorを返す
+    assert!(matches!(
+        Regex::from_bytes(b"x"),
+        Err(Error::Load(LoadError::BadMagic))
+    ));
+    assert!(matches!(
+        lang::equivalent("(?>a)", "a"),
+        Err(Error::Dfa(DfaError::Atomic))
+    ));
+    assert_eq!(Generator::new("a(").unwrap_err().span(), Some(2..2));
+}
@@ -1,104 +1,768 @@
 //! 命令列と入力文字列を受け取り、マッチングを行う
-use super::Instruction;
+use super::{parser::Assertion, unicode::is_word, Instruction};
+use crate::helper::safe_add;
 use std::{
-    collections::VecDeque,
+    collections::{HashSet, VecDeque},
     error::Error,
     fmt::{self, Display},
+    hash::{BuildHasherDefault, Hasher},
+    ops::{AddAssign, Range},
 };
 
 #[derive(Debug)]
 pub enum EvalError {
     PCOverFlow,
     SPOverFlow,
-    InvalidContext,
+    NoMatch,
+    AtomicInWidth, // 幅優先探索ではアトミックグループと強欲な量指定子を評価できない
 }
 
 impl Display for EvalError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "CodeGenError: {:?}", self)
+        match self {
+            EvalError::AtomicInWidth => write!(
+                f,
+                "EvalError: atomic groups and possessive quantifiers require depth-first search"
+            ),
+            _ => write!(f, "EvalError: {:?}", self),
+        }
     }
 }
 
 impl Error for EvalError {}
 
+/// キャプチャグループの位置を保存するスロット。
+///
+/// n番目のグループの開始位置は2n、終了位置は2n+1番目に保存する。
+pub type Slots = Vec<Option<usize>>;
+
+/// マッチの結果。終了位置、実行したmatch命令の番号、スロット
+type Hit = (usize, usize, Slots);
+
+/// 評価に用いる作業領域
+///
+/// 評価のたびにスタックや評価済みの状態の記録を確保し直さないよう、呼び出し側で保持して使い回す。
+/// 内容は評価の開始時に初期化するため、異なる命令列や入力の評価にも用いることができる。
+#[derive(Debug, Default)]
+pub struct Cache {
+    depth: Vec<DepthCache>, // 深さ優先探索用。アトミックグループの入れ子の深さごとに1つ
+    width: WidthCache,      // 幅優先探索用
+}
+
+/// 深さ優先探索の作業領域
+#[derive(Debug, Default)]
+struct DepthCache {
+    stack: Vec<(usize, usize, Slots)>, // バックトラック用のスタック
+    visited: Visited,                  // 評価済みの(pc, sp)の組
+}
+
+/// 評価済みの(pc, sp)の組の集合
+type Visited = HashSet<(usize, usize), BuildHasherDefault<StateHasher>>;
+
+/// [Visited]に用いるハッシュ関数。
+///
+/// キーは外部から与えられないため、SipHashのようなHashDoSへの耐性は不要とし、
+/// 整数ごとに回転と乗算を1回ずつ行うだけの軽量なものとする（FxHashと同じ方式）。
+#[derive(Debug, Default)]
+struct StateHasher(u64);
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.write_u64(*b as u64);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = (self.0.rotate_left(5) ^ i).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+}
+
+/// 幅優先探索のスレッド。プログラムカウンタ、マッチの開始位置、スロット
+type Thread = (usize, usize, Slots);
+
+/// 幅優先探索の作業領域
+#[derive(Debug, Default)]
+struct WidthCache {
+    current: VecDeque<Thread>,  // 現在の位置のスレッド
+    next: VecDeque<Thread>,     // 次の位置のスレッド
+    visited: Vec<bool>,         // 同じ位置でスレッドを追加済みのpc
+    stack: Vec<(usize, Slots)>, // スレッドの追加時に辿る命令
+}
+
 /// 命令列の評価を行う関数。
 ///
-/// instが命令列となり、その命令列を用いて入力文字列lineにマッチさせる。
+/// instが命令列となり、その命令列を用いて入力文字列lineの位置startからマッチさせる。
 /// is_depthがtrueの場合に深さ優先探索を、falseの場合に幅優先探索を行う。
+/// ^や$などの表明はline全体を基準に判定するため、
+/// 行の途中から評価する場合もlineをスライスせずにstartを指定すること。
+/// 位置の単位は[Haystack]の実装による。
+///
+/// cacheは評価に用いる作業領域で、同じものを繰り返し渡すことでメモリの確保を省ける。
 ///
 /// 実行時エラーが起きた場合はErrを返す。
-/// マッチ成功時はOk(true)を、失敗時はOk(false)を返す。
-pub fn eval(insts: &[Instruction], line: &[char], is_depth: bool) -> Result<String, EvalError> {
-    let mut v: VecDeque<(usize, usize)> = VecDeque::new();
-    let mut ans: Vec<String> = Vec::new();
-    fn _eval(
-        insts: &[Instruction],
-        line: &[char],
-        pc: usize,
-        sp: usize,
-        is_depth: bool,
-        v: &mut VecDeque<(usize, usize)>,
-        ans: &mut Vec<String>,
-    ) -> Result<String, EvalError> {
-        if pc >= insts.len() {
-            return Err(EvalError::PCOverFlow);
+/// マッチ成功時は最長のマッチの終了位置をOk(Some(end))で、失敗時はOk(None)を返す。
+pub fn eval<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    line: &H,
+    start: usize,
+    is_depth: bool,
+    cache: &mut Cache,
+) -> Result<Option<usize>, EvalError> {
+    let mut stats = Stats::default();
+    match eval_partial(insts, line, start, is_depth, true, &mut stats, cache)? {
+        Partial::Done(end) => Ok(end),
+        Partial::Incomplete => unreachable!("input is complete"),
+    }
+}
+
+/// キャプチャグループの位置も求める[eval]。
+///
+/// nslotsはスロットの数。マッチ成功時は最長のマッチの終了位置と、
+/// そのマッチでの各グループの位置を返す。
+pub fn eval_captures<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    line: &H,
+    start: usize,
+    is_depth: bool,
+    nslots: usize,
+    cache: &mut Cache,
+) -> Result<Option<(usize, Slots)>, EvalError> {
+    let mut input = Input {
+        line,
+        eof: true,
+        hit_end: false,
+        stats: Stats::default(),
+    };
+    let hit = if is_depth {
+        eval_depth(insts, &mut input, start, nslots, cache)?
+    } else {
+        eval_width(insts, &mut input, start, nslots, true, cache)?.map(|(_, hit)| hit)
+    };
+    Ok(hit.map(|(end, _, slots)| (end, slots)))
+}
+
+/// 番号付きのmatch命令を持つ命令列を評価する[eval]。
+///
+/// マッチ成功時は最長のマッチの終了位置と、実行したmatch命令の番号を返す。
+/// 同じ長さで複数のmatch命令に到達する場合は、優先度の高い経路のものとなる。
+pub fn eval_tagged<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    line: &H,
+    start: usize,
+    is_depth: bool,
+    cache: &mut Cache,
+) -> Result<Option<(usize, usize)>, EvalError> {
+    let mut input = Input {
+        line,
+        eof: true,
+        hit_end: false,
+        stats: Stats::default(),
+    };
+    let hit = if is_depth {
+        eval_depth(insts, &mut input, start, 0, cache)?
+    } else {
+        eval_width(insts, &mut input, start, 0, true, cache)?.map(|(_, hit)| hit)
+    };
+    Ok(hit.map(|(end, tag, _)| (end, tag)))
+}
+
+/// 位置start以降で最初にマッチする範囲を求める。
+///
+/// 同じ開始位置では最長のマッチを返す。結果は開始位置をずらしながら[eval]を繰り返す場合と同じだが、
+/// 入力を一度だけ辿るため、マッチしない行でも計算量は命令数×文字数に抑えられる。
+///
+/// 幅優先探索では、各位置で開始するスレッドを優先度の最も低いスレッドとして追加する。
+/// 深さ優先探索では、マッチしなかった開始位置で評価済みの(pc, sp)の組は、
+/// 以降の開始位置でも失敗するため、記録を破棄せずに次の開始位置の評価に用いる。
+/// 評価中の統計情報も返す。
+pub fn eval_find<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    line: &H,
+    start: usize,
+    is_depth: bool,
+    cache: &mut Cache,
+) -> Result<(Option<Range<usize>>, Stats), EvalError> {
+    let mut input = Input {
+        line,
+        eof: true,
+        hit_end: false,
+        stats: Stats::default(),
+    };
+    let hit = if is_depth {
+        find_depth(insts, &mut input, start, cache)?
+    } else {
+        eval_width(insts, &mut input, start, 0, false, cache)?
+    };
+    Ok((hit.map(|(begin, (end, _, _))| begin..end), input.stats))
+}
+
+/// 幅優先探索で評価できる命令列か確かめる。
+///
+/// アトミックグループと強欲な量指定子は深さ優先探索でのみ評価できるため、
+/// これらを含む場合は[EvalError::AtomicInWidth]を返す。
+pub fn check_width(insts: &[Instruction]) -> Result<(), EvalError> {
+    if insts
+        .iter()
+        .any(|inst| matches!(inst, Instruction::Atomic(_)))
+    {
+        return Err(EvalError::AtomicInWidth);
+    }
+    Ok(())
+}
+
+/// 評価中の統計情報
+///
+/// 深さ優先探索では、バックトラックのためにスタックに積んだ分岐をスレッドとみなす。
+/// キャッシュは評価済みの状態の記録で、深さ優先探索では(pc, sp)の組、
+/// 幅優先探索では同じ位置でスレッドを追加済みのpcを記録する。
+///
+/// `+=`で複数回の評価の統計情報を合算できる。peak_queueは最大値、それ以外は合計となる。
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub instructions: usize, // 実行した命令の数
+    pub threads: usize,      // 生成したスレッドの数
+    pub peak_queue: usize,   // スレッドのキュー（深さ優先探索ではスタック）の最大長
+    pub cache_hits: usize,   // 評価済みの状態に再度到達した回数
+    pub cache_misses: usize, // 未評価の状態に到達した回数
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.instructions += rhs.instructions;
+        self.threads += rhs.threads;
+        self.peak_queue = self.peak_queue.max(rhs.peak_queue);
+        self.cache_hits += rhs.cache_hits;
+        self.cache_misses += rhs.cache_misses;
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "instructions = {}, threads = {}, peak queue = {}, cache hits = {}, cache misses = {}",
+            self.instructions, self.threads, self.peak_queue, self.cache_hits, self.cache_misses
+        )
+    }
+}
+
+/// 統計情報も求める[eval]
+pub fn eval_stats<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    line: &H,
+    start: usize,
+    is_depth: bool,
+    cache: &mut Cache,
+) -> Result<(Option<usize>, Stats), EvalError> {
+    let mut input = Input {
+        line,
+        eof: true,
+        hit_end: false,
+        stats: Stats::default(),
+    };
+    let end = if is_depth {
+        eval_depth(insts, &mut input, start, 0, cache)?
+    } else {
+        eval_width(insts, &mut input, start, 0, true, cache)?.map(|(_, hit)| hit)
+    };
+    Ok((end.map(|(end, _, _)| end), input.stats))
+}
+
+/// 途中までしか読み込んでいない入力に対する評価結果
+#[derive(Debug, PartialEq)]
+pub enum Partial {
+    Done(Option<usize>), // 評価完了。マッチした場合は最長のマッチの終了位置
+    Incomplete,          // 結果を確定するには続きの入力が必要
+}
+
+/// 入力の続きがあるかもしれない状態で、命令列の評価を行う関数。
+///
+/// eofがfalseの場合、lineの末尾は入力の終わりとはみなさない。
+/// 評価中にlineの末尾を参照した場合は、続きの入力次第で結果が変わるため、
+/// Partial::Incompleteを返す。eofがtrueの場合は[eval]と同じ。
+/// 評価中の統計情報はstatsに加算する。
+pub fn eval_partial<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    line: &H,
+    start: usize,
+    is_depth: bool,
+    eof: bool,
+    stats: &mut Stats,
+    cache: &mut Cache,
+) -> Result<Partial, EvalError> {
+    let mut input = Input {
+        line,
+        eof,
+        hit_end: false,
+        stats: Stats::default(),
+    };
+    // グループの位置は不要なため、スロットは確保しない
+    let end = if is_depth {
+        eval_depth(insts, &mut input, start, 0, cache)?
+    } else {
+        eval_width(insts, &mut input, start, 0, true, cache)?.map(|(_, hit)| hit)
+    };
+    *stats += input.stats;
+    if input.hit_end && !eof {
+        Ok(Partial::Incomplete)
+    } else {
+        Ok(Partial::Done(end.map(|(end, _, _)| end)))
+    }
+}
+
+/// 評価対象の文字列
+///
+/// 位置の単位は実装ごとに異なり、`[char]`と`Vec<char>`では文字単位、`str`ではバイト単位となる。
+/// `str`の場合は文字の境界の位置のみを扱い、UTF-8をそのまま辿るため、
+/// `Vec<char>`への変換は不要となる。
+pub trait Haystack {
+    /// 末尾の位置
+    fn end(&self) -> usize;
+
+    /// 位置posの文字と、次の文字の位置。posが末尾の場合はNone
+    fn char_at(&self, pos: usize) -> Option<(char, usize)>;
+
+    /// 位置posの直前の文字。posが先頭の場合はNone
+    fn char_before(&self, pos: usize) -> Option<char>;
+
+    /// 文字単位の入力の場合はそのスライス。JITコンパイルした機械語での評価に用いる
+    fn as_chars(&self) -> Option<&[char]> {
+        None
+    }
+
+    /// UTF-8の文字列の場合はその文字列。JITコンパイルした機械語での評価に用いる
+    fn as_str(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Haystack for [char] {
+    fn end(&self) -> usize {
+        self.len()
+    }
+
+    fn char_at(&self, pos: usize) -> Option<(char, usize)> {
+        self.get(pos).map(|c| (*c, pos + 1))
+    }
+
+    fn char_before(&self, pos: usize) -> Option<char> {
+        pos.checked_sub(1).and_then(|i| self.get(i)).copied()
+    }
+
+    fn as_chars(&self) -> Option<&[char]> {
+        Some(self)
+    }
+}
+
+impl Haystack for Vec<char> {
+    fn end(&self) -> usize {
+        self.len()
+    }
+
+    fn char_at(&self, pos: usize) -> Option<(char, usize)> {
+        self[..].char_at(pos)
+    }
+
+    fn char_before(&self, pos: usize) -> Option<char> {
+        self[..].char_before(pos)
+    }
+
+    fn as_chars(&self) -> Option<&[char]> {
+        Some(self)
+    }
+}
+
+impl Haystack for str {
+    fn end(&self) -> usize {
+        self.len()
+    }
+
+    fn char_at(&self, pos: usize) -> Option<(char, usize)> {
+        // ASCIIの場合はデコードを省く
+        let b = *self.as_bytes().get(pos)?;
+        if b.is_ascii() {
+            return Some((b as char, pos + 1));
         }
-        match insts[pc] {
-            Instruction::Char(c) => {
-                if sp >= line.len(){
-                    return Err(EvalError::SPOverFlow);
-                } else if c == line[sp] {
-                    return _eval(insts, line, pc + 1, sp + 1, is_depth, v, ans);
-                } else {
-                    return Err(EvalError::InvalidContext);
-                }
-            }
-            Instruction::Match => {
-                // println!("match  :{:?}", &line[0..sp]);
-                let answer = format!(
-                    "{}",
-                    &line[0..sp]
-                        .iter()
-                        .fold(String::new(), |accm, x| accm + x.to_string().as_str())
-                );
-                ans.push(answer.clone());
-                return Ok(answer);
-            }
-            Instruction::Jump(pc1) => {
-                return _eval(insts, line, pc1, sp, is_depth, v, ans);
+        let c = self.get(pos..)?.chars().next()?;
+        Some((c, pos + c.len_utf8()))
+    }
+
+    fn char_before(&self, pos: usize) -> Option<char> {
+        self.get(..pos)?.chars().next_back()
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        Some(self)
+    }
+}
+
+/// 評価対象の入力
+struct Input<'a, H: ?Sized> {
+    line: &'a H,
+    eof: bool,     // lineの末尾が入力の終わりか
+    hit_end: bool, // 評価中にlineの末尾を参照したか
+    stats: Stats,
+}
+
+impl<H: Haystack + ?Sized> Input<'_, H> {
+    /// 表明を評価
+    fn check_assert(&mut self, a: Assertion, sp: usize) -> bool {
+        let line = self.line;
+        match a {
+            Assertion::StartText => sp == 0,
+            Assertion::StartLine => sp == 0 || line.char_before(sp) == Some('\n'),
+            Assertion::EndText => self.at_end(sp),
+            Assertion::EndLine => {
+                self.at_end(sp) || line.char_at(sp).is_some_and(|(c, _)| c == '\n')
             }
-            Instruction::Split(pc1, pc2) => {
-                v.push_back((pc2, sp));
-                v.push_back((pc1, sp));
-                return Ok(String::new());
+            Assertion::WordBoundary
+            | Assertion::NotWordBoundary
+            | Assertion::AsciiWordBoundary
+            | Assertion::AsciiNotWordBoundary => {
+                let ascii = a.word_ascii() == Some(true);
+                let before = line.char_before(sp).is_some_and(|c| is_word(c, ascii));
+                let after = match line.char_at(sp) {
+                    Some((c, _)) => is_word(c, ascii),
+                    None => {
+                        // 続きの入力次第で結果が変わるため、末尾を参照したことを記録する
+                        self.at_end(sp);
+                        false
+                    }
+                };
+                let boundary = before != after;
+                boundary == matches!(a, Assertion::WordBoundary | Assertion::AsciiWordBoundary)
             }
+        }
+    }
+
+    /// 入力の終わりか判定
+    fn at_end(&mut self, sp: usize) -> bool {
+        if sp < self.line.end() {
+            return false;
+        }
+        self.hit_end = true;
+        self.eof
+    }
+
+    /// 1文字を消費する命令を評価し、マッチした場合は次の文字の位置を返す
+    fn check_char(&mut self, inst: &Instruction, sp: usize) -> Option<usize> {
+        let Some((x, next)) = self.line.char_at(sp) else {
+            self.hit_end = true;
+            return None;
+        };
+        let matched = match inst {
+            Instruction::Char(c) => *c == x,
+            Instruction::AnyChar(nl) => *nl || x != '\n',
+            Instruction::Class(class) => class.contains(x),
+            _ => false,
         };
+        matched.then_some(next)
     }
-    // 分岐のないパターンを評価
-    let r = _eval(insts, line, 0, 0, is_depth, &mut v, &mut ans)?;
-    // 分岐パターンを評価
-    if r.len() == 0 {
+}
+
+/// 深さ優先探索で評価
+///
+/// 一度評価した(pc, sp)の組は再評価しないため、
+/// `(a*)*`のような式でも無限ループせず、計算量は命令数×文字数に抑えられる。
+/// 同じ(pc, sp)に到達する経路のうち、優先度の高い（先に探索した）経路のグループの位置を採用する。
+fn eval_depth<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    input: &mut Input<H>,
+    start: usize,
+    nslots: usize,
+    cache: &mut Cache,
+) -> Result<Option<Hit>, EvalError> {
+    search_depth(insts, input, 0, start, vec![None; nslots], None, cache)
+}
+
+/// 開始位置をstartから1文字ずつずらしながら深さ優先探索を行い、
+/// 最初にマッチした開始位置と、そのマッチを返す。
+///
+/// 評価済みの(pc, sp)の組の記録は、全ての開始位置で共有する。
+/// マッチしなかった開始位置から到達した状態は、どの経路でもmatch命令に到達しないため、
+/// 次の開始位置で再び到達しても探索を打ち切ってよい。
+fn find_depth<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    input: &mut Input<H>,
+    start: usize,
+    cache: &mut Cache,
+) -> Result<Option<(usize, Hit)>, EvalError> {
+    let mut scratch = cache.depth.pop().unwrap_or_default();
+    scratch.visited.clear();
+    let mut begin = start;
+    let ret = loop {
+        scratch.stack.clear();
+        scratch.stack.push((0, begin, Vec::new()));
+        match search_depth_with(insts, input, None, &mut scratch, cache) {
+            Ok(Some(hit)) => break Ok(Some((begin, hit))),
+            Ok(None) => {}
+            Err(e) => break Err(e),
+        }
+        match input.line.char_at(begin) {
+            Some((_, next)) => begin = next,
+            None => break Ok(None),
+        }
+    };
+    cache.depth.push(scratch);
+    ret
+}
+
+/// 位置(pc, sp)から深さ優先探索を行う。
+///
+/// bodyがNoneの場合は、全ての経路を探索して最長のマッチを返す。
+/// bodyを指定した場合はアトミックグループの評価とし、
+/// 優先度の最も高い経路でbody.endに到達した時点で、その位置とスロットを返す。
+/// グループ内の残りの分岐は探索しない。body外の命令に到達した経路は失敗とみなす。
+///
+/// 作業領域はcacheから入れ子の深さごとに取り出し、終了後に戻す。
+fn search_depth<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    input: &mut Input<H>,
+    pc: usize,
+    sp: usize,
+    slots: Slots,
+    body: Option<Range<usize>>,
+    cache: &mut Cache,
+) -> Result<Option<Hit>, EvalError> {
+    let mut scratch = cache.depth.pop().unwrap_or_default();
+    scratch.stack.clear();
+    scratch.visited.clear();
+    scratch.stack.push((pc, sp, slots));
+    let ret = search_depth_with(insts, input, body, &mut scratch, cache);
+    cache.depth.push(scratch);
+    ret
+}
+
+/// 作業領域scratchのスタックに積んだ位置から、[search_depth]の探索を行う
+fn search_depth_with<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    input: &mut Input<H>,
+    body: Option<Range<usize>>,
+    scratch: &mut DepthCache,
+    cache: &mut Cache,
+) -> Result<Option<Hit>, EvalError> {
+    let DepthCache { stack, visited } = scratch;
+    let mut longest: Option<Hit> = None;
+    input.stats.threads += 1;
+    input.stats.peak_queue = input.stats.peak_queue.max(1);
+
+    while let Some((mut pc, mut sp, mut slots)) = stack.pop() {
         loop {
-            let result = if is_depth {
-                v.pop_back()
-            } else {
-                v.pop_front()
-            };
-            match result {
-                Some((pc, sp)) => {
-                    if let Ok(_) = _eval(insts, line, pc, sp, is_depth, &mut v, &mut ans) {}
+            if let Some(body) = &body {
+                if pc == body.end {
+                    return Ok(Some((sp, 0, slots)));
+                }
+                if !body.contains(&pc) {
+                    break;
+                }
+            }
+            if !visited.insert((pc, sp)) {
+                input.stats.cache_hits += 1;
+                break;
+            }
+            input.stats.cache_misses += 1;
+            input.stats.instructions += 1;
+            let inst = insts.get(pc).ok_or(EvalError::PCOverFlow)?;
+            match inst {
+                Instruction::Char(_) | Instruction::AnyChar(_) | Instruction::Class(_) => {
+                    let Some(next_sp) = input.check_char(inst, sp) else {
+                        break;
+                    };
+                    safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                    sp = next_sp;
+                }
+                Instruction::Assert(a) => {
+                    if !input.check_assert(*a, sp) {
+                        break;
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                }
+                Instruction::Save(n) => {
+                    if let Some(slot) = slots.get_mut(*n) {
+                        *slot = Some(sp);
+                    }
+                    safe_add(&mut pc, &1, || EvalError::PCOverFlow)?;
+                }
+                Instruction::Match(tag) => {
+                    if longest.as_ref().is_none_or(|(end, _, _)| *end < sp) {
+                        longest = Some((sp, *tag, slots.clone()));
+                    }
+                    break;
+                }
+                Instruction::Jump(addr) => pc = *addr,
+                Instruction::Split(addr1, addr2) => {
+                    stack.push((*addr2, sp, slots.clone()));
+                    input.stats.threads += 1;
+                    input.stats.peak_queue = input.stats.peak_queue.max(stack.len());
+                    pc = *addr1;
+                }
+                Instruction::Atomic(end) => {
+                    let mut next_pc = pc;
+                    safe_add(&mut next_pc, &1, || EvalError::PCOverFlow)?;
+                    let body = next_pc..*end;
+                    match search_depth(insts, input, next_pc, sp, slots, Some(body), cache)? {
+                        Some((end_sp, _, end_slots)) => {
+                            pc = *end;
+                            sp = end_sp;
+                            slots = end_slots;
+                        }
+                        None => break,
+                    }
                 }
-                _ => break,
             }
         }
     }
-    // 最長の解を表示
-    let mut longest: String = String::from("");
-    for str in ans {
-        if str.chars().count() > longest.chars().count() {
-            longest = str;
+
+    // アトミックグループの終わりに到達しなかった場合は失敗
+    Ok(longest.filter(|_| body.is_none()))
+}
+
+/// 幅優先探索で評価
+///
+/// 入力の1文字ごとに、その位置で実行中のスレッド（プログラムカウンタ）を全て進める。
+/// スレッドは優先度の順に並べ、同じ命令に到達したスレッドは優先度の高いもののみ残すため、
+/// グループの位置は、最長のマッチに到達した経路のうち優先度の最も高いものとなる。
+///
+/// anchoredがfalseの場合は、start以降の各位置で開始するスレッドを優先度の最も低いスレッドとして追加し、
+/// 最も前の開始位置からの最長のマッチを求める。
+/// スレッドは開始位置の順に並ぶため、マッチした後は開始位置がより後のスレッドを破棄する。
+/// マッチの開始位置と、そのマッチを返す。
+fn eval_width<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    input: &mut Input<H>,
+    start: usize,
+    nslots: usize,
+    anchored: bool,
+    cache: &mut Cache,
+) -> Result<Option<(usize, Hit)>, EvalError> {
+    let WidthCache {
+        current,
+        next,
+        visited,
+        stack,
+    } = &mut cache.width;
+    current.clear();
+    next.clear();
+    visited.clear();
+    visited.resize(insts.len(), false);
+    let mut longest: Option<(usize, Hit)> = None;
+    let mut sp = start;
+
+    let slots = vec![None; nslots];
+    add_thread(insts, input, sp, 0, (start, slots), current, visited, stack)?;
+    loop {
+        input.stats.peak_queue = input.stats.peak_queue.max(current.len());
+        visited.fill(false);
+        // 末尾ではどのスレッドも進めないため、next_spは使われない
+        let next_sp = input.line.char_at(sp).map_or(sp, |(_, next)| next);
+        while let Some((pc, begin, slots)) = current.pop_front() {
+            // マッチより後の位置で開始したスレッドは不要
+            if longest.as_ref().is_some_and(|(b, _)| *b < begin) {
+                continue;
+            }
+            input.stats.instructions += 1;
+            let inst = &insts[pc];
+            match inst {
+                Instruction::Match(tag) => {
+                    // 同じ位置では優先度の高い（先に並ぶ）スレッドを採用する
+                    let longer = |(b, (end, _, _)): &(usize, Hit)| begin < *b || *end < sp;
+                    if longest.as_ref().is_none_or(longer) {
+                        longest = Some((begin, (sp, *tag, slots)));
+                    }
+                }
+                _ => {
+                    if input.check_char(inst, sp).is_some() {
+                        let mut next_pc = pc;
+                        safe_add(&mut next_pc, &1, || EvalError::PCOverFlow)?;
+                        let thread = (begin, slots);
+                        add_thread(insts, input, next_sp, next_pc, thread, next, visited, stack)?;
+                    }
+                }
+            }
+        }
+        // マッチするまでは、次の位置で開始するスレッドを追加する
+        let seed = !anchored && longest.is_none() && next_sp > sp;
+        if seed {
+            let thread = (next_sp, vec![None; nslots]);
+            add_thread(insts, input, next_sp, 0, thread, next, visited, stack)?;
         }
+        if next.is_empty() && !seed {
+            break;
+        }
+        std::mem::swap(current, next);
+        sp = next_sp;
     }
-    // println!("longest:{}", longest);
+
     Ok(longest)
 }
+
+/// スレッドを追加
+///
+/// jump、split、assert、saveは文字を消費しないため、ここで辿ってしまい、
+/// 文字を消費する命令とmatch命令のみをスレッドとして追加する。
+/// アトミックグループは評価できないため、到達した場合はエラーとする。
+/// threadはマッチの開始位置とスロット、stackは辿る命令を積む作業領域。
+#[allow(clippy::too_many_arguments)]
+fn add_thread<H: Haystack + ?Sized>(
+    insts: &[Instruction],
+    input: &mut Input<H>,
+    sp: usize,
+    pc: usize,
+    thread: (usize, Slots),
+    threads: &mut VecDeque<Thread>,
+    visited: &mut [bool],
+    stack: &mut Vec<(usize, Slots)>,
+) -> Result<(), EvalError> {
+    let (begin, slots) = thread;
+    stack.clear();
+    stack.push((pc, slots));
+    while let Some((pc, mut slots)) = stack.pop() {
+        let inst = insts.get(pc).ok_or(EvalError::PCOverFlow)?;
+        if visited[pc] {
+            input.stats.cache_hits += 1;
+            continue;
+        }
+        visited[pc] = true;
+        input.stats.cache_misses += 1;
+        match inst {
+            Instruction::Jump(addr) => stack.push((*addr, slots)),
+            Instruction::Split(addr1, addr2) => {
+                stack.push((*addr2, slots.clone()));
+                stack.push((*addr1, slots));
+            }
+            Instruction::Assert(a) => {
+                if input.check_assert(*a, sp) {
+                    let mut next_pc = pc;
+                    safe_add(&mut next_pc, &1, || EvalError::PCOverFlow)?;
+                    stack.push((next_pc, slots));
+                }
+            }
+            Instruction::Save(n) => {
+                if let Some(slot) = slots.get_mut(*n) {
+                    *slot = Some(sp);
+                }
+                let mut next_pc = pc;
+                safe_add(&mut next_pc, &1, || EvalError::PCOverFlow)?;
+                stack.push((next_pc, slots));
+            }
+            Instruction::Atomic(_) => return Err(EvalError::AtomicInWidth),
+            _ => {
+                // スレッドとして追加する命令は、スレッドを進める際に数える
+                input.stats.threads += 1;
+                threads.push_back((pc, begin, slots));
+                continue;
+            }
+        }
+        input.stats.instructions += 1;
+    }
+    Ok(())
+}
@@ -0,0 +1,425 @@
+//! 正規表現が受理する文字列の生成
+//!
+//! ASTをたどり、正規表現全体にマッチする文字列を短い順に列挙したり、ランダムに生成したりする。
+//! テストのフィクスチャやファジングの入力の作成に用いることを想定している。
+//!
+//! 生成する文字列は、長さの上限と、`*`と`+`の繰り返し回数の上限で制限する。
+//! 受理する文字列が少ない（または無い）場合に列挙が終わらなくなるのを防ぐため、
+//! 短い順の列挙では展開する回数にも上限を設け、達した時点で見つかった分を返す。
+//! 文字クラスと`.`は、読みやすいASCIIの文字を優先して選ぶ。
+//! 表明（`^`や`$`）とアトミックグループによる制約は生成時には無視し、
+//! 命令列で全体にマッチするか確かめた文字列のみ返す。
+//!
+//! ```
+//! use regex::generate::Generator;
+//! let gen = Generator::new("a(b|c)*").unwrap();
+//! assert_eq!(gen.shortest(5), vec!["a", "ab", "ac", "abb", "abc"]);
+//!
+//! // シードが同じなら同じ文字列を生成する
+//! let samples = gen.sample(3, 42);
+//! assert_eq!(samples, gen.sample(3, 42));
+//! assert!(samples.iter().all(|s| s.starts_with('a')));
+//! ```
+use super::{
+    class::Class,
+    codegen,
+    evaluator::{self, Cache},
+    parser::AST,
+    Error, Flags, Instruction,
+};
+use std::collections::HashSet;
+
+/// 生成する文字列の長さの上限の初期値
+pub const DEFAULT_MAX_LEN: usize = 16;
+
+/// `*`と`+`の繰り返し回数の上限の初期値
+pub const DEFAULT_MAX_REPEAT: usize = 3;
+
+/// 短い順に列挙する際の、ASTの要素を展開する回数の上限の初期値
+pub const DEFAULT_MAX_STEPS: usize = 100_000;
+
+/// 列挙時に、文字クラスから選ぶ読みやすいASCII以外の文字の数
+const OTHER_CHARS: usize = 3;
+
+/// ランダムに生成する際の、1つの文字列あたりの試行回数の上限
+const ATTEMPTS: usize = 100;
+
+/// 正規表現が受理する文字列を生成する
+#[derive(Debug)]
+pub struct Generator {
+    ast: AST,
+    code: Vec<Instruction>,
+    max_len: usize,
+    max_repeat: usize,
+    max_steps: usize,
+}
+
+impl Generator {
+    /// 正規表現をパースし、生成器を作る
+    pub fn new(expr: &str) -> Result<Self, Error> {
+        Self::with_flags(expr, Flags::default())
+    }
+
+    /// フラグの初期値を指定して、生成器を作る
+    pub fn with_flags(expr: &str, flags: Flags) -> Result<Self, Error> {
+        let ast = super::parse(expr, flags)?;
+        let code = codegen::get_code(&ast)?;
+        Ok(Generator {
+            ast,
+            code,
+            max_len: DEFAULT_MAX_LEN,
+            max_repeat: DEFAULT_MAX_REPEAT,
+            max_steps: DEFAULT_MAX_STEPS,
+        })
+    }
+
+    /// 生成する文字列の長さ（文字数）の上限を指定
+    pub fn max_len(mut self, n: usize) -> Self {
+        self.max_len = n;
+        self
+    }
+
+    /// `*`と`+`の繰り返し回数の上限を指定。`+`は上限が0でも1回は繰り返す
+    pub fn max_repeat(mut self, n: usize) -> Self {
+        self.max_repeat = n;
+        self
+    }
+
+    /// 短い順に列挙する際の、ASTの要素を展開する回数の上限を指定
+    pub fn max_steps(mut self, n: usize) -> Self {
+        self.max_steps = n;
+        self
+    }
+
+    /// 受理する文字列を短い順に、重複なく最大n個返す。
+    ///
+    /// 同じ長さの文字列は、ASTの選択肢の順（`|`の左、繰り返しの少ない方が先）に並ぶ。
+    /// 展開する回数の上限に達した場合は、それまでに見つかった文字列のみ返す。
+    pub fn shortest(&self, n: usize) -> Vec<String> {
+        let mut e = Enumerator {
+            gen: self,
+            buf: Vec::new(),
+            seen: HashSet::new(),
+            out: Vec::new(),
+            limit: n,
+            steps: 0,
+            cache: Cache::default(),
+        };
+        for len in 0..=self.max_len {
+            if !e.walk(&mut vec![Item::Node(&self.ast)], len) {
+                break;
+            }
+        }
+        e.out
+    }
+
+    /// 受理する文字列を、シードseedの乱数でランダムにn個生成する。
+    ///
+    /// 重複を含むことがある。上限の範囲で受理する文字列を見つけられない場合は、n個より少なくなる。
+    pub fn sample(&self, n: usize, seed: u64) -> Vec<String> {
+        let mut rng = Rng::new(seed);
+        let mut out = Vec::new();
+        let mut cache = Cache::default();
+        for _ in 0..n.saturating_mul(ATTEMPTS) {
+            if out.len() >= n {
+                break;
+            }
+            let mut buf = Vec::new();
+            if self.sample_node(&self.ast, &mut rng, &mut buf) && self.accepts(&buf, &mut cache) {
+                out.push(buf.into_iter().collect());
+            }
+        }
+        out
+    }
+
+    /// ASTをランダムにたどり、bufに文字を追加する。長さの上限を超えた場合はfalseを返す
+    fn sample_node(&self, ast: &AST, rng: &mut Rng, buf: &mut Vec<char>) -> bool {
+        match ast {
+            AST::Char(c) => buf.push(*c),
+            AST::AnyChar(nl) => buf.push(rng.char_in(&any_char(*nl))),
+            AST::Class(class) => buf.push(rng.char_in(class)),
+            AST::Assert(_) => (),
+            AST::Question(e) => {
+                if rng.below(2) == 0 {
+                    return self.sample_node(e, rng, buf);
+                }
+            }
+            AST::Star(e) | AST::Plus(e) => {
+                let min = usize::from(matches!(ast, AST::Plus(_)));
+                let max = self.max_repeat.max(min);
+                for _ in 0..min + rng.below(max - min + 1) {
+                    if !self.sample_node(e, rng, buf) {
+                        return false;
+                    }
+                }
+            }
+            AST::Or(e1, e2) => {
+                let e = if rng.below(2) == 0 { e1 } else { e2 };
+                return self.sample_node(e, rng, buf);
+            }
+            AST::Seq(v) => {
+                for e in v {
+                    if !self.sample_node(e, rng, buf) {
+                        return false;
+                    }
+                }
+            }
+            AST::Capture(_, _, e) | AST::Atomic(e) => return self.sample_node(e, rng, buf),
+        }
+        buf.len() <= self.max_len
+    }
+
+    /// 命令列が文字列全体にマッチするか。アトミックグループを扱えるよう、深さ優先探索で評価する
+    fn accepts(&self, line: &[char], cache: &mut Cache) -> bool {
+        let end = evaluator::eval(&self.code, line, 0, true, cache);
+        matches!(end, Ok(Some(end)) if end == line.len())
+    }
+}
+
+/// 列挙中に、これから展開する要素
+#[derive(Clone, Copy)]
+enum Item<'a> {
+    Node(&'a AST),
+    Repeat(&'a AST, usize, usize), // 繰り返す式、最小の回数、繰り返した回数
+}
+
+/// 決まった長さの文字列を列挙するための情報
+struct Enumerator<'a> {
+    gen: &'a Generator,
+    buf: Vec<char>,        // 生成中の文字列
+    seen: HashSet<String>, // 出力済みの文字列
+    out: Vec<String>,
+    limit: usize, // 出力する文字列の数の上限
+    steps: usize, // これまでに展開した回数
+    cache: Cache, // 評価の作業領域
+}
+
+impl<'a> Enumerator<'a> {
+    /// todoの要素を後ろから展開し、残りremaining文字となる文字列を全て出力する。
+    ///
+    /// 上限の数か展開する回数の上限に達し、列挙を打ち切る場合はfalseを返す。
+    /// todoは呼び出し前の状態に戻す。
+    fn walk(&mut self, todo: &mut Vec<Item<'a>>, remaining: usize) -> bool {
+        self.steps += 1;
+        if self.out.len() >= self.limit || self.steps > self.gen.max_steps {
+            return false;
+        }
+        let Some(item) = todo.pop() else {
+            if remaining == 0 && self.gen.accepts(&self.buf, &mut self.cache) {
+                let s = self.buf.iter().collect::<String>();
+                if self.seen.insert(s.clone()) {
+                    self.out.push(s);
+                }
+            }
+            return self.out.len() < self.limit;
+        };
+
+        let cont = match item {
+            Item::Node(AST::Char(c)) => self.each_char(todo, remaining, &[*c]),
+            Item::Node(AST::AnyChar(nl)) => {
+                self.each_char(todo, remaining, &candidates(&any_char(*nl)))
+            }
+            Item::Node(AST::Class(class)) => self.each_char(todo, remaining, &candidates(class)),
+            Item::Node(AST::Assert(_)) => self.walk(todo, remaining),
+            Item::Node(AST::Question(e)) => {
+                self.walk(todo, remaining) && self.with(todo, &[Item::Node(e)], remaining)
+            }
+            Item::Node(AST::Star(e)) => self.with(todo, &[Item::Repeat(e, 0, 0)], remaining),
+            Item::Node(AST::Plus(e)) => self.with(todo, &[Item::Repeat(e, 1, 0)], remaining),
+            Item::Node(AST::Or(e1, e2)) => {
+                self.with(todo, &[Item::Node(e1)], remaining)
+                    && self.with(todo, &[Item::Node(e2)], remaining)
+            }
+            Item::Node(AST::Seq(v)) => {
+                let items = v.iter().rev().map(Item::Node).collect::<Vec<_>>();
+                self.with(todo, &items, remaining)
+            }
+            Item::Node(AST::Capture(_, _, e) | AST::Atomic(e)) => {
+                self.with(todo, &[Item::Node(e)], remaining)
+            }
+            Item::Repeat(e, min, count) => {
+                // 繰り返しを終えるか、もう1回繰り返す
+                (count < min || self.walk(todo, remaining))
+                    && (count >= self.gen.max_repeat.max(min)
+                        || self.with(
+                            todo,
+                            &[Item::Repeat(e, min, count + 1), Item::Node(e)],
+                            remaining,
+                        ))
+            }
+        };
+        todo.push(item);
+        cont
+    }
+
+    /// itemsを積んで展開し、積む前の状態に戻す
+    fn with(&mut self, todo: &mut Vec<Item<'a>>, items: &[Item<'a>], remaining: usize) -> bool {
+        let n = todo.len();
+        todo.extend_from_slice(items);
+        let cont = self.walk(todo, remaining);
+        todo.truncate(n);
+        cont
+    }
+
+    /// 文字charsのそれぞれを追加して展開する
+    fn each_char(&mut self, todo: &mut Vec<Item<'a>>, remaining: usize, chars: &[char]) -> bool {
+        if remaining == 0 {
+            return true;
+        }
+        for c in chars {
+            self.buf.push(*c);
+            let cont = self.walk(todo, remaining - 1);
+            self.buf.pop();
+            if !cont {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// `.`にマッチする文字のクラス。nlがtrueの場合は改行を含む
+fn any_char(nl: bool) -> Class {
+    if nl {
+        return Class::new(vec![('\0', char::MAX)]);
+    }
+    let mut class = Class::from_char('\n');
+    class.negate();
+    class
+}
+
+/// 読みやすい順に並べたASCIIの文字
+fn readable() -> impl Iterator<Item = char> {
+    ('a'..='z')
+        .chain('A'..='Z')
+        .chain('0'..='9')
+        .chain((' '..='~').filter(|c| !c.is_ascii_alphanumeric()))
+}
+
+/// 列挙時に文字クラスから選ぶ文字。
+///
+/// 含まれる読みやすいASCIIの文字を全てと、それ以外の文字を先頭から[OTHER_CHARS]個選ぶ。
+fn candidates(class: &Class) -> Vec<char> {
+    let mut chars = Vec::new();
+    for c in readable() {
+        if class.contains(c) {
+            chars.push(c);
+        }
+    }
+    let others = class
+        .ranges()
+        .iter()
+        .flat_map(|(lo, hi)| *lo..=*hi)
+        .filter(|c| !(' '..='~').contains(c))
+        .take(OTHER_CHARS);
+    chars.extend(others);
+    chars
+}
+
+/// 乱数生成器（xorshift64*）
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // 状態が0の場合は0しか生成しないため、シードを混ぜてから用いる
+        Rng(seed ^ 0x9e37_79b9_7f4a_7c15)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// 0以上n未満の値
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    /// クラスに含まれる文字。多くの場合は読みやすいASCIIの文字を選ぶ
+    fn char_in(&mut self, class: &Class) -> char {
+        let readable = readable()
+            .filter(|c| class.contains(*c))
+            .collect::<Vec<_>>();
+        if !readable.is_empty() && self.below(4) != 0 {
+            return readable[self.below(readable.len())];
+        }
+        let total = class
+            .ranges()
+            .iter()
+            .map(|(lo, hi)| *hi as u64 - *lo as u64 + 1)
+            .sum::<u64>();
+        loop {
+            let mut i = self.next() % total;
+            for (lo, hi) in class.ranges() {
+                let size = *hi as u64 - *lo as u64 + 1;
+                if i < size {
+                    // サロゲートの範囲を引いた場合は選び直す
+                    if let Some(c) = char::from_u32(*lo as u32 + i as u32) {
+                        return c;
+                    }
+                    break;
+                }
+                i -= size;
+            }
+        }
+    }
+}
+
+#[test]
+fn test() {
+    let shortest = |expr, n| Generator::new(expr).unwrap().shortest(n);
+    assert_eq!(shortest("a(b|c)*", 5), vec!["a", "ab", "ac", "abb", "abc"]);
+    assert_eq!(shortest("x?", 5), vec!["", "x"]);
+    assert_eq!(shortest("[0-2]+", 5), vec!["0", "1", "2", "00", "01"]);
+    assert_eq!(shortest("(a|a)*", 5), vec!["", "a", "aa", "aaa"]); // 重複を除く
+    assert_eq!(shortest("^a$|b", 5), vec!["a", "b"]);
+    assert_eq!(shortest("a$b", 5), Vec::<String>::new());
+    assert_eq!(shortest("(?i)ab", 2), vec!["ab", "aB"]);
+    assert_eq!(shortest(".", 2), vec!["a", "b"]);
+
+    // 上限
+    let gen = Generator::new("(ab)+").unwrap().max_repeat(2);
+    assert_eq!(gen.shortest(5), vec!["ab", "abab"]);
+    let gen = Generator::new("a*").unwrap().max_len(2).max_repeat(10);
+    assert_eq!(gen.shortest(5), vec!["", "a", "aa"]);
+    assert_eq!(
+        Generator::new("a+").unwrap().max_repeat(0).shortest(5),
+        vec!["a"]
+    );
+    // 受理する文字列が無い場合も、展開する回数の上限で打ち切る
+    assert_eq!(shortest(".*$a", 5), Vec::<String>::new());
+    let gen = Generator::new("[ab]*c").unwrap().max_steps(20);
+    assert_eq!(gen.shortest(5), vec!["c"]);
+
+    // ランダムに生成した文字列は全て受理される
+    for expr in [
+        "[a-z]+@[a-z]+\\.(com|org)",
+        "(?s)x.*y",
+        "[^a-c]?(de)*",
+        "(a*)*b",
+    ] {
+        let gen = Generator::new(expr).unwrap().max_len(12);
+        let re = crate::Regex::new(expr, true).unwrap();
+        let samples = gen.sample(50, 1);
+        assert_eq!(samples.len(), 50, "{expr}");
+        for s in samples {
+            let line = s.chars().collect::<Vec<char>>();
+            assert!(line.len() <= 12);
+            assert_eq!(
+                re.match_at(&line, 0).unwrap(),
+                Some(line.len()),
+                "{expr} {s:?}"
+            );
+        }
+    }
+    assert!(Generator::new("a$b").unwrap().sample(3, 0).is_empty());
+    assert!(Generator::new("abc")
+        .unwrap()
+        .max_len(2)
+        .sample(3, 0)
+        .is_empty());
+}
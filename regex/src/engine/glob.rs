@@ -0,0 +1,258 @@
+//! シェルのglobを正規表現の抽象構文木に変換する
+//!
+//! globはパス全体にマッチするかで判定し、`/`をパスの区切りとして扱う。
+//!
+//! - `*` : `/`以外の0文字以上
+//! - `?` : `/`以外の1文字
+//! - `[...]` : 文字クラス。`[!...]`と`[^...]`は否定。`/`にはマッチしない
+//! - `{a,b}` : いずれかの選択肢。選択肢の中にもglobを書ける
+//! - `**` : `/`を含む0文字以上。`**/`は0個以上のディレクトリ
+//! - `\` : 次の1文字をエスケープ
+//!
+//! 閉じていない`[`と`{`は通常の文字として扱う。
+//! .gitignoreと同様に`{a,b}`を展開しない場合は、[Glob::without_braces]を用いる。
+//!
+//! ```
+//! use regex::glob::Glob;
+//! let glob = Glob::new("src/**/*.{rs,toml}").unwrap();
+//! assert!(glob.is_match("src/main.rs"));
+//! assert!(glob.is_match("src/engine/parser.rs"));
+//! assert!(!glob.is_match("tests/main.rs"));
+//! ```
+use super::{class::Class, codegen, parser::AST, Error, Flags, Regex};
+
+/// globを抽象構文木に変換
+pub fn to_ast(glob: &str) -> AST {
+    to_ast_with(glob, true)
+}
+
+/// globを抽象構文木に変換。bracesがfalseの場合は`{`を通常の文字とする
+fn to_ast_with(glob: &str, braces: bool) -> AST {
+    let chars = glob.chars().collect::<Vec<char>>();
+    let mut i = 0;
+    AST::Seq(parse_seq(&chars, &mut i, braces, false))
+}
+
+/// 位置iからglobの列をパース。
+///
+/// in_braceがtrueの場合は`{...}`の選択肢として、`,`と`}`の手前で止まる。
+/// それ以外の場合は末尾まで読み、`,`と`}`も通常の文字とする。
+/// bracesがfalseの場合は、`{`も通常の文字とする。
+fn parse_seq(chars: &[char], i: &mut usize, braces: bool, in_brace: bool) -> Vec<AST> {
+    let mut seq = Vec::new();
+    while let Some(&c) = chars.get(*i) {
+        match c {
+            ',' | '}' if in_brace => break,
+            '*' if chars.get(*i + 1) == Some(&'*') => {
+                *i += 2;
+                let any = AST::Star(Box::new(AST::AnyChar(true)));
+                if chars.get(*i) == Some(&'/') {
+                    // `**/`は0個以上のディレクトリにマッチ
+                    *i += 1;
+                    let dirs = AST::Seq(vec![any, AST::Char('/')]);
+                    seq.push(AST::Question(Box::new(dirs)));
+                } else {
+                    seq.push(any);
+                }
+            }
+            '*' => {
+                *i += 1;
+                seq.push(AST::Star(Box::new(AST::Class(not_separator()))));
+            }
+            '?' => {
+                *i += 1;
+                seq.push(AST::Class(not_separator()));
+            }
+            '[' => match parse_bracket(chars, *i) {
+                Some((class, next)) => {
+                    *i = next;
+                    seq.push(AST::Class(class));
+                }
+                None => {
+                    *i += 1;
+                    seq.push(AST::Char('['));
+                }
+            },
+            '{' if braces => match parse_brace(chars, *i) {
+                Some((ast, next)) => {
+                    *i = next;
+                    seq.push(ast);
+                }
+                None => {
+                    *i += 1;
+                    seq.push(AST::Char('{'));
+                }
+            },
+            '\\' if *i + 1 < chars.len() => {
+                seq.push(AST::Char(chars[*i + 1]));
+                *i += 2;
+            }
+            _ => {
+                *i += 1;
+                seq.push(AST::Char(c));
+            }
+        }
+    }
+    seq
+}
+
+/// chars[start]から始まる`{...}`をパースし、選択肢の抽象構文木と`}`の次の位置を返す。
+/// 閉じていない場合はNoneを返す
+fn parse_brace(chars: &[char], start: usize) -> Option<(AST, usize)> {
+    let mut i = start + 1;
+    let mut alts = Vec::new();
+    loop {
+        alts.push(AST::Seq(parse_seq(chars, &mut i, true, true)));
+        if *chars.get(i)? == '}' {
+            break;
+        }
+        i += 1; // `,`
+    }
+    let ast = alts
+        .into_iter()
+        .reduce(|acc, alt| AST::Or(Box::new(acc), Box::new(alt)))?;
+    Some((ast, i + 1))
+}
+
+/// chars[start]から始まる`[...]`をパースし、文字クラスと`]`の次の位置を返す。
+///
+/// `]`は先頭の場合のみ通常の文字となり、`\`はエスケープとして扱わない。
+/// 閉じていない場合はNoneを返す。
+fn parse_bracket(chars: &[char], start: usize) -> Option<(Class, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let lo = *chars.get(i)?;
+        if lo == ']' && !first {
+            break;
+        }
+        first = false;
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|hi| *hi != ']') {
+            let hi = chars[i + 2];
+            // 逆順の範囲は何にもマッチしない
+            if lo <= hi {
+                ranges.push((lo, hi));
+            }
+            i += 3;
+        } else {
+            ranges.push((lo, lo));
+            i += 1;
+        }
+    }
+    let mut class = Class::new(ranges);
+    if negated {
+        class.negate();
+    }
+    class.intersect(&not_separator());
+    Some((class, i + 1))
+}
+
+/// `/`以外の全ての文字
+fn not_separator() -> Class {
+    let mut class = Class::from_char('/');
+    class.negate();
+    class
+}
+
+/// コンパイル済みのglob
+#[derive(Debug)]
+pub struct Glob {
+    glob: String,
+    regex: Regex,
+}
+
+impl Glob {
+    /// globを抽象構文木に変換し、正規表現と同じ命令列にコンパイル
+    pub fn new(glob: &str) -> Result<Self, Error> {
+        Self::compile(glob, true)
+    }
+
+    /// `{a,b}`を展開せず、`{`と`}`を通常の文字として扱うglobをコンパイル。
+    ///
+    /// .gitignoreのパターンはこちらを用いる。
+    pub fn without_braces(glob: &str) -> Result<Self, Error> {
+        Self::compile(glob, false)
+    }
+
+    fn compile(glob: &str, braces: bool) -> Result<Self, Error> {
+        let ast = to_ast_with(glob, braces);
+        let code = codegen::get_code(&ast)?;
+        let regex = Regex {
+            code,
+            is_depth: false,
+            flags: Flags::default(),
+            names: vec![None],
+            jit: None,
+            cache: Default::default(),
+        };
+        Ok(Glob {
+            glob: glob.to_string(),
+            regex,
+        })
+    }
+
+    /// 元のglob
+    pub fn as_str(&self) -> &str {
+        &self.glob
+    }
+
+    /// パス全体がglobにマッチするか判定
+    pub fn is_match(&self, path: &str) -> bool {
+        // 最長のマッチがパス全体となる場合のみマッチ
+        matches!(self.regex.match_at(path, 0), Ok(Some(end)) if end == path.len())
+    }
+}
+
+#[test]
+fn test_glob() {
+    let is_match = |glob: &str, path: &str| Glob::new(glob).unwrap().is_match(path);
+    assert!(is_match("*.rs", "main.rs"));
+    assert!(!is_match("*.rs", "src/main.rs"));
+    assert!(is_match("src/*.rs", "src/main.rs"));
+    assert!(is_match("src/**/*.rs", "src/main.rs"));
+    assert!(is_match("src/**/*.rs", "src/engine/parser.rs"));
+    assert!(!is_match("src/**/*.rs", "src/engine/parser.rs.bak"));
+    assert!(is_match("**/target", "a/b/target"));
+    assert!(is_match("**/target", "target"));
+    assert!(is_match("**", "a/b/c"));
+    assert!(is_match("ma?n.r[a-s]", "main.rs"));
+    assert!(!is_match("ma?n.r[!a-s]", "main.rs"));
+    assert!(!is_match("a[!x]b", "a/b"));
+    assert!(is_match("[]]x", "]x"));
+    assert!(is_match("[x", "[x"));
+    assert!(!is_match("[z-a]", "m"));
+    assert!(is_match("\\*", "*"));
+    assert!(!is_match("\\*", "a"));
+    assert!(!is_match("a?b", "a/b"));
+
+    assert!(is_match("*.{rs,toml}", "Cargo.toml"));
+    assert!(is_match("*.{rs,toml}", "lib.rs"));
+    assert!(!is_match("*.{rs,toml}", "lib.c"));
+    assert!(is_match("{src,tests}/**/*.rs", "tests/a/b.rs"));
+    assert!(is_match("a{b,{c,d}e}", "ade"));
+    assert!(is_match("a{,x}", "a"));
+    assert!(is_match("{a,b", "{a,b"));
+    assert!(is_match("a,b}", "a,b}"));
+    assert!(is_match("{\\,,x}", ","));
+    assert!(!is_match("{a,b}", "a,b"));
+
+    let glob = Glob::without_braces("*.{log,tmp}").unwrap();
+    assert!(glob.is_match("x.{log,tmp}"));
+    assert!(!glob.is_match("x.tmp"));
+
+    assert_eq!(
+        to_ast("a{b,c}"),
+        AST::Seq(vec![
+            AST::Char('a'),
+            AST::Or(
+                Box::new(AST::Seq(vec![AST::Char('b')])),
+                Box::new(AST::Seq(vec![AST::Char('c')]))
+            )
+        ])
+    );
+}
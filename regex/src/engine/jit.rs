@@ -0,0 +1,418 @@
+//! 命令列から構成したDFAを、x86-64の機械語に変換して実行する（JITコンパイル）
+//!
+//! フィーチャ`jit`が有効で、Linux/x86-64の場合のみ機械語を生成する。
+//! それ以外の環境では[Jit::new]が常にNoneを返し、インタプリタで評価する。
+//!
+//! 生成するコードはDFAの状態ごとのラベルと、文字の範囲の比較と分岐からなる。
+//! 入力がcharの配列の場合と、UTF-8の文字列の場合の2つの関数を生成し、
+//! UTF-8の場合は1文字を読み込むサブルーチンでデコードする。
+//! 引数と返り値はSystem V ABIに従い、以下のレジスタを用いる。
+//!
+//! ```text
+//! rdi : 入力の先頭（charの配列、またはUTF-8のバイト列）
+//! rsi : 入力の長さ（文字数、またはバイト数）
+//! rdx : 現在の位置
+//! rcx : 初期状態の種類（直前の文字の種類。[Prev::ALL](super::dfa::Prev::ALL)の順の番号）
+//! rax : 最長のマッチの終了位置（マッチしない場合は-1）
+//! r8d : 現在の文字
+//! r9d : 範囲の比較用、UTF-8のデコード用
+//! r10 : 次の文字の位置（UTF-8の場合）
+//! r11d : UTF-8のデコード用
+//! ```
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub use native::Jit;
+
+#[cfg(not(all(feature = "jit", target_arch = "x86_64", target_os = "linux")))]
+pub use fallback::Jit;
+
+#[cfg(not(all(feature = "jit", target_arch = "x86_64", target_os = "linux")))]
+mod fallback {
+    use super::super::Instruction;
+
+    /// JITコンパイルに対応しない環境での代替。生成されることはない
+    #[derive(Debug)]
+    pub enum Jit {}
+
+    impl Jit {
+        pub fn new(_insts: &[Instruction]) -> Option<Self> {
+            None
+        }
+
+        pub fn longest_match(&self, _line: &[char], _start: usize) -> Option<usize> {
+            match *self {}
+        }
+
+        pub fn longest_match_str(&self, _line: &str, _start: usize) -> Option<usize> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+mod native {
+    use super::super::{
+        dfa::{Dfa, Prev, State, Target},
+        Instruction,
+    };
+    use std::{fmt, ptr};
+
+    /// 生成した関数の型
+    type MatchFn = unsafe extern "sysv64" fn(*const u8, usize, usize, usize) -> isize;
+
+    /// 実行可能なページに配置した機械語
+    pub struct Jit {
+        mem: *mut libc::c_void, // mmapで確保した領域
+        len: usize,             // 領域の大きさ
+        func: MatchFn,          // charの配列を入力とする関数
+        func_str: MatchFn,      // UTF-8の文字列を入力とする関数
+    }
+
+    // 領域は生成後に読み込みと実行のみ可能とし、変更しないため、スレッド間で共有できる
+    unsafe impl Send for Jit {}
+    unsafe impl Sync for Jit {}
+
+    impl fmt::Debug for Jit {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Jit {{ len: {} }}", self.len)
+        }
+    }
+
+    impl Drop for Jit {
+        fn drop(&mut self) {
+            unsafe {
+                libc::munmap(self.mem, self.len);
+            }
+        }
+    }
+
+    impl Jit {
+        /// 命令列をDFAに変換し、機械語を生成する。
+        ///
+        /// DFAで表現できない場合（状態数が多すぎる場合やアトミックグループを含む場合）や、
+        /// 領域の確保に失敗した場合はNoneを返す。
+        pub fn new(insts: &[Instruction]) -> Option<Self> {
+            let dfa = Dfa::new(insts).ok()?;
+            let (code, str_entry) = compile(&dfa);
+            unsafe {
+                let len = code.len();
+                let mem = libc::mmap(
+                    ptr::null_mut(),
+                    len,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                );
+                if mem == libc::MAP_FAILED {
+                    return None;
+                }
+                ptr::copy_nonoverlapping(code.as_ptr(), mem as *mut u8, len);
+                if libc::mprotect(mem, len, libc::PROT_READ | libc::PROT_EXEC) != 0 {
+                    libc::munmap(mem, len);
+                    return None;
+                }
+                let func = std::mem::transmute::<*mut libc::c_void, MatchFn>(mem);
+                let func_str =
+                    std::mem::transmute::<*mut u8, MatchFn>((mem as *mut u8).add(str_entry));
+                Some(Jit {
+                    mem,
+                    len,
+                    func,
+                    func_str,
+                })
+            }
+        }
+
+        /// 位置startからマッチさせ、最長のマッチの終了位置を返す
+        pub fn longest_match(&self, line: &[char], start: usize) -> Option<usize> {
+            let prev = Prev::at(line, start) as usize;
+            // 生成したコードはline[..line.len()]の範囲のみ読み込む
+            let ptr = line.as_ptr() as *const u8;
+            let end = unsafe { (self.func)(ptr, line.len(), start, prev) };
+            usize::try_from(end).ok()
+        }
+
+        /// UTF-8の文字列lineのバイト位置startからマッチさせ、最長のマッチの終了位置を返す。
+        ///
+        /// startが文字の境界でない場合はNoneを返す。
+        pub fn longest_match_str(&self, line: &str, start: usize) -> Option<usize> {
+            if start <= line.len() && !line.is_char_boundary(start) {
+                return None;
+            }
+            let prev = Prev::at(line, start) as usize;
+            // 生成したコードはline[..line.len()]の範囲のみ、文字の境界から読み込む
+            let end = unsafe { (self.func_str)(line.as_ptr(), line.len(), start, prev) };
+            usize::try_from(end).ok()
+        }
+    }
+
+    /// ラベルとジャンプ先の解決を行う、簡単なアセンブラ
+    #[derive(Default)]
+    struct Asm {
+        code: Vec<u8>,
+        labels: Vec<Option<usize>>,  // ラベルの位置
+        fixups: Vec<(usize, usize)>, // rel32を書き込む位置と、ラベル
+    }
+
+    impl Asm {
+        fn label(&mut self) -> usize {
+            self.labels.push(None);
+            self.labels.len() - 1
+        }
+
+        fn bind(&mut self, label: usize) {
+            self.labels[label] = Some(self.code.len());
+        }
+
+        fn emit(&mut self, bytes: &[u8]) {
+            self.code.extend(bytes);
+        }
+
+        fn imm32(&mut self, n: u32) {
+            self.code.extend(n.to_le_bytes());
+        }
+
+        /// ジャンプ命令（opcodeの後にrel32が続くもの）
+        fn jump(&mut self, opcode: &[u8], label: usize) {
+            self.emit(opcode);
+            self.fixups.push((self.code.len(), label));
+            self.imm32(0);
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            for (pos, label) in self.fixups {
+                let target = self.labels[label].unwrap() as i64;
+                let rel = (target - (pos as i64 + 4)) as i32;
+                self.code[pos..pos + 4].copy_from_slice(&rel.to_le_bytes());
+            }
+            self.code
+        }
+    }
+
+    const JE: &[u8] = &[0x0f, 0x84];
+    const JNE: &[u8] = &[0x0f, 0x85];
+    const JB: &[u8] = &[0x0f, 0x82];
+    const JAE: &[u8] = &[0x0f, 0x83];
+    const JBE: &[u8] = &[0x0f, 0x86];
+    const JMP: &[u8] = &[0xe9];
+    const CALL: &[u8] = &[0xe8];
+    const RET: &[u8] = &[0xc3];
+    const MOV_RAX_RDX: &[u8] = &[0x48, 0x89, 0xd0];
+
+    /// 入力の形式
+    #[derive(Clone, Copy)]
+    enum Unit {
+        Char,        // charの配列
+        Utf8(usize), // UTF-8のバイト列。値は1文字を読み込むサブルーチンのラベル
+    }
+
+    /// DFAを機械語に変換し、UTF-8の文字列を入力とする関数の先頭の位置とともに返す
+    fn compile(dfa: &Dfa) -> (Vec<u8>, usize) {
+        let mut asm = Asm::default();
+        let decode = asm.label();
+        compile_fn(&mut asm, dfa, Unit::Char);
+        let str_entry = asm.code.len();
+        compile_fn(&mut asm, dfa, Unit::Utf8(decode));
+        asm.bind(decode);
+        compile_decode(&mut asm);
+        (asm.finish(), str_entry)
+    }
+
+    /// 入力の形式がunitの場合の関数を生成
+    fn compile_fn(asm: &mut Asm, dfa: &Dfa, unit: Unit) {
+        let labels = dfa.states.iter().map(|_| asm.label()).collect::<Vec<_>>();
+
+        // mov rax, -1
+        asm.emit(&[0x48, 0xc7, 0xc0]);
+        asm.imm32(u32::MAX);
+        // 初期状態へ分岐
+        let (last, starts) = dfa.start.split_last().unwrap();
+        for (kind, start) in starts.iter().enumerate() {
+            asm.emit(&[0x83, 0xf9, kind as u8]); // cmp ecx, kind
+            asm.jump(JE, labels[*start]);
+        }
+        asm.jump(JMP, labels[*last]);
+
+        for (i, state) in dfa.states.iter().enumerate() {
+            asm.bind(labels[i]);
+            compile_state(asm, dfa, state, &labels, unit);
+        }
+    }
+
+    /// UTF-8の1文字を読み込むサブルーチンを生成。
+    ///
+    /// rdxの位置の文字をr8dに、次の文字の位置をr10に設定する。
+    /// 入力は正しいUTF-8で、rdxは文字の境界かつrsi未満であること。
+    fn compile_decode(asm: &mut Asm) {
+        let (done, two, three, four, cont) = (
+            asm.label(),
+            asm.label(),
+            asm.label(),
+            asm.label(),
+            asm.label(),
+        );
+        asm.emit(&[0x44, 0x0f, 0xb6, 0x04, 0x17]); // movzx r8d, byte [rdi + rdx]
+        asm.emit(&[0x4c, 0x8d, 0x52, 0x01]); // lea r10, [rdx + 1]
+        asm.emit(&[0x41, 0x81, 0xf8]); // cmp r8d, 0x80
+        asm.imm32(0x80);
+        asm.jump(JB, done);
+
+        // 先頭のバイトから、バイト数を表すビットを取り除く
+        asm.emit(&[0x41, 0x81, 0xf8]); // cmp r8d, 0xe0
+        asm.imm32(0xe0);
+        asm.jump(JB, two);
+        asm.emit(&[0x41, 0x81, 0xf8]); // cmp r8d, 0xf0
+        asm.imm32(0xf0);
+        asm.jump(JB, three);
+        asm.jump(JMP, four);
+        for (label, mask) in [(two, 0x1f), (three, 0x0f), (four, 0x07)] {
+            asm.bind(label);
+            asm.emit(&[0x41, 0x81, 0xe0]); // and r8d, mask
+            asm.imm32(mask);
+            asm.jump(JMP, cont);
+        }
+
+        // 継続バイトの下位6ビットを順に追加
+        asm.bind(cont);
+        asm.emit(&[0x49, 0x39, 0xf2]); // cmp r10, rsi
+        asm.jump(JAE, done);
+        asm.emit(&[0x46, 0x0f, 0xb6, 0x0c, 0x17]); // movzx r9d, byte [rdi + r10]
+        asm.emit(&[0x45, 0x89, 0xcb]); // mov r11d, r9d
+        asm.emit(&[0x41, 0x81, 0xe3]); // and r11d, 0xc0
+        asm.imm32(0xc0);
+        asm.emit(&[0x41, 0x81, 0xfb]); // cmp r11d, 0x80
+        asm.imm32(0x80);
+        asm.jump(JNE, done);
+        asm.emit(&[0x41, 0x81, 0xe1]); // and r9d, 0x3f
+        asm.imm32(0x3f);
+        asm.emit(&[0x41, 0xc1, 0xe0, 0x06]); // shl r8d, 6
+        asm.emit(&[0x45, 0x09, 0xc8]); // or r8d, r9d
+        asm.emit(&[0x49, 0xff, 0xc2]); // inc r10
+        asm.jump(JMP, cont);
+
+        asm.bind(done);
+        asm.emit(RET);
+    }
+
+    /// 1つの状態のコードを生成
+    fn compile_state(asm: &mut Asm, dfa: &Dfa, state: &State, labels: &[usize], unit: Unit) {
+        let end = asm.label();
+        asm.emit(&[0x48, 0x39, 0xf2]); // cmp rdx, rsi
+        asm.jump(JAE, end);
+        match unit {
+            Unit::Char => asm.emit(&[0x44, 0x8b, 0x04, 0x97]), // mov r8d, [rdi + rdx * 4]
+            Unit::Utf8(decode) => asm.jump(CALL, decode),
+        }
+
+        // 遷移ごとに文字の範囲をまとめ、最も多くの範囲で共通する遷移は比較せずに実行する
+        let mut arms = state.arms(&dfa.alphabet);
+        arms.sort_by_key(|(_, ranges)| ranges.len());
+        let arm_labels = arms.iter().map(|_| asm.label()).collect::<Vec<_>>();
+        let (default, rest) = arms.split_last().unwrap();
+        for ((_, ranges), label) in rest.iter().zip(&arm_labels) {
+            for (lo, hi) in ranges {
+                if lo == hi {
+                    asm.emit(&[0x41, 0x81, 0xf8]); // cmp r8d, lo
+                    asm.imm32(*lo as u32);
+                    asm.jump(JE, *label);
+                } else {
+                    asm.emit(&[0x45, 0x89, 0xc1]); // mov r9d, r8d
+                    asm.emit(&[0x41, 0x81, 0xe9]); // sub r9d, lo
+                    asm.imm32(*lo as u32);
+                    asm.emit(&[0x41, 0x81, 0xf9]); // cmp r9d, hi - lo
+                    asm.imm32(*hi as u32 - *lo as u32);
+                    asm.jump(JBE, *label);
+                }
+            }
+        }
+        compile_target(asm, default.0, labels, unit);
+        for ((target, _), label) in rest.iter().zip(&arm_labels) {
+            asm.bind(*label);
+            compile_target(asm, *target, labels, unit);
+        }
+
+        // 入力の終わり
+        asm.bind(end);
+        if state.accept_end {
+            asm.emit(MOV_RAX_RDX);
+        }
+        asm.emit(RET);
+    }
+
+    /// 1つの遷移のコードを生成
+    fn compile_target(asm: &mut Asm, (accept, next): Target, labels: &[usize], unit: Unit) {
+        if accept {
+            asm.emit(MOV_RAX_RDX);
+        }
+        match next {
+            Some(next) => {
+                match unit {
+                    Unit::Char => asm.emit(&[0x48, 0xff, 0xc2]), // inc rdx
+                    Unit::Utf8(_) => asm.emit(&[0x4c, 0x89, 0xd2]), // mov rdx, r10
+                }
+                asm.jump(JMP, labels[next]);
+            }
+            None => asm.emit(RET),
+        }
+    }
+
+    #[test]
+    fn test() {
+        use super::super::{
+            codegen,
+            evaluator::{self, Cache},
+            parser,
+        };
+
+        let exprs = [
+            "a(bc)+|c(def)*",
+            "(?i)[a-c]x?[^b]",
+            "(a*)*b?",
+            "^a|b$",
+            "(?m)^b\n?$|.c",
+            "(?s)a.+c",
+            "x?",
+            "[α-ω]+\u{1f600}",
+            "\\bb|c\\B|(?-u:\\bγ)",
+        ];
+        let inputs = [
+            "",
+            "a",
+            "abc",
+            "bcbc",
+            "cdefdef",
+            "aab",
+            "b\nb",
+            "ab\nbc",
+            "Ax\nc",
+            "βγ\u{1f600}",
+            "b_c γb",
+            "xあc",
+        ];
+        for expr in exprs {
+            let code = codegen::get_code(&parser::parse(expr).unwrap()).unwrap();
+            let jit = Jit::new(&code).unwrap();
+            let mut cache = Cache::default();
+            for input in inputs {
+                let line = input.chars().collect::<Vec<char>>();
+                for start in 0..=line.len() + 1 {
+                    let expected = evaluator::eval(&code, &line, start, true, &mut cache).unwrap();
+                    assert_eq!(
+                        jit.longest_match(&line, start),
+                        expected,
+                        "{expr} {input:?}"
+                    );
+                }
+                // UTF-8の文字列でも、バイト位置で同じ結果となる
+                for (start, _) in input.char_indices().chain([(input.len(), ' ')]) {
+                    let expected = evaluator::eval(&code, input, start, true, &mut cache).unwrap();
+                    assert_eq!(
+                        jit.longest_match_str(input, start),
+                        expected,
+                        "{expr} {input:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! 正規表現が表す言語（文字列の集合）の演算と比較
+//!
+//! 正規表現の言語は、その正規表現が全体にマッチする文字列の集合とする。
+//! たとえば`[0-9]+`の言語は1文字以上の数字からなる文字列全体であり、`12a`は含まない。
+//!
+//! 言語は最小化した完全なDFAで表し、積（`&`）、和（`|`）、補集合（`!`）をとれる。
+//! 等価性と包含関係は差集合が空かどうかで判定し、成り立たない場合は最短の反例を返す。
+//! アトミックグループと強欲な量指定子はDFAで表せないため、これらを含む正規表現はエラーとする。
+//!
+//! ```
+//! use regex::lang::{self, Relation};
+//! // リファクタリング前後のパターンが同じ文字列を受理するか確認する
+//! assert_eq!(lang::equivalent("(a|b)*", "(a*b*)*").unwrap(), Relation::Holds);
+//! assert_eq!(
+//!     lang::equivalent("[0-9]+", "[0-9]*").unwrap(),
+//!     Relation::Counterexample(String::new())
+//! );
+//! ```
+use super::{
+    class::prev_char,
+    codegen,
+    dfa::{Dfa, Prev},
+    Error, Flags,
+};
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    ops::{BitAnd, BitOr, Not},
+};
+
+/// 関係の判定結果
+#[derive(Debug, PartialEq, Clone)]
+pub enum Relation {
+    Holds,                  // 成り立つ
+    Counterexample(String), // 成り立たない。値は反例となる最短の文字列
+}
+
+impl Relation {
+    /// 成り立つか
+    pub fn holds(&self) -> bool {
+        *self == Relation::Holds
+    }
+}
+
+/// 正規表現aとbが等価（同じ言語を表す）か判定する。
+///
+/// 等価でない場合は、一方のみに含まれる最短の文字列を反例とする。
+pub fn equivalent(a: &str, b: &str) -> Result<Relation, Error> {
+    Ok(Language::new(a)?.equivalent(&Language::new(b)?))
+}
+
+/// 正規表現aの言語がbの言語に含まれるか判定する。
+///
+/// 含まれない場合は、aのみに含まれる最短の文字列を反例とする。
+pub fn subset(a: &str, b: &str) -> Result<Relation, Error> {
+    Ok(Language::new(a)?.subset(&Language::new(b)?))
+}
+
+/// 正規表現の言語
+///
+/// 全ての文字について遷移を持つ（完全な）DFAを、最小化して保持する。
+/// 遷移のない状態への遷移は、どの文字でも自身に遷移する受理しない状態（死状態）への遷移とする。
+#[derive(Debug, Clone)]
+pub struct Language {
+    alphabet: Vec<(char, char)>, // 文字の範囲の一覧。'\0'から隙間なく並ぶ
+    next: Vec<Vec<usize>>,       // next[状態][範囲]が遷移先
+    accept: Vec<bool>,           // 受理状態か
+    start: usize,
+}
+
+impl Language {
+    /// 正規表現の言語を求める
+    pub fn new(expr: &str) -> Result<Self, Error> {
+        Self::with_flags(expr, Flags::default())
+    }
+
+    /// フラグの初期値を指定して、正規表現の言語を求める
+    pub fn with_flags(expr: &str, flags: Flags) -> Result<Self, Error> {
+        let ast = super::parse(expr, flags)?;
+        let code = codegen::get_code(&ast)?;
+        let dfa = Dfa::new(&code)?;
+        Ok(Self::from_dfa(&dfa))
+    }
+
+    /// 全ての文字列からなる言語
+    pub fn all() -> Self {
+        Language {
+            alphabet: vec![('\0', char::MAX)],
+            next: vec![vec![0]],
+            accept: vec![true],
+            start: 0,
+        }
+    }
+
+    /// 空の言語
+    pub fn empty() -> Self {
+        !Self::all()
+    }
+
+    /// マッチングに用いるDFAから、文字列全体にマッチするかを判定するDFAを構成
+    fn from_dfa(dfa: &Dfa) -> Self {
+        let dead = dfa.states.len();
+        let mut next = dfa
+            .states
+            .iter()
+            .map(|s| s.next.iter().map(|n| n.unwrap_or(dead)).collect())
+            .collect::<Vec<Vec<usize>>>();
+        next.push(vec![dead; dfa.alphabet.len()]);
+        let mut accept = dfa.states.iter().map(|s| s.accept_end).collect::<Vec<_>>();
+        accept.push(false);
+        Language {
+            alphabet: dfa.alphabet.clone(),
+            next,
+            accept,
+            start: dfa.start[Prev::Start as usize],
+        }
+        .minimize()
+    }
+
+    /// 最小化したDFAの状態数（死状態を含む）
+    pub fn states(&self) -> usize {
+        self.next.len()
+    }
+
+    /// 文字列sを含むか
+    pub fn contains(&self, s: &str) -> bool {
+        let state = s.chars().fold(self.start, |state, c| {
+            self.next[state][class_of(&self.alphabet, c)]
+        });
+        self.accept[state]
+    }
+
+    /// 空集合か
+    pub fn is_empty(&self) -> bool {
+        self.shortest().is_none()
+    }
+
+    /// 含まれる最短の文字列。空集合の場合はNone
+    ///
+    /// 同じ長さの文字列が複数ある場合は、各文字に英数字などの読みやすい文字を優先して用いる。
+    pub fn shortest(&self) -> Option<String> {
+        let mut parent: Vec<Option<(usize, usize)>> = vec![None; self.states()];
+        let mut visited = vec![false; self.states()];
+        let mut queue = VecDeque::from([self.start]);
+        visited[self.start] = true;
+        while let Some(state) = queue.pop_front() {
+            if self.accept[state] {
+                let mut chars = Vec::new();
+                let mut s = state;
+                while let Some((prev, class)) = parent[s] {
+                    let (lo, hi) = self.alphabet[class];
+                    chars.push(representative(lo, hi));
+                    s = prev;
+                }
+                return Some(chars.into_iter().rev().collect());
+            }
+            for (class, next) in self.next[state].iter().enumerate() {
+                if !visited[*next] {
+                    visited[*next] = true;
+                    parent[*next] = Some((state, class));
+                    queue.push_back(*next);
+                }
+            }
+        }
+        None
+    }
+
+    /// otherと等価か判定
+    pub fn equivalent(&self, other: &Language) -> Relation {
+        let diff = self.product(other, |a, b| a != b);
+        relation(&diff)
+    }
+
+    /// otherに含まれるか判定
+    pub fn subset(&self, other: &Language) -> Relation {
+        relation(&(self & &!other))
+    }
+
+    /// 2つの言語の直積をとり、受理状態をfで決めたDFAを構成
+    fn product(&self, other: &Language, f: impl Fn(bool, bool) -> bool) -> Language {
+        // 両方の範囲の境界で分割し直す
+        let starts = self
+            .alphabet
+            .iter()
+            .chain(&other.alphabet)
+            .map(|(lo, _)| *lo)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        let alphabet = starts
+            .iter()
+            .enumerate()
+            .map(|(i, lo)| (*lo, starts.get(i + 1).map_or(char::MAX, |n| prev_char(*n))))
+            .collect::<Vec<_>>();
+        let classes = alphabet
+            .iter()
+            .map(|(lo, _)| {
+                (
+                    class_of(&self.alphabet, *lo),
+                    class_of(&other.alphabet, *lo),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut ids = HashMap::from([((self.start, other.start), 0)]);
+        let mut pairs = vec![(self.start, other.start)];
+        let mut next = Vec::new();
+        let mut accept = Vec::new();
+        while let Some(&(a, b)) = pairs.get(next.len()) {
+            let mut row = Vec::with_capacity(alphabet.len());
+            for (ca, cb) in &classes {
+                let pair = (self.next[a][*ca], other.next[b][*cb]);
+                let id = *ids.entry(pair).or_insert_with(|| {
+                    pairs.push(pair);
+                    pairs.len() - 1
+                });
+                row.push(id);
+            }
+            next.push(row);
+            accept.push(f(self.accept[a], other.accept[b]));
+        }
+        Language {
+            alphabet,
+            next,
+            accept,
+            start: 0,
+        }
+        .minimize()
+    }
+
+    /// 到達可能な状態のみを残し、等価な状態をまとめて最小化する（Mooreのアルゴリズム）
+    fn minimize(&self) -> Language {
+        // 到達可能な状態
+        let mut reachable = vec![self.start];
+        let mut seen = vec![false; self.states()];
+        seen[self.start] = true;
+        let mut i = 0;
+        while let Some(&s) = reachable.get(i) {
+            for n in &self.next[s] {
+                if !seen[*n] {
+                    seen[*n] = true;
+                    reachable.push(*n);
+                }
+            }
+            i += 1;
+        }
+
+        // 受理するかで分割し、遷移先のブロックが異なる状態を分割できなくなるまで繰り返す
+        let mut block = vec![0; self.states()];
+        for s in &reachable {
+            block[*s] = self.accept[*s] as usize;
+        }
+        let mut count = 0;
+        loop {
+            let mut ids: HashMap<(usize, Vec<usize>), usize> = HashMap::new();
+            let mut new_block = vec![0; self.states()];
+            for s in &reachable {
+                let key = (block[*s], self.next[*s].iter().map(|n| block[*n]).collect());
+                let len = ids.len();
+                new_block[*s] = *ids.entry(key).or_insert(len);
+            }
+            block = new_block;
+            if ids.len() == count {
+                break;
+            }
+            count = ids.len();
+        }
+
+        // ブロックを状態とし、同じ遷移となる隣接した範囲をまとめる
+        let mut next = vec![Vec::new(); count];
+        let mut accept = vec![false; count];
+        for s in &reachable {
+            next[block[*s]] = self.next[*s].iter().map(|n| block[*n]).collect();
+            accept[block[*s]] = self.accept[*s];
+        }
+        let mut alphabet: Vec<(char, char)> = Vec::new();
+        let mut columns: Vec<Vec<usize>> = Vec::new();
+        for (i, range) in self.alphabet.iter().enumerate() {
+            let column = next.iter().map(|row| row[i]).collect::<Vec<_>>();
+            match (alphabet.last_mut(), columns.last()) {
+                (Some(last), Some(c)) if *c == column => last.1 = range.1,
+                _ => {
+                    alphabet.push(*range);
+                    columns.push(column);
+                }
+            }
+        }
+        let next = (0..count)
+            .map(|s| columns.iter().map(|c| c[s]).collect())
+            .collect();
+        Language {
+            alphabet,
+            next,
+            accept,
+            start: block[self.start],
+        }
+    }
+}
+
+/// 積集合
+impl BitAnd for &Language {
+    type Output = Language;
+
+    fn bitand(self, rhs: Self) -> Language {
+        self.product(rhs, |a, b| a && b)
+    }
+}
+
+impl BitAnd for Language {
+    type Output = Language;
+
+    fn bitand(self, rhs: Self) -> Language {
+        &self & &rhs
+    }
+}
+
+/// 和集合
+impl BitOr for &Language {
+    type Output = Language;
+
+    fn bitor(self, rhs: Self) -> Language {
+        self.product(rhs, |a, b| a || b)
+    }
+}
+
+impl BitOr for Language {
+    type Output = Language;
+
+    fn bitor(self, rhs: Self) -> Language {
+        &self | &rhs
+    }
+}
+
+/// 補集合
+impl Not for &Language {
+    type Output = Language;
+
+    fn not(self) -> Language {
+        Language {
+            accept: self.accept.iter().map(|a| !a).collect(),
+            ..self.clone()
+        }
+    }
+}
+
+impl Not for Language {
+    type Output = Language;
+
+    fn not(self) -> Language {
+        !&self
+    }
+}
+
+/// 差集合が空なら成り立ち、空でなければその最短の文字列を反例とする
+fn relation(diff: &Language) -> Relation {
+    match diff.shortest() {
+        Some(s) => Relation::Counterexample(s),
+        None => Relation::Holds,
+    }
+}
+
+/// 文字cを含む範囲の添字
+fn class_of(alphabet: &[(char, char)], c: char) -> usize {
+    alphabet.partition_point(|(lo, _)| *lo <= c) - 1
+}
+
+/// 範囲を代表する文字。英数字、ASCIIの表示可能な文字の順に優先し、なければ先頭の文字とする
+fn representative(lo: char, hi: char) -> char {
+    ('a'..='z')
+        .chain('A'..='Z')
+        .chain('0'..='9')
+        .chain(' '..='~')
+        .find(|c| (lo..=hi).contains(c))
+        .unwrap_or(lo)
+}
+
+#[test]
+fn test() {
+    let holds = |a, b| equivalent(a, b).unwrap().holds();
+    assert!(holds("(a|b)*", "(a*b*)*"));
+    assert!(holds("a+", "aa*"));
+    assert!(holds("^abc$", "abc"));
+    assert!(holds("(?i)ab", "[aA][bB]"));
+    assert!(holds("[0-9]+(\\.[0-9]+)?", "[0-9]+|[0-9]+\\.[0-9]+"));
+    assert!(holds("a$b", "x^")); // どちらも空
+    assert!(holds("(?s).", "[^z]|z"));
+    assert!(holds("(?m)a$\n^b", "a\nb"));
+
+    assert_eq!(
+        equivalent("[0-9]+", "[0-9]*").unwrap(),
+        Relation::Counterexample(String::new())
+    );
+    assert_eq!(
+        equivalent("[a-z]+@[a-z]+", "[a-z]+@[a-z]+(\\.[a-z]+)?").unwrap(),
+        Relation::Counterexample("a@a.a".to_string())
+    );
+    assert_eq!(
+        equivalent(".", "(?s).").unwrap(),
+        Relation::Counterexample("\n".to_string())
+    );
+
+    assert!(subset("a+", "a*").unwrap().holds());
+    assert_eq!(
+        subset("a*", "a+").unwrap(),
+        Relation::Counterexample(String::new())
+    );
+    assert_eq!(
+        subset("ab|cd", "a.").unwrap(),
+        Relation::Counterexample("cd".to_string())
+    );
+
+    // 積、和、補集合
+    let a = Language::new("[a-z]+").unwrap();
+    let b = Language::new("[a-z]*").unwrap();
+    assert!((&a & &!&b).is_empty());
+    assert_eq!((&b & &!&a).shortest(), Some(String::new()));
+    let ab = Language::new("a[0-9]").unwrap() | Language::new("b").unwrap();
+    assert!(ab.equivalent(&Language::new("a[0-9]|b").unwrap()).holds());
+    assert!(ab.contains("a5") && ab.contains("b") && !ab.contains("a"));
+    assert!((!Language::empty()).equivalent(&Language::all()).holds());
+    assert!(Language::new("x^").unwrap().is_empty());
+
+    // 最小化により、等価な正規表現は同じ状態数となる
+    let x = Language::new("(a|b)*abb").unwrap();
+    let y = Language::new("(a*b*)*a(b|bbbb*b)bb*b*").unwrap();
+    assert_eq!(x.states(), Language::new("[ab]*abb").unwrap().states());
+    assert!(!x.equivalent(&y).holds());
+
+    // アトミックグループはDFAで表せない
+    assert!(Language::new("(?>a|ab)c").is_err());
+}
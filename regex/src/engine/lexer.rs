@@ -0,0 +1,298 @@
+//! 正規表現の規則から字句解析器を構成する
+//!
+//! トークンの種類とパターンの組（規則）を優先度の順に並べ、1つの命令列にまとめる。
+//! i番目の規則のパターンの後には番号iのmatch命令を置き、マッチした規則を判別する。
+//!
+//! 各位置では全ての規則のうち最長のマッチをトークンとし、同じ長さの場合は先に並ぶ規則を優先する。
+//! どの規則にもマッチしない位置（空文字列のみにマッチする場合を含む）はエラーとする。
+//!
+//! トークンの範囲の単位は入力の型による。
+//! `&str`の場合はバイト単位、`&[char]`の場合は文字単位となる。
+//!
+//! ```
+//! use regex::lexer::{Lexer, Token};
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Kind {
+//!     If,
+//!     Ident,
+//!     Number,
+//!     Space,
+//! }
+//!
+//! let lexer = Lexer::builder()
+//!     .rule(Kind::If, "if")
+//!     .rule(Kind::Ident, "[a-z][a-z0-9]*")
+//!     .rule(Kind::Number, "[0-9]+")
+//!     .rule(Kind::Space, " +")
+//!     .build()
+//!     .unwrap();
+//! let tokens = lexer.tokenize("if ifx 42").unwrap();
+//! assert_eq!(
+//!     tokens,
+//!     vec![
+//!         Token { kind: Kind::If, span: 0..2 },
+//!         Token { kind: Kind::Space, span: 2..3 },
+//!         Token { kind: Kind::Ident, span: 3..6 },
+//!         Token { kind: Kind::Space, span: 6..7 },
+//!         Token { kind: Kind::Number, span: 7..9 },
+//!     ]
+//! );
+//! assert!(lexer.tokenize("x = 1").is_err());
+//! ```
+use super::{
+    codegen,
+    evaluator::{self, Cache, Haystack},
+    Error as RegexError, Flags, Instruction,
+};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    ops::Range,
+};
+
+/// 字句解析のエラーを表す型
+#[derive(Debug)]
+pub enum LexError {
+    NoRules,                             // 規則が1つもない
+    InvalidRule(usize, Box<RegexError>), // i番目の規則のパターンが不正。値は番号とエラー
+    NoMatch(usize),                      // どの規則にもマッチしない。値は入力中の位置
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::NoRules => write!(f, "LexError: no rules"),
+            LexError::InvalidRule(i, e) => write!(f, "LexError: invalid rule {i}: {e}"),
+            LexError::NoMatch(pos) => write!(f, "LexError: no rule matches at {pos}"),
+        }
+    }
+}
+
+impl Error for LexError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LexError::InvalidRule(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// トークン。spanは入力中の位置の範囲で、単位は入力の型による
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<K> {
+    pub kind: K,
+    pub span: Range<usize>,
+}
+
+/// [Lexer]の構成に用いるビルダー
+#[derive(Debug)]
+pub struct LexerBuilder<K> {
+    rules: Vec<(K, String)>,
+    flags: Flags,
+    is_depth: bool,
+}
+
+impl<K> LexerBuilder<K> {
+    /// 規則を追加。先に追加した規則ほど優先度が高い
+    pub fn rule(mut self, kind: K, pattern: &str) -> Self {
+        self.rules.push((kind, pattern.to_string()));
+        self
+    }
+
+    /// 全ての規則に共通するフラグの初期値を指定
+    pub fn flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// trueの場合に深さ優先探索を、falseの場合に幅優先探索を行う。初期値はtrue
+    pub fn is_depth(mut self, is_depth: bool) -> Self {
+        self.is_depth = is_depth;
+        self
+    }
+
+    /// 全ての規則をパースし、1つの命令列にまとめる
+    pub fn build(self) -> Result<Lexer<K>, RegexError> {
+        if self.rules.is_empty() {
+            return Err(LexError::NoRules.into());
+        }
+        let mut asts = Vec::new();
+        let mut kinds = Vec::new();
+        for (i, (kind, pattern)) in self.rules.into_iter().enumerate() {
+            let ast = super::parse(&pattern, self.flags)
+                .map_err(|e| LexError::InvalidRule(i, Box::new(e)))?;
+            asts.push(ast);
+            kinds.push(kind);
+        }
+        let code = codegen::get_code_tagged(&asts)?;
+        if !self.is_depth {
+            evaluator::check_width(&code)?;
+        }
+        Ok(Lexer {
+            code,
+            kinds,
+            is_depth: self.is_depth,
+        })
+    }
+}
+
+/// 字句解析器
+#[derive(Debug)]
+pub struct Lexer<K> {
+    code: Vec<Instruction>,
+    kinds: Vec<K>, // match命令の番号に対応するトークンの種類
+    is_depth: bool,
+}
+
+impl<K: Clone> Lexer<K> {
+    /// ビルダーを生成
+    pub fn builder() -> LexerBuilder<K> {
+        LexerBuilder {
+            rules: Vec::new(),
+            flags: Flags::default(),
+            is_depth: true,
+        }
+    }
+
+    /// 位置posから始まるトークンを返す。マッチしない場合はNone
+    pub fn token_at<H: Haystack + ?Sized>(
+        &self,
+        line: &H,
+        pos: usize,
+    ) -> Result<Option<Token<K>>, RegexError> {
+        self.token_with_cache(line, pos, &mut Cache::default())
+    }
+
+    /// 作業領域cacheを用いる[Lexer::token_at]
+    fn token_with_cache<H: Haystack + ?Sized>(
+        &self,
+        line: &H,
+        pos: usize,
+        cache: &mut Cache,
+    ) -> Result<Option<Token<K>>, RegexError> {
+        let token = evaluator::eval_tagged(&self.code, line, pos, self.is_depth, cache)?
+            .filter(|(end, _)| *end > pos)
+            .map(|(end, tag)| Token {
+                kind: self.kinds[tag].clone(),
+                span: pos..end,
+            });
+        Ok(token)
+    }
+
+    /// 先頭から順にトークンを返すイテレータ。
+    ///
+    /// どの規則にもマッチしない位置では[LexError::NoMatch]を返し、終了する。
+    pub fn tokens<'a, H: Haystack + ?Sized>(&'a self, line: &'a H) -> Tokens<'a, K, H> {
+        Tokens {
+            lexer: self,
+            line,
+            pos: Some(0),
+            cache: Cache::default(),
+        }
+    }
+
+    /// 文字列全体をトークンに分割。範囲はバイト単位とする
+    pub fn tokenize(&self, input: &str) -> Result<Vec<Token<K>>, RegexError> {
+        self.tokens(input).collect()
+    }
+}
+
+/// [Lexer::tokens]が返すイテレータ
+pub struct Tokens<'a, K, H: ?Sized> {
+    lexer: &'a Lexer<K>,
+    line: &'a H,
+    pos: Option<usize>, // 次のトークンの開始位置。終了時はNone
+    cache: Cache,       // 評価の作業領域
+}
+
+impl<K: Clone, H: Haystack + ?Sized> Iterator for Tokens<'_, K, H> {
+    type Item = Result<Token<K>, RegexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.pos.filter(|pos| *pos < self.line.end())?;
+        match self.lexer.token_with_cache(self.line, pos, &mut self.cache) {
+            Ok(Some(token)) => {
+                self.pos = Some(token.span.end);
+                Some(Ok(token))
+            }
+            Ok(None) => {
+                self.pos = None;
+                Some(Err(LexError::NoMatch(pos).into()))
+            }
+            Err(e) => {
+                self.pos = None;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[test]
+fn test() {
+    for is_depth in [true, false] {
+        let lexer = Lexer::builder()
+            .rule("int", "int")
+            .rule("ident", "[a-zA-Z_][a-zA-Z0-9_]*")
+            .rule("number", "[0-9]+(\\.[0-9]+)?")
+            .rule("op", "[-+*/=]|==")
+            .rule("space", "[ \t]+")
+            .is_depth(is_depth)
+            .build()
+            .unwrap();
+        let kinds = |input| {
+            lexer
+                .tokenize(input)
+                .unwrap()
+                .into_iter()
+                .map(|t| (t.kind, t.span))
+                .collect::<Vec<_>>()
+        };
+        // 最長一致と、同じ長さの場合の優先度
+        assert_eq!(
+            kinds("int integer==3.14"),
+            vec![
+                ("int", 0..3),
+                ("space", 3..4),
+                ("ident", 4..11),
+                ("op", 11..13),
+                ("number", 13..17),
+            ]
+        );
+        assert_eq!(kinds(""), vec![]);
+
+        let line = "a = 1 ? b".chars().collect::<Vec<char>>();
+        let tokens = lexer.tokens(&line).collect::<Vec<_>>();
+        assert_eq!(tokens.len(), 7);
+        let e = tokens[6].as_ref().unwrap_err();
+        assert!(matches!(e, RegexError::Lex(LexError::NoMatch(6))));
+    }
+
+    // 範囲の単位は入力の型による
+    let lexer = Lexer::builder()
+        .rule(0, "[あ-ん]+")
+        .rule(1, "[a-z]+")
+        .build()
+        .unwrap();
+    let spans = |tokens: Vec<Token<i32>>| tokens.into_iter().map(|t| t.span).collect::<Vec<_>>();
+    assert_eq!(spans(lexer.tokenize("あいab").unwrap()), vec![0..6, 6..8]);
+    let line = "あいab".chars().collect::<Vec<char>>();
+    let tokens = lexer.tokens(&line[..]).collect::<Result<Vec<_>, _>>();
+    assert_eq!(spans(tokens.unwrap()), vec![0..2, 2..4]);
+
+    // 空文字列のみにマッチする場合はエラー
+    let lexer = Lexer::builder().rule(0, "a*").build().unwrap();
+    assert_eq!(lexer.tokenize("aa").unwrap().len(), 1);
+    assert!(lexer.tokenize("b").is_err());
+
+    let e = Lexer::builder()
+        .rule(0, "a")
+        .rule(1, "(")
+        .build()
+        .unwrap_err();
+    let RegexError::Lex(LexError::InvalidRule(1, e)) = e else {
+        panic!("{e}");
+    };
+    assert_eq!(e.span(), Some(1..1));
+    assert!(Lexer::<u8>::builder().build().is_err());
+}
@@ -1,7 +1,12 @@
 //! 正規表現の式をパースし、抽象構文木に変換
+use super::{
+    class::{case_variants, Class},
+    unicode,
+};
 use std::{
     error::Error,
     fmt::{self, Debug, Display},
+    iter::Peekable,
     mem::take,
 };
 
@@ -13,6 +18,14 @@ mod elm {
     pub const RPAR: char = ')';
     pub const PIPE: char = '|';
     pub const BKSL: char = '\\';
+    pub const DOT: char = '.';
+    pub const HAT: char = '^';
+    pub const DOLL: char = '$';
+    pub const LBRK: char = '[';
+    pub const RBRK: char = ']';
+    pub const HYPH: char = '-';
+    pub const LBRC: char = '{';
+    pub const RBRC: char = '}';
 }
 /// パースエラーを表すための型
 #[derive(Debug)]
@@ -22,6 +35,13 @@ pub enum ParseError {
     InvalidRightParen(usize),   // 左開き括弧無し
     NoPrev(usize),              // +、|、*、?の前に式がない
     NoRightParen,               // 右閉じ括弧無し
+    InvalidFlag(usize, char),   // 不明なフラグ
+    InvalidRange(usize),        // 文字クラスの範囲の始点が終点より大きい
+    NoRightBracket(usize),      // 文字クラスの右閉じ括弧無し
+    InvalidGroupName(usize),    // 不正または重複したグループ名
+    InvalidProperty(usize),     // 不明なUnicodeプロパティ
+    InvalidRepeat(usize),       // 回数の指定が不正
+    InvalidClassName(usize),    // 不明な文字クラス名（[:name:]）
     Empty,                      // 空のパターン
 }
 
@@ -44,6 +64,27 @@ impl Display for ParseError {
             ParseError::NoRightParen => {
                 write!(f, "ParseError: no right parenthesis")
             }
+            ParseError::InvalidFlag(pos, c) => {
+                write!(f, "ParseError: invalid flag: pos = {pos}, char = '{c}'")
+            }
+            ParseError::InvalidRange(pos) => {
+                write!(f, "ParseError: invalid class range: pos = {pos}")
+            }
+            ParseError::NoRightBracket(pos) => {
+                write!(f, "ParseError: no right bracket: pos = {pos}")
+            }
+            ParseError::InvalidGroupName(pos) => {
+                write!(f, "ParseError: invalid group name: pos = {pos}")
+            }
+            ParseError::InvalidProperty(pos) => {
+                write!(f, "ParseError: invalid property: pos = {pos}")
+            }
+            ParseError::InvalidRepeat(pos) => {
+                write!(f, "ParseError: invalid repetition count: pos = {pos}")
+            }
+            ParseError::InvalidClassName(pos) => {
+                write!(f, "ParseError: invalid character class name: pos = {pos}")
+            }
             ParseError::Empty => write!(f, "ParseError: empty expression"),
         }
     }
@@ -51,15 +92,105 @@ impl Display for ParseError {
 
 impl Error for ParseError {} // エラー用に、Errorトレイトを実装
 
+impl ParseError {
+    /// エラーの原因となった文字の位置。右閉じ括弧無しと空のパターンの場合はNone
+    pub fn pos(&self) -> Option<usize> {
+        match self {
+            ParseError::InvalidEscape(pos, _)
+            | ParseError::InvalidOr(pos, _)
+            | ParseError::InvalidRightParen(pos)
+            | ParseError::NoPrev(pos)
+            | ParseError::InvalidFlag(pos, _)
+            | ParseError::InvalidRange(pos)
+            | ParseError::NoRightBracket(pos)
+            | ParseError::InvalidGroupName(pos)
+            | ParseError::InvalidProperty(pos)
+            | ParseError::InvalidRepeat(pos)
+            | ParseError::InvalidClassName(pos) => Some(*pos),
+            ParseError::NoRightParen | ParseError::Empty => None,
+        }
+    }
+}
+
+/// 位置に関する表明（幅0でマッチする）
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Assertion {
+    StartLine,            // 行頭 : (?m)の^
+    EndLine,              // 行末 : (?m)の$
+    StartText,            // 入力の先頭 : ^、\A
+    EndText,              // 入力の末尾 : $、\z
+    WordBoundary,         // 単語境界 : \b
+    NotWordBoundary,      // 単語境界以外 : \B
+    AsciiWordBoundary,    // ASCIIの単語境界 : (?-u)の\b
+    AsciiNotWordBoundary, // ASCIIの単語境界以外 : (?-u)の\B
+}
+
+impl Assertion {
+    /// 単語境界の表明の場合、ASCIIの範囲で判定するかを返す
+    pub fn word_ascii(&self) -> Option<bool> {
+        match self {
+            Assertion::WordBoundary | Assertion::NotWordBoundary => Some(false),
+            Assertion::AsciiWordBoundary | Assertion::AsciiNotWordBoundary => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::StartLine => write!(f, "start_line"),
+            Assertion::EndLine => write!(f, "end_line"),
+            Assertion::StartText => write!(f, "start_text"),
+            Assertion::EndText => write!(f, "end_text"),
+            Assertion::WordBoundary => write!(f, "word_boundary"),
+            Assertion::NotWordBoundary => write!(f, "not_word_boundary"),
+            Assertion::AsciiWordBoundary => write!(f, "ascii_word_boundary"),
+            Assertion::AsciiNotWordBoundary => write!(f, "ascii_not_word_boundary"),
+        }
+    }
+}
+
+/// 正規表現の構文
+///
+/// いずれの構文も同じASTに変換する。POSIXの構文でも、`\w`や`\b`などのエスケープシーケンスは使える。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    #[default]
+    Native,   // このクレートの構文
+    Basic,    // POSIXの基本正規表現（BRE）。`\(\)`、`\{n,m\}`、`[[:alpha:]]`など
+    Extended, // POSIXの拡張正規表現（ERE）。`()`、`{n,m}`、`[[:alpha:]]`など
+}
+
+/// パース時のフラグ
+///
+/// 正規表現中では`(?s)`、`(?m)`、`(?-s)`のように指定し、
+/// 指定以降、そのグループの終わりまで有効となる。
+/// `(?s:abc)`のように指定した場合は、括弧内のみ有効。
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Flags {
+    pub dot_nl: bool,     // s : .が改行にもマッチ
+    pub multi_line: bool, // m : ^と$が行頭と行末にマッチ
+    pub ignore_case: bool, // i : 大文字と小文字を区別しない
+    pub ascii: bool,       // -u : \w、\d、\s、\b、\BをASCIIの範囲で判定（(?u)で戻す）
+    pub syntax: Syntax,    // 構文。正規表現中では変更できない
+}
+
 /// 抽象構文木を表現するための型
-#[derive(Debug, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum AST {
     Char(char),
+    AnyChar(bool), // trueの場合は改行にもマッチ
+    Class(Class),
+    Assert(Assertion),
     Plus(Box<AST>),
     Star(Box<AST>),
     Question(Box<AST>),
     Or(Box<AST>, Box<AST>),
     Seq(Vec<AST>),
+    Capture(usize, Option<String>, Box<AST>), // グループの番号（1始まり）と名前
+    Atomic(Box<AST>), // アトミックグループ : (?>...)、および強欲な量指定子 : *+、++、?+
 }
 impl fmt::Display for AST {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -68,14 +199,31 @@ impl fmt::Display for AST {
 }
 
 /// parse_plus_star_question関数で利用するための列挙型
+#[allow(clippy::upper_case_acronyms)]
 enum PSQ {
     Plus,
     Star,
     Question,
 }
 
+/// 括弧のコンテキスト
+///
+/// 括弧の開始時に外側のコンテキストを保存し、括弧の終了時に復元する。
+struct Context {
+    seq: Vec<AST>,
+    seq_or: Vec<AST>,
+    flags: Flags,
+    capture: Option<(usize, Option<String>)>, // キャプチャする場合は、グループの番号と名前
+    atomic: bool,                             // アトミックグループか
+}
+
 /// 正規表現を抽象構文木に変換
 pub fn parse(expr: &str) -> Result<AST, ParseError> {
+    parse_with_flags(expr, Flags::default())
+}
+
+/// フラグの初期値を指定して、正規表現を抽象構文木に変換
+pub fn parse_with_flags(expr: &str, flags: Flags) -> Result<AST, ParseError> {
     // 内部状態を表現するための型
     // Char状態 : 文字列処理中
     // Escape状態 : エスケープシーケンス処理中
@@ -86,35 +234,103 @@ pub fn parse(expr: &str) -> Result<AST, ParseError> {
 
     let mut seq: Vec<AST> = Vec::new(); // 現在のSeqのコンテキスト
     let mut seq_or: Vec<AST> = Vec::new(); // 現在のOrのコンテキスト
-    let mut stack: Vec<(Vec<AST>, Vec<AST>)> = Vec::new(); // コンテキストのスタック
+    let mut stack: Vec<Context> = Vec::new(); // コンテキストのスタック
     let mut state: ParseState = ParseState::Char; // 現在の状態
+    let mut flags = flags; // 現在のフラグ
+    let mut pipe_pos: Option<usize> = None; // 直前の|の位置
+    let mut names: Vec<Option<String>> = Vec::new(); // これまでのグループの名前
+
+    let expr_chars = expr.chars().collect::<Vec<char>>(); // BREで$の後を調べるため
+    let mut chars = expr.chars().enumerate().peekable();
+    while let Some((i, c)) = chars.next() {
+        // 構文による違いを吸収し、特殊文字のみ以下で処理する
+        if let ParseState::Escape = state {
+            if flags.syntax == Syntax::Basic && BRE_ESCAPED_META.contains(&c) {
+                state = ParseState::Char;
+            }
+        } else if c != elm::BKSL && is_literal(flags.syntax, c, &seq, &expr_chars[i + 1..]) {
+            seq.push(char_ast(c, flags));
+            continue;
+        }
 
-    for (i, c) in expr.chars().enumerate() {
         match state {
             ParseState::Char => {
                 match c {
-                    elm::PLUS => parse_plus_star_question(&mut seq, PSQ::Plus, i)?,
-                    elm::STAR => parse_plus_star_question(&mut seq, PSQ::Star, i)?,
-                    elm::QUES => parse_plus_star_question(&mut seq, PSQ::Question, i)?,
+                    elm::PLUS | elm::STAR | elm::QUES => {
+                        let ast_type = match c {
+                            elm::PLUS => PSQ::Plus,
+                            elm::STAR => PSQ::Star,
+                            _ => PSQ::Question,
+                        };
+                        // 直後の+は強欲な量指定子
+                        let possessive = flags.syntax == Syntax::Native
+                            && chars.next_if(|(_, c)| *c == elm::PLUS).is_some();
+                        parse_plus_star_question(&mut seq, ast_type, possessive, i)?
+                    }
                     elm::LPAR => {
                         let prev = take(&mut seq);
                         let prev_or = take(&mut seq_or);
-                        stack.push((prev, prev_or));
+                        let prev_flags = flags;
+                        let mut capture = None;
+                        let mut atomic = false;
+                        if flags.syntax == Syntax::Native
+                            && chars.next_if(|(_, c)| *c == elm::QUES).is_some()
+                        {
+                            if chars.next_if(|(_, c)| *c == '>').is_some() {
+                                // (?>...)
+                                atomic = true;
+                            } else if chars.next_if(|(_, c)| *c == 'P').is_some()
+                                || chars.peek().is_some_and(|(_, c)| *c == '<')
+                            {
+                                // (?<name>...) または (?P<name>...)
+                                let name = parse_group_name(&mut chars, i, &names)?;
+                                names.push(Some(name.clone()));
+                                capture = Some((names.len(), Some(name)));
+                            } else if parse_flags(&mut chars, &mut flags)? {
+                                // (?flags)の場合は、現在のコンテキストのフラグを変更
+                                seq = prev;
+                                seq_or = prev_or;
+                                continue;
+                            }
+                            // (?flags:...)と(?:...)はキャプチャしない
+                        } else {
+                            names.push(None);
+                            capture = Some((names.len(), None));
+                        }
+                        stack.push(Context {
+                            seq: prev,
+                            seq_or: prev_or,
+                            flags: prev_flags,
+                            capture,
+                            atomic,
+                        });
+                        pipe_pos = None;
                     }
                     elm::RPAR => {
-                        if let Some((mut prev, prev_or)) = stack.pop() {
+                        if let Some(ctx) = stack.pop() {
                             if !seq.is_empty() {
                                 seq_or.push(AST::Seq(seq));
+                            } else if pipe_pos.is_some() {
+                                return Err(ParseError::InvalidOr(i, c));
                             }
 
                             // Orを生成
+                            let mut prev = ctx.seq;
                             if let Some(ast) = foldr(seq_or) {
-                                prev.push(ast);
+                                match ctx.capture {
+                                    Some((n, name)) => {
+                                        prev.push(AST::Capture(n, name, Box::new(ast)))
+                                    }
+                                    None if ctx.atomic => prev.push(AST::Atomic(Box::new(ast))),
+                                    None => prev.push(ast),
+                                }
                             }
 
                             // 以前のコンテキストを、現在のコンテキストにする
                             seq = prev;
-                            seq_or = prev_or;
+                            seq_or = ctx.seq_or;
+                            flags = ctx.flags;
+                            pipe_pos = None;
                         } else {
                             let err = ParseError::InvalidRightParen(i);
                             return Err(err);
@@ -125,32 +341,63 @@ pub fn parse(expr: &str) -> Result<AST, ParseError> {
                             return Err(ParseError::NoPrev(i));
                         } else {
                             let prev = take(&mut seq);
-                            seq_or.push(AST::Seq(prev))
+                            seq_or.push(AST::Seq(prev));
+                            pipe_pos = Some(i);
                         }
                     }
                     elm::BKSL => {
                         state = ParseState::Escape;
                     }
+                    elm::LBRC => {
+                        let (min, max) = parse_interval(&mut chars, i, flags.syntax)?;
+                        parse_repeat(&mut seq, min, max, i)?;
+                    }
+                    elm::LBRK => {
+                        seq.push(parse_class(&mut chars, i, flags)?);
+                    }
+                    elm::DOT => {
+                        seq.push(AST::AnyChar(flags.dot_nl));
+                    }
+                    elm::HAT => {
+                        let a = if flags.multi_line {
+                            Assertion::StartLine
+                        } else {
+                            Assertion::StartText
+                        };
+                        seq.push(AST::Assert(a));
+                    }
+                    elm::DOLL => {
+                        let a = if flags.multi_line {
+                            Assertion::EndLine
+                        } else {
+                            Assertion::EndText
+                        };
+                        seq.push(AST::Assert(a));
+                    }
                     _ => {
-                        seq.push(AST::Char(c));
+                        seq.push(char_ast(c, flags));
                     }
                 };
             }
             ParseState::Escape => {
-                let ret = parse_escape(i, c);
-                match ret {
-                    Ok(ast) => {
-                        seq.push(ast);
-                        state = ParseState::Char;
-                    }
-                    _ => return ret
-                }
+                let ast = parse_escape(&mut chars, i, c, flags)?;
+                seq.push(ast);
+                state = ParseState::Char;
             }
         }
     }
+    // 末尾の\はエスケープする文字がない
+    if let ParseState::Escape = state {
+        return Err(ParseError::InvalidEscape(expr_chars.len() - 1, elm::BKSL));
+    }
+    if !stack.is_empty() {
+        return Err(ParseError::NoRightParen);
+    }
     if !seq.is_empty() {
         let prev = take(&mut seq);
         seq_or.push(AST::Seq(prev));
+    } else if let Some(pos) = pipe_pos {
+        return Err(ParseError::InvalidOr(pos, elm::PIPE));
     }
     if let Some(ast) = foldr(seq_or) {
         Ok(ast)
@@ -159,14 +406,236 @@ pub fn parse(expr: &str) -> Result<AST, ParseError> {
     }
 }
 
+/// BREで`\`を付けると特殊文字となる文字。`\+`、`\?`、`\|`はGNUの拡張
+const BRE_ESCAPED_META: [char; 6] = [
+    elm::LPAR,
+    elm::RPAR,
+    elm::LBRC,
+    elm::PIPE,
+    elm::PLUS,
+    elm::QUES,
+];
+
+/// 回数の指定の上限
+pub const REPEAT_MAX: usize = 1000;
+
+/// 回数を指定した繰り返しを展開した後の、ノード数の上限。
+/// `((a{1000}){1000}){1000}`のような入れ子の展開で、ASTが巨大になることを防ぐ
+pub const REPEAT_SIZE_MAX: usize = 100_000;
+
+/// 構文によって、文字cを通常の文字として扱うか判定
+///
+/// seqは現在のSeqのコンテキスト、restはcより後の文字。
+/// このクレートの構文では`{`は常に通常の文字となる。
+/// EREでは`{`は数字か`,`が続く場合のみ回数の指定となる。
+/// BREでは`+`、`?`、`|`、`(`、`)`、`{`は通常の文字で、`\`を付けると特殊文字となる。
+/// また`*`は先頭（`\(`と`\|`の直後、および先頭の`^`の直後を含む）で、
+/// `^`は先頭以外で、`$`は末尾（`\)`と`\|`の直前を含む）以外で通常の文字となる。
+fn is_literal(syntax: Syntax, c: char, seq: &[AST], rest: &[char]) -> bool {
+    match (syntax, c) {
+        (Syntax::Native, elm::LBRC) => true,
+        (Syntax::Extended, elm::LBRC) => {
+            !rest.first().is_some_and(|c| c.is_ascii_digit() || *c == ',')
+        }
+        (Syntax::Basic, elm::PLUS | elm::QUES | elm::PIPE | elm::LPAR | elm::RPAR | elm::LBRC) => {
+            true
+        }
+        (Syntax::Basic, elm::STAR) => match seq {
+            [] => true,
+            [AST::Assert(a)] => matches!(a, Assertion::StartText | Assertion::StartLine),
+            _ => false,
+        },
+        (Syntax::Basic, elm::HAT) => !seq.is_empty(),
+        (Syntax::Basic, elm::DOLL) => {
+            !(rest.is_empty()
+                || rest.starts_with(&[elm::BKSL, elm::RPAR])
+                || rest.starts_with(&[elm::BKSL, elm::PIPE]))
+        }
+        _ => false,
+    }
+}
+
+/// `{`に続く回数の指定（`{n}`、`{n,}`、`{n,m}`、`{,m}`）をパースし、最小と最大の回数を返す
+///
+/// BREでは`\}`で閉じる。最大の回数がない場合はNoneとする。
+/// posは`{`の位置で、エラーの表示に用いる。
+fn parse_interval<I>(
+    chars: &mut Peekable<I>,
+    pos: usize,
+    syntax: Syntax,
+) -> Result<(usize, Option<usize>), ParseError>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    let number = |chars: &mut Peekable<I>| -> Result<Option<usize>, ParseError> {
+        let mut n: Option<usize> = None;
+        while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+            let d = c as usize - '0' as usize;
+            n = Some(n.unwrap_or(0) * 10 + d);
+            if n > Some(REPEAT_MAX) {
+                return Err(ParseError::InvalidRepeat(pos));
+            }
+        }
+        Ok(n)
+    };
+    let min = number(chars)?;
+    let max = if chars.next_if(|(_, c)| *c == ',').is_some() {
+        number(chars)?
+    } else {
+        Some(min.ok_or(ParseError::InvalidRepeat(pos))?)
+    };
+    if syntax == Syntax::Basic && chars.next_if(|(_, c)| *c == elm::BKSL).is_none() {
+        return Err(ParseError::InvalidRepeat(pos));
+    }
+    if chars.next_if(|(_, c)| *c == elm::RBRC).is_none() {
+        return Err(ParseError::InvalidRepeat(pos));
+    }
+    let min = min.unwrap_or(0);
+    if max.is_some_and(|max| max < min) {
+        return Err(ParseError::InvalidRepeat(pos));
+    }
+    Ok((min, max))
+}
+
+/// 回数を指定した繰り返しをASTに変換
+///
+/// `e{n,m}`はeをn回並べ、続けて`e?`をm-n回入れ子にする（`e{2,4}`は`ee(e(e)?)?`）。
+/// 最大の回数がない場合は、続けて`e*`を並べる。前に式がない場合はエラー。
+/// 展開後のノード数が[REPEAT_SIZE_MAX]を超える場合もエラーとする
+fn parse_repeat(
+    seq: &mut Vec<AST>,
+    min: usize,
+    max: Option<usize>,
+    pos: usize,
+) -> Result<(), ParseError> {
+    let prev = seq.pop().ok_or(ParseError::NoPrev(pos))?;
+    let count = max.unwrap_or(min + 1);
+    if ast_size(&prev).saturating_mul(count) > REPEAT_SIZE_MAX {
+        return Err(ParseError::InvalidRepeat(pos));
+    }
+    let mut v = vec![prev.clone(); min];
+    match max {
+        None => v.push(AST::Star(Box::new(prev))),
+        Some(max) => {
+            let mut tail: Option<AST> = None;
+            for _ in min..max {
+                let e = match tail {
+                    Some(t) => AST::Seq(vec![prev.clone(), t]),
+                    None => prev.clone(),
+                };
+                tail = Some(AST::Question(Box::new(e)));
+            }
+            v.extend(tail);
+        }
+    }
+    seq.push(AST::Seq(v));
+    Ok(())
+}
+
+/// ASTのノード数
+fn ast_size(ast: &AST) -> usize {
+    match ast {
+        AST::Char(_) | AST::AnyChar(_) | AST::Class(_) | AST::Assert(_) => 1,
+        AST::Plus(e)
+        | AST::Star(e)
+        | AST::Question(e)
+        | AST::Capture(_, _, e)
+        | AST::Atomic(e) => 1 + ast_size(e),
+        AST::Or(e1, e2) => 1 + ast_size(e1) + ast_size(e2),
+        AST::Seq(v) => 1 + v.iter().map(ast_size).sum::<usize>(),
+    }
+}
+
+/// `(?`に続くフラグをパースし、flagsに反映
+///
+/// `(?sm)`のように`)`で終わる場合はtrueを、
+/// `(?sm:`のように`:`で終わる場合はfalseを返す。
+/// `-`以降のフラグは無効化される。
+fn parse_flags<I>(chars: &mut I, flags: &mut Flags) -> Result<bool, ParseError>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    let mut enable = true;
+    for (i, c) in chars {
+        match c {
+            's' => flags.dot_nl = enable,
+            'm' => flags.multi_line = enable,
+            'i' => flags.ignore_case = enable,
+            'u' => flags.ascii = !enable,
+            '-' if enable => enable = false,
+            elm::RPAR => return Ok(true),
+            ':' => return Ok(false),
+            _ => return Err(ParseError::InvalidFlag(i, c)),
+        }
+    }
+    Err(ParseError::NoRightParen)
+}
+
+/// `(?P`または`(?`に続く`<name>`をパースし、グループ名を返す
+///
+/// 名前は英数字と`_`からなる空でない文字列で、既存のグループ名と重複してはならない。
+/// posは`(`の位置で、エラーの表示に用いる。
+fn parse_group_name<I>(
+    chars: &mut Peekable<I>,
+    pos: usize,
+    names: &[Option<String>],
+) -> Result<String, ParseError>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    if chars.next_if(|(_, c)| *c == '<').is_none() {
+        return Err(ParseError::InvalidGroupName(pos));
+    }
+    let mut name = String::new();
+    for (_, c) in chars {
+        match c {
+            '>' if !name.is_empty() && !names.iter().flatten().any(|n| *n == name) => {
+                return Ok(name)
+            }
+            c if c.is_alphanumeric() || c == '_' => name.push(c),
+            _ => break,
+        }
+    }
+    Err(ParseError::InvalidGroupName(pos))
+}
+
+/// ASTに含まれるキャプチャグループの名前の一覧
+///
+/// 添字はグループの番号で、0番目はマッチ全体を表すため常にNoneとなる。
+pub fn capture_names(ast: &AST) -> Vec<Option<String>> {
+    fn walk(ast: &AST, names: &mut Vec<Option<String>>) {
+        match ast {
+            AST::Plus(e) | AST::Star(e) | AST::Question(e) | AST::Atomic(e) => walk(e, names),
+            AST::Or(e1, e2) => {
+                walk(e1, names);
+                walk(e2, names);
+            }
+            AST::Seq(v) => v.iter().for_each(|e| walk(e, names)),
+            AST::Capture(n, name, e) => {
+                if names.len() <= *n {
+                    names.resize(*n + 1, None);
+                }
+                names[*n] = name.clone();
+                walk(e, names);
+            }
+            _ => (),
+        }
+    }
+    let mut names = vec![None];
+    walk(ast, &mut names);
+    names
+}
+
 /// +、*、?をASTに変換
 ///
 /// 後置記法で、+、*、?の前にパターンがない場合はエラー
+/// possessiveがtrueの場合は強欲な量指定子（*+、++、?+）とし、アトミックグループで囲む。
 ///
 /// 例 : *ab、abc|+などはエラー
 fn parse_plus_star_question(
     seq: &mut Vec<AST>,
     ast_type: PSQ,
+    possessive: bool,
     pos: usize,
 ) -> Result<(), ParseError> {
     if let Some(prev) = seq.pop() {
@@ -175,7 +644,11 @@ fn parse_plus_star_question(
             PSQ::Star => AST::Star(Box::new(prev)),
             PSQ::Question => AST::Question(Box::new(prev)),
         };
-        seq.push(ast);
+        if possessive {
+            seq.push(AST::Atomic(Box::new(ast)));
+        } else {
+            seq.push(ast);
+        }
         Ok(())
     } else {
         Err(ParseError::NoPrev(pos))
@@ -183,16 +656,316 @@ fn parse_plus_star_question(
 }
 
 /// 特殊文字のエスケープ
-fn parse_escape(pos: usize, c: char) -> Result<AST, ParseError> {
+fn parse_escape<I>(
+    chars: &mut Peekable<I>,
+    pos: usize,
+    c: char,
+    flags: Flags,
+) -> Result<AST, ParseError>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    if let Some(a) = escape_assert(c, flags) {
+        return Ok(AST::Assert(a));
+    }
+    if let Some(mut class) = escape_class(chars, pos, c, flags)? {
+        if flags.ignore_case {
+            class.case_fold();
+        }
+        return Ok(AST::Class(class));
+    }
+    match escape_char(c) {
+        Some(c) => Ok(char_ast(c, flags)),
+        None => Err(ParseError::InvalidEscape(pos, c)),
+    }
+}
+
+/// 表明を表すエスケープシーケンス。表明でない場合はNoneを返す
+///
+/// `\b`と`\B`は、フラグ`-u`が有効な場合にASCIIの範囲で単語を判定する。
+fn escape_assert(c: char, flags: Flags) -> Option<Assertion> {
+    let a = match (c, flags.ascii) {
+        ('b', false) => Assertion::WordBoundary,
+        ('B', false) => Assertion::NotWordBoundary,
+        ('b', true) => Assertion::AsciiWordBoundary,
+        ('B', true) => Assertion::AsciiNotWordBoundary,
+        ('A', _) => Assertion::StartText,
+        ('z', _) => Assertion::EndText,
+        _ => return None,
+    };
+    Some(a)
+}
+
+/// 文字クラスを表すエスケープシーケンス。文字クラスでない場合はNoneを返す
+///
+/// `\w`、`\d`、`\s`はUnicodeの定義に従い、フラグ`-u`が有効な場合はASCIIの範囲に限る。
+/// `\p`は常にUnicodeの定義に従う。大文字の`\W`、`\D`、`\S`、`\P`は補集合となる。
+fn escape_class<I>(
+    chars: &mut Peekable<I>,
+    pos: usize,
+    c: char,
+    flags: Flags,
+) -> Result<Option<Class>, ParseError>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    let mut class = match (c.to_ascii_lowercase(), flags.ascii) {
+        ('w', false) => unicode::word(),
+        ('d', false) => unicode::digit(),
+        ('s', false) => unicode::space(),
+        ('w', true) => Class::new(vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')]),
+        ('d', true) => Class::new(vec![('0', '9')]),
+        ('s', true) => Class::new(vec![('\t', '\r'), (' ', ' ')]),
+        ('p', _) => parse_property(chars, pos)?,
+        _ => return Ok(None),
+    };
+    if c.is_ascii_uppercase() {
+        class.negate();
+    }
+    Ok(Some(class))
+}
+
+/// `\p`に続くプロパティ名をパースし、対応する文字クラスを返す
+///
+/// `\p{Greek}`のように`{}`で囲むか、`\pL`のように1文字で指定する。
+fn parse_property<I>(chars: &mut Peekable<I>, pos: usize) -> Result<Class, ParseError>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    let name = match chars.next() {
+        Some((_, '{')) => {
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '}')) => break,
+                    Some((_, c)) => name.push(c),
+                    None => return Err(ParseError::InvalidProperty(pos)),
+                }
+            }
+            name
+        }
+        Some((_, c)) => c.to_string(),
+        None => return Err(ParseError::InvalidProperty(pos)),
+    };
+    unicode::property(&name).ok_or(ParseError::InvalidProperty(pos))
+}
+
+/// エスケープされた文字を、それが表す文字に変換
+fn escape_char(c: char) -> Option<char> {
     match c {
         elm::PLUS | elm::STAR | elm::QUES | elm::PIPE | elm::LPAR | elm::RPAR | elm::BKSL => {
-            Ok(AST::Char(c))
+            Some(c)
         }
-        _ => {
-            let err = ParseError::InvalidEscape(pos, c);
-            Err(err)
+        elm::DOT | elm::HAT | elm::DOLL | elm::LBRK | elm::RBRK | elm::HYPH => Some(c),
+        elm::LBRC | elm::RBRC => Some(c),
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        _ => None,
+    }
+}
+
+/// 1文字をASTに変換
+///
+/// 大文字と小文字を区別しない場合、大文字と小文字を持つ文字は文字クラスとする。
+fn char_ast(c: char, flags: Flags) -> AST {
+    if flags.ignore_case && !case_variants(c).is_empty() {
+        let mut class = Class::from_char(c);
+        class.case_fold();
+        AST::Class(class)
+    } else {
+        AST::Char(c)
+    }
+}
+
+/// POSIXの文字クラス名（`[:alpha:]`など）に対応する文字クラス。不明な名前の場合はNoneを返す
+///
+/// POSIXロケールの定義に従い、ASCIIの範囲に限る。
+fn posix_class(name: &str) -> Option<Class> {
+    let ranges = match name {
+        "alpha" => vec![('A', 'Z'), ('a', 'z')],
+        "digit" => vec![('0', '9')],
+        "alnum" => vec![('0', '9'), ('A', 'Z'), ('a', 'z')],
+        "upper" => vec![('A', 'Z')],
+        "lower" => vec![('a', 'z')],
+        "space" => vec![('\t', '\r'), (' ', ' ')],
+        "blank" => vec![('\t', '\t'), (' ', ' ')],
+        "punct" => vec![('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+        "print" => vec![(' ', '~')],
+        "graph" => vec![('!', '~')],
+        "cntrl" => vec![('\0', '\x1f'), ('\x7f', '\x7f')],
+        "xdigit" => vec![('0', '9'), ('A', 'F'), ('a', 'f')],
+        _ => return None,
+    };
+    Some(Class::new(ranges))
+}
+
+/// `[`に続く文字クラスをパース
+///
+/// `[abc]`、`[a-z]`、`[^0-9]`、`[\p{Greek}\d]`のように記述する。
+/// 先頭の`]`と、先頭または末尾の`-`は通常の文字として扱う。
+/// `[\p{L}&&\p{Greek}]`と`[\w--\d]`のように、`&&`で積集合を、`--`で差集合をとれる。
+/// 集合演算子は左から順に適用し、`^`は全体の補集合となる。
+///
+/// POSIXの構文では、`\`は通常の文字で、集合演算子は使えない。
+/// 代わりに`[:alpha:]`などの文字クラス名と、`[=a=]`、`[.a.]`（1文字のみ）を記述できる。
+fn parse_class<I>(chars: &mut Peekable<I>, pos: usize, flags: Flags) -> Result<AST, ParseError>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    // クラス内の要素。文字と、`\w`や`\p{...}`などの文字クラス
+    enum Item {
+        Char(usize, char, bool), // 位置、文字、エスケープされているか
+        Class(usize, Class),
+    }
+
+    // クラス内の1要素を読み込む。文字を表すエスケープシーケンスも1文字として扱う
+    fn next_item<I>(chars: &mut Peekable<I>, pos: usize, flags: Flags) -> Result<Item, ParseError>
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
+        match chars.next() {
+            Some((i, elm::BKSL)) if flags.syntax == Syntax::Native => match chars.next() {
+                Some((j, c)) => {
+                    if let Some(class) = escape_class(chars, j, c, flags)? {
+                        return Ok(Item::Class(i, class));
+                    }
+                    match escape_char(c) {
+                        Some(c) => Ok(Item::Char(i, c, true)),
+                        None => Err(ParseError::InvalidEscape(j, c)),
+                    }
+                }
+                None => Err(ParseError::NoRightBracket(pos)),
+            },
+            Some((i, elm::LBRK)) if flags.syntax != Syntax::Native => {
+                match chars.next_if(|(_, c)| matches!(c, ':' | '=' | '.')) {
+                    Some((_, kind)) => posix_item(chars, pos, i, kind),
+                    None => Ok(Item::Char(i, elm::LBRK, false)),
+                }
+            }
+            Some((i, c)) => Ok(Item::Char(i, c, false)),
+            None => Err(ParseError::NoRightBracket(pos)),
         }
     }
+
+    // `[:`、`[=`、`[.`に続く要素を、対応する`:]`などまで読み込む。iは`[`の位置
+    fn posix_item<I>(
+        chars: &mut Peekable<I>,
+        pos: usize,
+        i: usize,
+        kind: char,
+    ) -> Result<Item, ParseError>
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
+        let mut name = String::new();
+        loop {
+            match chars.next() {
+                Some((_, c)) if c == kind && chars.next_if(|(_, c)| *c == elm::RBRK).is_some() => {
+                    break
+                }
+                Some((_, c)) => name.push(c),
+                None => return Err(ParseError::NoRightBracket(pos)),
+            }
+        }
+        if kind == ':' {
+            return posix_class(&name)
+                .map(|class| Item::Class(i, class))
+                .ok_or(ParseError::InvalidClassName(i));
+        }
+        let mut it = name.chars();
+        match (it.next(), it.next()) {
+            (Some(c), None) => Ok(Item::Char(i, c, true)),
+            _ => Err(ParseError::InvalidClassName(i)),
+        }
+    }
+
+    // 演算子の左側の結果leftに、演算子op（`&`または`-`）で右側を適用
+    fn combine(left: Option<(Class, char)>, right: Class) -> Class {
+        match left {
+            Some((mut class, '&')) => {
+                class.intersect(&right);
+                class
+            }
+            Some((mut class, _)) => {
+                class.difference(&right);
+                class
+            }
+            None => right,
+        }
+    }
+
+    let negated = chars.next_if(|(_, c)| *c == elm::HAT).is_some();
+    let mut operand = Class::default(); // 集合演算子の間の要素の和集合
+    let mut left = None; // 集合演算子の左側の結果と、演算子
+    let mut first = true;
+    loop {
+        let (lo, escaped) = match next_item(chars, pos, flags)? {
+            Item::Char(_, lo, escaped) => (lo, escaped),
+            Item::Class(_, class) => {
+                operand.union(&class);
+                first = false;
+                continue;
+            }
+        };
+        if lo == elm::RBRK && !escaped && !first {
+            break;
+        }
+
+        // 集合演算子の`&&`（積集合）と`--`（差集合）。先頭の`--`は範囲とする
+        if !escaped
+            && flags.syntax == Syntax::Native
+            && (lo == '&' || (lo == elm::HYPH && !first))
+            && chars.next_if(|(_, c)| *c == lo).is_some()
+        {
+            left = Some((combine(left, take(&mut operand)), lo));
+            continue;
+        }
+        first = false;
+
+        if chars.next_if(|(_, c)| *c == elm::HYPH).is_none() {
+            operand.union(&Class::from_char(lo));
+            continue;
+        }
+        // [a--b]は差集合。[+--]のように`]`が続く場合は範囲
+        let dash = match flags.syntax {
+            Syntax::Native => chars.next_if(|(_, c)| *c == elm::HYPH),
+            _ => None,
+        };
+        if let Some((i, _)) = dash {
+            if chars.peek().is_some_and(|(_, c)| *c == elm::RBRK) {
+                if elm::HYPH < lo {
+                    return Err(ParseError::InvalidRange(i));
+                }
+                operand.union(&Class::new(vec![(lo, elm::HYPH)]));
+            } else {
+                operand.union(&Class::from_char(lo));
+                left = Some((combine(left, take(&mut operand)), elm::HYPH));
+            }
+            continue;
+        }
+        let (i, hi, escaped) = match next_item(chars, pos, flags)? {
+            Item::Char(i, hi, escaped) => (i, hi, escaped),
+            Item::Class(i, _) => return Err(ParseError::InvalidRange(i)), // [a-\d]
+        };
+        if hi == elm::RBRK && !escaped {
+            // [a-]のように末尾の-は通常の文字
+            operand.union(&Class::new(vec![(lo, lo), (elm::HYPH, elm::HYPH)]));
+            break;
+        }
+        if hi < lo {
+            return Err(ParseError::InvalidRange(i));
+        }
+        operand.union(&Class::new(vec![(lo, hi)]));
+    }
+
+    let mut class = combine(left, operand);
+    if flags.ignore_case {
+        class.case_fold();
+    }
+    if negated {
+        class.negate();
+    }
+    Ok(AST::Class(class))
 }
 
 /// orで結合された複数の式をASTに変換
@@ -277,10 +1050,31 @@ fn test() {
         AST::Seq(vec![AST::Char('a'), AST::Char('b')])
     );
     // parentheses
-    assert_eq!(parse("(a)").unwrap(), AST::Seq(vec![AST::Seq(vec![AST::Char('a')])]));
-    assert_eq!(parse("(a)b").unwrap(), AST::Seq(vec![AST::Seq(vec![AST::Char('a')]), AST::Char('b')]));
-    assert_eq!(parse("a(b)").unwrap(), AST::Seq(vec![AST::Char('a'), AST::Seq(vec![AST::Char('b')])]));
-    assert_eq!(parse("(ab)").unwrap(), AST::Seq(vec![AST::Seq(vec![AST::Char('a'), AST::Char('b')])]));
+    let cap = |n, e| AST::Capture(n, None, Box::new(AST::Seq(e)));
+    assert_eq!(parse("(a)").unwrap(), AST::Seq(vec![cap(1, vec![AST::Char('a')])]));
+    assert_eq!(parse("(a)b").unwrap(), AST::Seq(vec![cap(1, vec![AST::Char('a')]), AST::Char('b')]));
+    assert_eq!(parse("a(b)").unwrap(), AST::Seq(vec![AST::Char('a'), cap(1, vec![AST::Char('b')])]));
+    assert_eq!(parse("(ab)").unwrap(), AST::Seq(vec![cap(1, vec![AST::Char('a'), AST::Char('b')])]));
+    assert_eq!(
+        parse("((a)b)(c)").unwrap(),
+        AST::Seq(vec![
+            cap(1, vec![cap(2, vec![AST::Char('a')]), AST::Char('b')]),
+            cap(3, vec![AST::Char('c')])
+        ])
+    );
+    // non-capturing group
+    assert_eq!(parse("(?:a)").unwrap(), AST::Seq(vec![AST::Seq(vec![AST::Char('a')])]));
+    // named group
+    let ast = parse("(?<x>a)(b)(?P<y_1>c)").unwrap();
+    assert_eq!(
+        ast,
+        AST::Seq(vec![
+            AST::Capture(1, Some("x".into()), Box::new(AST::Seq(vec![AST::Char('a')]))),
+            cap(2, vec![AST::Char('b')]),
+            AST::Capture(3, Some("y_1".into()), Box::new(AST::Seq(vec![AST::Char('c')]))),
+        ])
+    );
+    assert_eq!(capture_names(&ast), vec![None, Some("x".into()), None, Some("y_1".into())]);
 
     // escape
     assert_eq!(parse("\\+").unwrap(), AST::Seq(vec![AST::Char('+')]));
@@ -301,5 +1095,221 @@ fn test() {
     assert!(parse("()").is_err());
     assert!(parse("").is_err());
     assert!(parse("\\").is_err());
+    // 末尾の\は、その位置のエスケープの誤り
+    assert!(matches!(parse("a\\"), Err(ParseError::InvalidEscape(1, '\\'))));
+    assert!(matches!(parse("a(\\"), Err(ParseError::InvalidEscape(2, '\\'))));
     assert!(parse("\\a").is_err());
+    assert!(parse("(?<>a)").is_err());
+    assert!(parse("(?<a-b>a)").is_err());
+    assert!(parse("(?<x>a)(?<x>b)").is_err());
+    assert!(parse("(?Px>a)").is_err());
+}
+
+#[test]
+fn test_flags() {
+    // dot
+    assert_eq!(parse(".").unwrap(), AST::Seq(vec![AST::AnyChar(false)]));
+    assert_eq!(parse("(?s).").unwrap(), AST::Seq(vec![AST::AnyChar(true)]));
+    assert_eq!(parse("(?s)(?-s).").unwrap(), AST::Seq(vec![AST::AnyChar(false)]));
+    assert_eq!(
+        parse("(?s:.).").unwrap(),
+        AST::Seq(vec![AST::Seq(vec![AST::AnyChar(true)]), AST::AnyChar(false)])
+    );
+    // anchor
+    assert_eq!(
+        parse("^a$").unwrap(),
+        AST::Seq(vec![
+            AST::Assert(Assertion::StartText),
+            AST::Char('a'),
+            AST::Assert(Assertion::EndText)
+        ])
+    );
+    assert_eq!(
+        parse("(?m)^a$").unwrap(),
+        AST::Seq(vec![
+            AST::Assert(Assertion::StartLine),
+            AST::Char('a'),
+            AST::Assert(Assertion::EndLine)
+        ])
+    );
+    let flags = Flags {
+        dot_nl: true,
+        multi_line: true,
+        ..Flags::default()
+    };
+    assert_eq!(
+        parse_with_flags("^.", flags).unwrap(),
+        AST::Seq(vec![AST::Assert(Assertion::StartLine), AST::AnyChar(true)])
+    );
+    // word boundary
+    assert_eq!(
+        parse("\\b\\B\\A\\z").unwrap(),
+        AST::Seq(vec![
+            AST::Assert(Assertion::WordBoundary),
+            AST::Assert(Assertion::NotWordBoundary),
+            AST::Assert(Assertion::StartText),
+            AST::Assert(Assertion::EndText)
+        ])
+    );
+    assert_eq!(
+        parse("(?m-u)\\b\\A(?u:\\B)").unwrap(),
+        AST::Seq(vec![
+            AST::Assert(Assertion::AsciiWordBoundary),
+            AST::Assert(Assertion::StartText),
+            AST::Seq(vec![AST::Assert(Assertion::NotWordBoundary)])
+        ])
+    );
+    assert_eq!(parse("(?-u)\\w").unwrap(), parse("[0-9A-Z_a-z]").unwrap());
+    // atomic
+    assert_eq!(
+        parse("(?>a)b").unwrap(),
+        AST::Seq(vec![AST::Atomic(Box::new(AST::Seq(vec![AST::Char('a')]))), AST::Char('b')])
+    );
+    assert_eq!(
+        parse("a*+b++").unwrap(),
+        AST::Seq(vec![
+            AST::Atomic(Box::new(AST::Star(Box::new(AST::Char('a'))))),
+            AST::Atomic(Box::new(AST::Plus(Box::new(AST::Char('b')))))
+        ])
+    );
+    assert!(parse("(?>a").is_err());
+    // escape
+    assert_eq!(parse("\\.\\^\\$").unwrap(), AST::Seq(vec![AST::Char('.'), AST::Char('^'), AST::Char('$')]));
+    assert_eq!(parse("\\n\\t").unwrap(), AST::Seq(vec![AST::Char('\n'), AST::Char('\t')]));
+
+    // abnormal case
+    assert!(parse("(?x)a").is_err());
+    assert!(parse("(?s").is_err());
+    assert!(parse("(?s)").is_err());
+    assert!(parse("(a").is_err());
+    assert!(parse("a|").is_err());
+    assert!(parse("(a|)").is_err());
+}
+
+#[test]
+fn test_class() {
+    let class = |ranges: &[(char, char)]| AST::Class(Class::new(ranges.to_vec()));
+    assert_eq!(parse("[abc]").unwrap(), AST::Seq(vec![class(&[('a', 'c')])]));
+    assert_eq!(parse("[a-cx]").unwrap(), AST::Seq(vec![class(&[('a', 'c'), ('x', 'x')])]));
+    assert_eq!(parse("[]a]").unwrap(), AST::Seq(vec![class(&[(']', ']'), ('a', 'a')])]));
+    assert_eq!(parse("[-a-]").unwrap(), AST::Seq(vec![class(&[('-', '-'), ('a', 'a')])]));
+    assert_eq!(parse("[\\]\\n]").unwrap(), AST::Seq(vec![class(&[('\n', '\n'), (']', ']')])]));
+    let mut negated = Class::new(vec![('0', '9')]);
+    negated.negate();
+    assert_eq!(parse("[^0-9]").unwrap(), AST::Seq(vec![AST::Class(negated)]));
+
+    // ignore case
+    assert_eq!(
+        parse("(?i)a1").unwrap(),
+        AST::Seq(vec![class(&[('A', 'A'), ('a', 'a')]), AST::Char('1')])
+    );
+    assert_eq!(parse("(?i)[a-c]").unwrap(), AST::Seq(vec![class(&[('A', 'C'), ('a', 'c')])]));
+    let mut negated = Class::new(vec![('A', 'A'), ('a', 'a')]);
+    negated.negate();
+    assert_eq!(parse("(?i)[^a]").unwrap(), AST::Seq(vec![AST::Class(negated)]));
+
+    // abnormal case
+    assert!(parse("[a").is_err());
+    assert!(parse("[]").is_err());
+    assert!(parse("[z-a]").is_err());
+    assert!(parse("[\\a]").is_err());
+}
+
+#[test]
+fn test_unicode_class() {
+    let class_of = |expr| match parse(expr).unwrap() {
+        AST::Seq(v) => match &v[..] {
+            [AST::Class(class)] => class.clone(),
+            _ => panic!("{expr}"),
+        },
+        _ => panic!("{expr}"),
+    };
+    let greek = class_of("\\p{Greek}");
+    assert!(greek.contains('α') && !greek.contains('a'));
+    assert_eq!(class_of("\\p{sc=Grek}"), greek);
+    let not_letter = class_of("\\PL");
+    assert!(not_letter.contains('1') && !not_letter.contains('字'));
+    assert!(class_of("\\w").contains('é') && !class_of("\\W").contains('é'));
+    assert!(class_of("\\d").contains('٣') && class_of("\\s").contains('\u{3000}'));
+    assert!(class_of("(?i)\\p{Lu}").contains('a'));
+
+    // 文字クラス内のプロパティと集合演算
+    let class = class_of("[\\p{Greek}\\d_]");
+    assert!(class.contains('λ') && class.contains('7') && class.contains('_'));
+    let class = class_of("[\\p{L}&&\\p{Greek}]");
+    assert!(class.contains('λ') && !class.contains('a') && !class.contains('\u{3f6}'));
+    let class = class_of("[a-z--aeiou]");
+    assert!(class.contains('b') && !class.contains('e'));
+    let class = class_of("[^\\w--\\d]");
+    assert!(class.contains('1') && class.contains(' ') && !class.contains('x'));
+    assert_eq!(class_of("[+--]"), Class::new(vec![('+', '-')]));
+    assert_eq!(class_of("[--/]"), Class::new(vec![('-', '/')]));
+
+    // abnormal case
+    assert!(parse("\\p{Klingon}").is_err());
+    assert!(parse("\\p{L").is_err());
+    assert!(parse("\\p").is_err());
+    assert!(parse("[a-\\d]").is_err());
+}
+
+#[test]
+fn test_posix() {
+    let with = |syntax| Flags {
+        syntax,
+        ..Flags::default()
+    };
+    let bre = |expr| parse_with_flags(expr, with(Syntax::Basic));
+    let ere = |expr| parse_with_flags(expr, with(Syntax::Extended));
+    let class = |ranges: &[(char, char)]| AST::Class(Class::new(ranges.to_vec()));
+    let a = || AST::Char('a');
+
+    // BREでは\を付けた場合のみ特殊文字
+    assert_eq!(bre("\\(a\\|b\\)*").unwrap(), parse("(a|b)*").unwrap());
+    assert_eq!(bre("a+?|(){}").unwrap(), parse("a\\+\\?\\|\\(\\)\\{\\}").unwrap());
+    assert_eq!(bre("a\\+").unwrap(), parse("a+").unwrap());
+    // 先頭の*、先頭以外の^、末尾以外の$は通常の文字
+    assert_eq!(bre("*a^b$c").unwrap(), parse("\\*a\\^b\\$c").unwrap());
+    assert_eq!(bre("^*a$").unwrap(), parse("^\\*a$").unwrap());
+    assert_eq!(bre("\\(^a$\\|*\\)").unwrap(), parse("(^a$|\\*)").unwrap());
+    assert_eq!(ere("(a|b)+$").unwrap(), parse("(a|b)+$").unwrap());
+
+    // 回数の指定
+    let seq = |v: Vec<AST>| AST::Seq(vec![AST::Seq(v)]);
+    assert_eq!(ere("a{2}").unwrap(), seq(vec![a(), a()]));
+    assert_eq!(
+        ere("a{1,3}").unwrap(),
+        seq(vec![a(), AST::Question(Box::new(AST::Seq(vec![a(), AST::Question(Box::new(a()))])))])
+    );
+    assert_eq!(ere("a{1,}").unwrap(), seq(vec![a(), AST::Star(Box::new(a()))]));
+    assert_eq!(ere("a{,1}").unwrap(), seq(vec![AST::Question(Box::new(a()))]));
+    assert_eq!(bre("a\\{2\\}").unwrap(), ere("a{2}").unwrap());
+    assert_eq!(ere("a{x}").unwrap(), parse("a{x}").unwrap());
+    assert!(ere("a{3,2}").is_err());
+    assert!(ere("a{1001}").is_err());
+    // 展開後のノード数の上限
+    assert!(ere("(a{100}){100}").is_ok());
+    assert!(ere("(a{1000}){1000}").is_err());
+    assert!(ere("((a{1000}){1000}){1000}").is_err());
+    assert!(ere("(a{1000}){1000,}").is_err());
+    assert!(ere("a{1").is_err());
+    assert!(ere("{1}").is_err());
+    assert!(bre("a\\{1}").is_err());
+
+    // 文字クラス名と、通常の文字となる\
+    assert_eq!(ere("[[:digit:]_]").unwrap(), AST::Seq(vec![class(&[('0', '9'), ('_', '_')])]));
+    assert_eq!(bre("[\\n]").unwrap(), AST::Seq(vec![class(&[('\\', '\\'), ('n', 'n')])]));
+    assert_eq!(ere("[[=a=]-[.c.]]").unwrap(), AST::Seq(vec![class(&[('a', 'c')])]));
+    assert_eq!(ere("[+--]").unwrap(), AST::Seq(vec![class(&[('+', '-')])]));
+    assert_eq!(ere("[[a]").unwrap(), AST::Seq(vec![class(&[('[', '['), ('a', 'a')])]));
+    assert!(ere("[[:foo:]]").is_err());
+    assert!(ere("[[=ab=]]").is_err());
+    assert!(ere("[[:alpha:]").is_err());
+
+    // このクレートの拡張は使えない
+    assert!(ere("(?i)a").is_err());
+    assert_eq!(
+        ere("a*+").unwrap(),
+        AST::Seq(vec![AST::Plus(Box::new(AST::Star(Box::new(a()))))])
+    );
+    assert!(ere("\\w").is_ok());
 }
@@ -0,0 +1,768 @@
+//! コンパイル済みの命令列の保存と読み込み
+//!
+//! バイナリ形式とテキスト形式の2種類を扱う。
+//! いずれも命令列のほか、フラグ、探索方法、キャプチャグループの名前を含む。
+//!
+//! バイナリ形式はリトルエンディアンで、整数は全てu32とする。
+//!
+//! ```text
+//! magic    : b"RGXP"
+//! version  : u16
+//! flags    : u8（ビット0から順にs、m、i、深さ優先探索、ASCII（-u）。
+//!            ビット5と6は構文で、0: 独自、1: BRE、2: ERE）
+//! names    : u32 個数、各要素は u8（0: 名前なし、1: 名前あり）と、名前ありの場合は u32 長さ + UTF-8
+//! code     : u32 個数、各要素は u8 の命令コードとオペランド
+//! ```
+//!
+//! テキスト形式は以下のような行単位の形式で、文字はRustの文字リテラルと同じく表記する。
+//!
+//! ```text
+//! version 1
+//! flags m
+//! method dfs
+//! groups 2
+//! name 1 key
+//! code 4
+//! 0000: save 2
+//! 0001: class 'a'-'z'
+//! 0002: save 3
+//! 0003: match
+//! ```
+use super::{
+    class::Class,
+    parser::{Assertion, Syntax},
+    Flags, Instruction, Regex,
+};
+use std::{
+    error::Error,
+    fmt::{self, Display, Write},
+};
+
+/// バイナリ形式の先頭のマジックナンバー
+const MAGIC: &[u8; 4] = b"RGXP";
+
+/// 形式のバージョン。互換性のない変更を行った場合に上げる
+pub const FORMAT_VERSION: u16 = 1;
+
+// 命令コード
+const OP_CHAR: u8 = 0;
+const OP_ANY: u8 = 1;
+const OP_ANY_NL: u8 = 2;
+const OP_CLASS: u8 = 3;
+const OP_ASSERT: u8 = 4;
+const OP_SAVE: u8 = 5;
+const OP_MATCH: u8 = 6;
+const OP_JUMP: u8 = 7;
+const OP_SPLIT: u8 = 8;
+const OP_MATCH_TAG: u8 = 9; // 番号が0以外のmatch
+const OP_ATOMIC: u8 = 10;
+
+/// 読み込み時のエラーを表す型
+#[derive(Debug)]
+pub enum LoadError {
+    BadMagic,                    // マジックナンバーが異なる
+    UnsupportedVersion(u16),     // 対応していないバージョン
+    UnexpectedEnd,               // 途中で入力が終わった
+    TrailingData(usize),         // 末尾に余分なデータがある。値は位置
+    InvalidChar(u32),            // 文字として不正な値
+    InvalidOpcode(usize, u8),    // 不正な命令コード。値は命令のアドレスとコード
+    InvalidOperand(usize),       // 不正なオペランド。値は命令のアドレス
+    InvalidJump(usize, usize),   // 範囲外へのジャンプ。値は命令のアドレスと飛び先
+    FallThrough(usize),          // 命令列の末尾を越えて次の命令に進む。値は命令のアドレス
+    InvalidName(usize),          // 不正なキャプチャグループの名前。値はグループの番号
+    Syntax(usize, &'static str), // テキスト形式の構文エラー。値は行番号（1始まり）と内容
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::BadMagic => write!(f, "LoadError: bad magic number"),
+            LoadError::UnsupportedVersion(v) => {
+                write!(f, "LoadError: unsupported version: version = {v}")
+            }
+            LoadError::UnexpectedEnd => write!(f, "LoadError: unexpected end of input"),
+            LoadError::TrailingData(pos) => write!(f, "LoadError: trailing data: pos = {pos}"),
+            LoadError::InvalidChar(c) => write!(f, "LoadError: invalid char: value = {c:#x}"),
+            LoadError::InvalidOpcode(pc, op) => {
+                write!(f, "LoadError: invalid opcode: pc = {pc}, opcode = {op}")
+            }
+            LoadError::InvalidOperand(pc) => write!(f, "LoadError: invalid operand: pc = {pc}"),
+            LoadError::InvalidJump(pc, addr) => {
+                write!(
+                    f,
+                    "LoadError: invalid jump target: pc = {pc}, addr = {addr}"
+                )
+            }
+            LoadError::FallThrough(pc) => {
+                write!(f, "LoadError: falls off the end of code: pc = {pc}")
+            }
+            LoadError::InvalidName(n) => write!(f, "LoadError: invalid group name: group = {n}"),
+            LoadError::Syntax(line, msg) => {
+                write!(f, "LoadError: syntax error: line = {line}, {msg}")
+            }
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+/// フラグと探索方法を1バイトに変換
+fn encode_flags(flags: Flags, is_depth: bool) -> u8 {
+    flags.dot_nl as u8
+        | (flags.multi_line as u8) << 1
+        | (flags.ignore_case as u8) << 2
+        | (is_depth as u8) << 3
+        | (flags.ascii as u8) << 4
+        | syntax_code(flags.syntax) << 5
+}
+
+/// 1バイトからフラグと探索方法を復元
+fn decode_flags(b: u8) -> Option<(Flags, bool)> {
+    if b & !0b111_1111 != 0 {
+        return None;
+    }
+    let flags = Flags {
+        dot_nl: b & 1 != 0,
+        multi_line: b & 2 != 0,
+        ignore_case: b & 4 != 0,
+        ascii: b & 16 != 0,
+        syntax: syntax_from_code(b >> 5)?,
+    };
+    Some((flags, b & 8 != 0))
+}
+
+fn syntax_code(syntax: Syntax) -> u8 {
+    match syntax {
+        Syntax::Native => 0,
+        Syntax::Basic => 1,
+        Syntax::Extended => 2,
+    }
+}
+
+fn syntax_from_code(b: u8) -> Option<Syntax> {
+    match b {
+        0 => Some(Syntax::Native),
+        1 => Some(Syntax::Basic),
+        2 => Some(Syntax::Extended),
+        _ => None,
+    }
+}
+
+fn assert_code(a: Assertion) -> u8 {
+    match a {
+        Assertion::StartLine => 0,
+        Assertion::EndLine => 1,
+        Assertion::StartText => 2,
+        Assertion::EndText => 3,
+        Assertion::WordBoundary => 4,
+        Assertion::NotWordBoundary => 5,
+        Assertion::AsciiWordBoundary => 6,
+        Assertion::AsciiNotWordBoundary => 7,
+    }
+}
+
+fn assert_from_code(b: u8) -> Option<Assertion> {
+    match b {
+        0 => Some(Assertion::StartLine),
+        1 => Some(Assertion::EndLine),
+        2 => Some(Assertion::StartText),
+        3 => Some(Assertion::EndText),
+        4 => Some(Assertion::WordBoundary),
+        5 => Some(Assertion::NotWordBoundary),
+        6 => Some(Assertion::AsciiWordBoundary),
+        7 => Some(Assertion::AsciiNotWordBoundary),
+        _ => None,
+    }
+}
+
+/// バイナリ形式に変換
+pub fn to_bytes(re: &Regex) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend(MAGIC);
+    out.extend(FORMAT_VERSION.to_le_bytes());
+    out.push(encode_flags(re.flags, re.is_depth));
+
+    let put = |out: &mut Vec<u8>, n: usize| out.extend((n as u32).to_le_bytes());
+    put(&mut out, re.names.len());
+    for name in &re.names {
+        match name {
+            Some(name) => {
+                out.push(1);
+                put(&mut out, name.len());
+                out.extend(name.as_bytes());
+            }
+            None => out.push(0),
+        }
+    }
+
+    put(&mut out, re.code.len());
+    for inst in &re.code {
+        match inst {
+            Instruction::Char(c) => {
+                out.push(OP_CHAR);
+                put(&mut out, *c as usize);
+            }
+            Instruction::AnyChar(false) => out.push(OP_ANY),
+            Instruction::AnyChar(true) => out.push(OP_ANY_NL),
+            Instruction::Class(class) => {
+                out.push(OP_CLASS);
+                put(&mut out, class.ranges().len());
+                for (lo, hi) in class.ranges() {
+                    put(&mut out, *lo as usize);
+                    put(&mut out, *hi as usize);
+                }
+            }
+            Instruction::Assert(a) => {
+                out.push(OP_ASSERT);
+                out.push(assert_code(*a));
+            }
+            Instruction::Save(n) => {
+                out.push(OP_SAVE);
+                put(&mut out, *n);
+            }
+            Instruction::Match(0) => out.push(OP_MATCH),
+            Instruction::Match(tag) => {
+                out.push(OP_MATCH_TAG);
+                put(&mut out, *tag);
+            }
+            Instruction::Jump(addr) => {
+                out.push(OP_JUMP);
+                put(&mut out, *addr);
+            }
+            Instruction::Split(addr1, addr2) => {
+                out.push(OP_SPLIT);
+                put(&mut out, *addr1);
+                put(&mut out, *addr2);
+            }
+            Instruction::Atomic(addr) => {
+                out.push(OP_ATOMIC);
+                put(&mut out, *addr);
+            }
+        }
+    }
+    out
+}
+
+/// バイナリ形式の読み込み位置
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], LoadError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(LoadError::UnexpectedEnd)?;
+        let s = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(s)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<usize, LoadError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn char(&mut self) -> Result<char, LoadError> {
+        let n = self.u32()? as u32;
+        char::from_u32(n).ok_or(LoadError::InvalidChar(n))
+    }
+
+    /// 要素数を読み込む。
+    ///
+    /// 不正な入力で巨大な領域を確保しないよう、1要素をsizeバイト以上として残りの長さで制限する。
+    fn count(&mut self, size: usize) -> Result<usize, LoadError> {
+        let n = self.u32()?;
+        if n.saturating_mul(size) > self.bytes.len() - self.pos {
+            return Err(LoadError::UnexpectedEnd);
+        }
+        Ok(n)
+    }
+}
+
+/// バイナリ形式から読み込む
+pub fn from_bytes(bytes: &[u8]) -> Result<Regex, LoadError> {
+    let mut r = Reader { bytes, pos: 0 };
+    if r.take(MAGIC.len()).map_err(|_| LoadError::BadMagic)? != MAGIC {
+        return Err(LoadError::BadMagic);
+    }
+    let version = r.u16()?;
+    if version != FORMAT_VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+    let b = r.u8()?;
+    let (flags, is_depth) = decode_flags(b).ok_or(LoadError::InvalidOperand(0))?;
+
+    let n = r.count(1)?;
+    let mut names = Vec::with_capacity(n);
+    for i in 0..n {
+        let name = match r.u8()? {
+            0 => None,
+            1 => {
+                let len = r.u32()?;
+                let s = std::str::from_utf8(r.take(len)?).map_err(|_| LoadError::InvalidName(i))?;
+                Some(s.to_string())
+            }
+            _ => return Err(LoadError::InvalidName(i)),
+        };
+        names.push(name);
+    }
+
+    let n = r.count(1)?;
+    let mut code = Vec::with_capacity(n);
+    for pc in 0..n {
+        let inst = match r.u8()? {
+            OP_CHAR => Instruction::Char(r.char()?),
+            OP_ANY => Instruction::AnyChar(false),
+            OP_ANY_NL => Instruction::AnyChar(true),
+            OP_CLASS => {
+                let len = r.count(8)?;
+                let mut ranges = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let (lo, hi) = (r.char()?, r.char()?);
+                    if lo > hi {
+                        return Err(LoadError::InvalidOperand(pc));
+                    }
+                    ranges.push((lo, hi));
+                }
+                Instruction::Class(Class::new(ranges))
+            }
+            OP_ASSERT => {
+                let a = assert_from_code(r.u8()?).ok_or(LoadError::InvalidOperand(pc))?;
+                Instruction::Assert(a)
+            }
+            OP_SAVE => Instruction::Save(r.u32()?),
+            OP_MATCH => Instruction::Match(0),
+            OP_MATCH_TAG => Instruction::Match(r.u32()?),
+            OP_JUMP => Instruction::Jump(r.u32()?),
+            OP_SPLIT => Instruction::Split(r.u32()?, r.u32()?),
+            OP_ATOMIC => Instruction::Atomic(r.u32()?),
+            op => return Err(LoadError::InvalidOpcode(pc, op)),
+        };
+        code.push(inst);
+    }
+    if r.pos != bytes.len() {
+        return Err(LoadError::TrailingData(r.pos));
+    }
+
+    build(code, flags, is_depth, names)
+}
+
+/// テキスト形式に変換
+pub fn to_text(re: &Regex) -> String {
+    let mut out = String::new();
+    let _ = write_text(re, &mut out);
+    out
+}
+
+fn write_text(re: &Regex, out: &mut String) -> fmt::Result {
+    writeln!(out, "version {FORMAT_VERSION}")?;
+    let flags = [
+        (re.flags.dot_nl, " s"),
+        (re.flags.multi_line, " m"),
+        (re.flags.ignore_case, " i"),
+        (re.flags.ascii, " ascii"),
+        (re.flags.syntax == Syntax::Basic, " bre"),
+        (re.flags.syntax == Syntax::Extended, " ere"),
+    ];
+    write!(out, "flags")?;
+    for (_, f) in flags.iter().filter(|(on, _)| *on) {
+        out.push_str(f);
+    }
+    writeln!(out)?;
+    writeln!(out, "method {}", if re.is_depth { "dfs" } else { "bfs" })?;
+    writeln!(out, "groups {}", re.names.len())?;
+    for (i, name) in re.names.iter().enumerate() {
+        if let Some(name) = name {
+            writeln!(out, "name {i} {name}")?;
+        }
+    }
+    writeln!(out, "code {}", re.code.len())?;
+    for (pc, inst) in re.code.iter().enumerate() {
+        write!(out, "{pc:>04}: ")?;
+        match inst {
+            Instruction::Char(c) => writeln!(out, "char {}", quote(*c))?,
+            Instruction::Class(class) => {
+                write!(out, "class")?;
+                for (lo, hi) in class.ranges() {
+                    if lo == hi {
+                        write!(out, " {}", quote(*lo))?;
+                    } else {
+                        write!(out, " {}-{}", quote(*lo), quote(*hi))?;
+                    }
+                }
+                writeln!(out)?;
+            }
+            Instruction::Jump(addr) => writeln!(out, "jump {addr}")?,
+            Instruction::Split(addr1, addr2) => writeln!(out, "split {addr1}, {addr2}")?,
+            Instruction::Atomic(addr) => writeln!(out, "atomic {addr}")?,
+            _ => writeln!(out, "{inst}")?,
+        }
+    }
+    Ok(())
+}
+
+/// 文字をRustの文字リテラルと同じ形式で表記
+fn quote(c: char) -> String {
+    format!("{c:?}")
+}
+
+/// テキスト形式から読み込む
+pub fn from_text(text: &str) -> Result<Regex, LoadError> {
+    let mut lines = Lines {
+        lines: text
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l.trim()))
+            .filter(|(_, l)| !l.is_empty() && !l.starts_with('#'))
+            .collect(),
+        pos: 0,
+    };
+
+    let (n, v) = lines.field("version")?;
+    let version = v
+        .parse::<u16>()
+        .map_err(|_| LoadError::Syntax(n, "invalid version"))?;
+    if version != FORMAT_VERSION {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+
+    let (n, v) = lines.field("flags")?;
+    let mut flags = Flags::default();
+    for f in v.split_whitespace() {
+        match f {
+            "s" => flags.dot_nl = true,
+            "m" => flags.multi_line = true,
+            "i" => flags.ignore_case = true,
+            "ascii" => flags.ascii = true,
+            "bre" => flags.syntax = Syntax::Basic,
+            "ere" => flags.syntax = Syntax::Extended,
+            _ => return Err(LoadError::Syntax(n, "invalid flag")),
+        }
+    }
+
+    let is_depth = match lines.field("method")? {
+        (_, "dfs") => true,
+        (_, "bfs") => false,
+        (n, _) => return Err(LoadError::Syntax(n, "invalid method")),
+    };
+
+    let (n, v) = lines.field("groups")?;
+    let groups = parse_number(n, v)?;
+    // グループごとにsave命令が2つ必要なため、行数を超えるグループ数は不正
+    if groups > lines.lines.len() * 2 + 1 {
+        return Err(LoadError::Syntax(n, "too many groups"));
+    }
+    let mut names = vec![None; groups];
+    while lines.peek_key() == Some("name") {
+        let (n, v) = lines.field("name")?;
+        let (i, name) = v.split_once(' ').unwrap_or((v, ""));
+        let i = parse_number(n, i)?;
+        match names.get_mut(i) {
+            Some(slot) if slot.is_none() && !name.is_empty() => *slot = Some(name.to_string()),
+            _ => return Err(LoadError::InvalidName(i)),
+        }
+    }
+
+    let (n, v) = lines.field("code")?;
+    let len = parse_number(n, v)?;
+    if len > lines.lines.len() {
+        return Err(LoadError::UnexpectedEnd);
+    }
+    let mut code = Vec::with_capacity(len);
+    for pc in 0..len {
+        let (n, line) = lines.next()?;
+        let (addr, inst) = line
+            .split_once(':')
+            .ok_or(LoadError::Syntax(n, "missing address"))?;
+        if parse_number(n, addr)? != pc {
+            return Err(LoadError::Syntax(n, "address mismatch"));
+        }
+        code.push(parse_inst(n, inst.trim())?);
+    }
+    if let Some((n, _)) = lines.lines.get(lines.pos) {
+        return Err(LoadError::TrailingData(*n));
+    }
+
+    build(code, flags, is_depth, names)
+}
+
+/// テキスト形式の行。空行と`#`で始まる行は除く
+struct Lines<'a> {
+    lines: Vec<(usize, &'a str)>, // 行番号と内容
+    pos: usize,
+}
+
+impl<'a> Lines<'a> {
+    fn next(&mut self) -> Result<(usize, &'a str), LoadError> {
+        let line = *self.lines.get(self.pos).ok_or(LoadError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(line)
+    }
+
+    /// 次の行の先頭の単語
+    fn peek_key(&self) -> Option<&'a str> {
+        let (_, line) = self.lines.get(self.pos)?;
+        line.split_whitespace().next()
+    }
+
+    /// `key 値`の形式の行を読み込み、行番号と値を返す
+    fn field(&mut self, key: &'static str) -> Result<(usize, &'a str), LoadError> {
+        let (n, line) = self.next()?;
+        match line.split_once(' ') {
+            Some((k, v)) if k == key => Ok((n, v.trim())),
+            None if line == key => Ok((n, "")),
+            _ => Err(LoadError::Syntax(n, key)),
+        }
+    }
+}
+
+fn parse_number(n: usize, s: &str) -> Result<usize, LoadError> {
+    s.trim()
+        .parse::<usize>()
+        .map_err(|_| LoadError::Syntax(n, "invalid number"))
+}
+
+/// 1命令をパース。nは行番号
+fn parse_inst(n: usize, s: &str) -> Result<Instruction, LoadError> {
+    let (op, arg) = s.split_once(' ').unwrap_or((s, ""));
+    let inst = match (op, arg) {
+        ("char", arg) => match parse_char(arg) {
+            Some((c, "")) => Instruction::Char(c),
+            _ => return Err(LoadError::Syntax(n, "invalid char")),
+        },
+        ("any", "") => Instruction::AnyChar(false),
+        ("any", "newline") => Instruction::AnyChar(true),
+        ("class", mut rest) => {
+            let mut ranges = Vec::new();
+            loop {
+                rest = rest.trim_start();
+                if rest.is_empty() {
+                    break;
+                }
+                let (lo, r) = parse_char(rest).ok_or(LoadError::Syntax(n, "invalid class"))?;
+                let (hi, r) = match r.strip_prefix('-') {
+                    Some(r) => parse_char(r).ok_or(LoadError::Syntax(n, "invalid class"))?,
+                    None => (lo, r),
+                };
+                if lo > hi {
+                    return Err(LoadError::Syntax(n, "invalid class"));
+                }
+                ranges.push((lo, hi));
+                rest = r;
+            }
+            Instruction::Class(Class::new(ranges))
+        }
+        ("assert", arg) => {
+            let a = (0..=u8::MAX)
+                .map_while(assert_from_code)
+                .find(|a| a.to_string() == arg)
+                .ok_or(LoadError::Syntax(n, "invalid assertion"))?;
+            Instruction::Assert(a)
+        }
+        ("save", arg) => Instruction::Save(parse_number(n, arg)?),
+        ("match", "") => Instruction::Match(0),
+        ("match", arg) => Instruction::Match(parse_number(n, arg)?),
+        ("jump", arg) => Instruction::Jump(parse_number(n, arg)?),
+        ("split", arg) => {
+            let (a1, a2) = arg
+                .split_once(',')
+                .ok_or(LoadError::Syntax(n, "invalid split"))?;
+            Instruction::Split(parse_number(n, a1)?, parse_number(n, a2)?)
+        }
+        ("atomic", arg) => Instruction::Atomic(parse_number(n, arg)?),
+        _ => return Err(LoadError::Syntax(n, "invalid instruction")),
+    };
+    Ok(inst)
+}
+
+/// 先頭の文字リテラル（`'a'`、`'\n'`、`'\u{1f600}'`など）をパースし、文字と残りを返す
+fn parse_char(s: &str) -> Option<(char, &str)> {
+    let s = s.strip_prefix('\'')?;
+    let mut chars = s.chars();
+    let c = match chars.next()? {
+        '\\' => match chars.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            c @ ('\\' | '\'' | '"') => c,
+            'u' => {
+                let rest = chars.as_str().strip_prefix('{')?;
+                let (hex, rest) = rest.split_once('}')?;
+                chars = rest.chars();
+                char::from_u32(u32::from_str_radix(hex, 16).ok()?)?
+            }
+            _ => return None,
+        },
+        '\'' => return None,
+        c => c,
+    };
+    let rest = chars.as_str().strip_prefix('\'')?;
+    Some((c, rest))
+}
+
+/// 読み込んだ命令列を検証し、正規表現を生成。
+///
+/// 評価時に範囲外を参照しないよう、ジャンプ先とスロットの番号、
+/// および末尾の命令が次の命令に進まないことを確認する。
+fn build(
+    code: Vec<Instruction>,
+    flags: Flags,
+    is_depth: bool,
+    names: Vec<Option<String>>,
+) -> Result<Regex, LoadError> {
+    // 0番目のグループはマッチ全体で、名前を持たない
+    match names.first() {
+        Some(None) => (),
+        _ => return Err(LoadError::InvalidName(0)),
+    }
+    for (pc, inst) in code.iter().enumerate() {
+        match inst {
+            Instruction::Jump(addr) if *addr >= code.len() => {
+                return Err(LoadError::InvalidJump(pc, *addr));
+            }
+            Instruction::Split(addr1, addr2) => {
+                if let Some(addr) = [addr1, addr2].into_iter().find(|a| **a >= code.len()) {
+                    return Err(LoadError::InvalidJump(pc, *addr));
+                }
+            }
+            // アトミックグループの終わりは、グループより後の命令とする
+            Instruction::Atomic(addr) if *addr <= pc || *addr >= code.len() => {
+                return Err(LoadError::InvalidJump(pc, *addr));
+            }
+            Instruction::Save(n) if *n >= names.len() * 2 => {
+                return Err(LoadError::InvalidOperand(pc));
+            }
+            Instruction::Match(_) | Instruction::Jump(_) => (),
+            _ if pc + 1 >= code.len() => return Err(LoadError::FallThrough(pc)),
+            _ => (),
+        }
+    }
+    if code.is_empty() {
+        return Err(LoadError::UnexpectedEnd);
+    }
+    Ok(Regex {
+        code,
+        is_depth,
+        flags,
+        names,
+        jit: None,
+        cache: Default::default(),
+    })
+}
+
+#[test]
+fn test() {
+    let exprs = [
+        "a(?<key>[a-z_]+)=(?:[0-9]|x)*$",
+        "(?ims)^.'\\n\\t[^ -\\-]|\u{1f600}?",
+        "(a|b(c))+",
+        "\\bfoo\\B\\A(?-u:\\b\\B\\w)\\z",
+    ];
+    for expr in exprs {
+        for is_depth in [true, false] {
+            let flags = Flags {
+                multi_line: true,
+                ..Flags::default()
+            };
+            let re = Regex::with_flags(expr, flags, is_depth).unwrap();
+            let listing = |re: &Regex| {
+                let code = re.code.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+                (code, re.names.clone(), re.flags, re.is_depth)
+            };
+
+            let bytes = re.to_bytes();
+            let loaded = Regex::from_bytes(&bytes).unwrap();
+            assert_eq!(listing(&loaded), listing(&re));
+
+            let text = re.to_text();
+            let loaded = Regex::from_text(&text).unwrap();
+            assert_eq!(listing(&loaded), listing(&re));
+            assert_eq!(loaded.to_bytes(), bytes);
+
+            // 途中で切れた入力や、余分なデータのある入力はエラー
+            for len in 0..bytes.len() {
+                assert!(from_bytes(&bytes[..len]).is_err());
+            }
+            let mut extra = bytes.clone();
+            extra.push(0);
+            assert!(matches!(
+                from_bytes(&extra),
+                Err(LoadError::TrailingData(_))
+            ));
+        }
+    }
+
+    // 範囲外へのジャンプ
+    let re = Regex::new("a*", true).unwrap();
+    let text = re.to_text().replace("jump 0", "jump 9");
+    assert!(matches!(
+        from_text(&text),
+        Err(LoadError::InvalidJump(_, 9))
+    ));
+    let mut bytes = re.to_bytes();
+    let n = bytes.len();
+    bytes[n - 5..n - 1].copy_from_slice(&9u32.to_le_bytes()); // 末尾のmatchの直前のjumpの飛び先
+    assert!(matches!(
+        from_bytes(&bytes),
+        Err(LoadError::InvalidJump(2, 9))
+    ));
+    let mut bytes = re.to_bytes();
+    bytes[4] = 99;
+    assert!(matches!(
+        from_bytes(&bytes),
+        Err(LoadError::UnsupportedVersion(99))
+    ));
+    assert!(matches!(from_bytes(b"XXXX"), Err(LoadError::BadMagic)));
+
+    // 番号付きのmatch命令
+    let text = re.to_text().replace("match", "match 3");
+    let loaded = from_text(&text).unwrap();
+    assert!(matches!(loaded.code.last(), Some(Instruction::Match(3))));
+    assert_eq!(from_bytes(&loaded.to_bytes()).unwrap().to_text(), text);
+
+    // 構文はフラグとして保存する
+    let flags = Flags {
+        syntax: Syntax::Basic,
+        ..Flags::default()
+    };
+    let re = Regex::with_flags("a\\{2\\}", flags, true).unwrap();
+    assert!(re.to_text().contains("flags bre\n"));
+    assert_eq!(from_bytes(&re.to_bytes()).unwrap().flags, flags);
+    assert_eq!(from_text(&re.to_text()).unwrap().flags, flags);
+
+    // アトミックグループ。終わりはグループより後の命令とする
+    let re = Regex::new("(?>a|ab)c*+", true).unwrap();
+    let text = re.to_text();
+    assert!(text.contains("0000: atomic 6\n") && text.contains("0006: atomic 10\n"));
+    assert_eq!(from_text(&text).unwrap().to_bytes(), re.to_bytes());
+    assert!(matches!(
+        from_text(&text.replace("atomic 6", "atomic 0")),
+        Err(LoadError::InvalidJump(0, 0))
+    ));
+
+    // 全てのバイトを1つずつ書き換えても、パニックしない
+    let re = Regex::new("(?<x>a[b-d])+|$", false).unwrap();
+    let bytes = re.to_bytes();
+    for i in 0..bytes.len() {
+        for b in [0, 1, 0x7f, 0xff] {
+            let mut corrupt = bytes.clone();
+            corrupt[i] = b;
+            if let Ok(re) = from_bytes(&corrupt) {
+                let line = "abcad".chars().collect::<Vec<char>>();
+                let _ = re.find_captures_at(&line, 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! std::io::Readから読み込みながらマッチングを行う
+use super::{
+    evaluator::{self, Cache, Partial, Stats},
+    Error as RegexError, Regex,
+};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io::{ErrorKind, Read},
+    ops::Range,
+};
+
+/// 一度に読み込むバイト数
+pub const CHUNK_SIZE: usize = 8 * 1024;
+
+/// バッファに保持する文字数の上限の初期値
+pub const BUFFER_LIMIT: usize = 1024 * 1024;
+
+/// ストリームでのマッチング時のエラーを表す型
+#[derive(Debug)]
+pub enum StreamError {
+    BufferOverFlow(usize), // マッチの確定に必要な文字数がバッファの上限を超えた。値は探索開始のバイト位置
+}
+
+impl Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamError::BufferOverFlow(pos) => {
+                write!(f, "StreamError: buffer overflow: pos = {pos}")
+            }
+        }
+    }
+}
+
+impl Error for StreamError {}
+
+/// [Regex::stream_find]が返すイテレータ
+///
+/// 入力をCHUNK_SIZEバイトずつ読み込み、文字に変換してバッファに追加する。
+/// マッチ範囲はストリーム先頭からのバイト単位の位置で返す。
+/// 不正なバイト列は置換文字1文字として評価し、位置は元のバイト列での位置とする。
+///
+/// バッファには、探索開始位置の直前の1文字（^の判定用）から読み込み済みの末尾までを保持し、
+/// 探索開始位置が進むとそれより前は破棄する。
+/// チャンクの境界をまたぐマッチは、続きを読み込んでから評価し直すことで扱う。
+pub struct StreamMatches<'a, R> {
+    regex: &'a Regex,
+    reader: R,
+    bytes: Vec<u8>,      // 文字に変換できていないバイト列（UTF-8の途中など）
+    buf: Vec<char>,      // 読み込み済みの文字
+    offsets: Vec<usize>, // bufの各文字の、ストリーム上のバイト位置
+    byte: usize,         // bufの末尾の、ストリーム上のバイト位置
+    base: usize,         // buf[0]のストリーム上の文字位置
+    pos: usize,          // 次の探索開始位置（ストリーム上の文字位置）
+    eof: bool,           // 入力を最後まで読み込んだか
+    done: bool,          // 探索を終了したか
+    limit: usize,        // バッファに保持する文字数の上限
+    stats: Stats,        // これまでの評価の統計情報
+    cache: Cache,        // 評価の作業領域
+}
+
+impl<'a, R: Read> StreamMatches<'a, R> {
+    pub(super) fn new(regex: &'a Regex, reader: R) -> Self {
+        StreamMatches {
+            regex,
+            reader,
+            bytes: Vec::new(),
+            buf: Vec::new(),
+            offsets: Vec::new(),
+            byte: 0,
+            base: 0,
+            pos: 0,
+            eof: false,
+            done: false,
+            limit: BUFFER_LIMIT,
+            stats: Stats::default(),
+            cache: Cache::default(),
+        }
+    }
+
+    /// バッファに保持する文字数の上限を設定。
+    ///
+    /// 1つの探索開始位置からマッチを確定させるまでに上限を超える文字数が必要な場合、
+    /// StreamError::BufferOverFlowを返して探索を終了する。
+    pub fn buffer_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// これまでの評価の統計情報。
+    ///
+    /// チャンクの境界をまたいで評価し直した分も含む。
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// CHUNK_SIZEバイト読み込み、文字に変換してバッファに追加
+    fn fill(&mut self) -> Result<(), RegexError> {
+        let mut chunk = [0; CHUNK_SIZE];
+        let n = loop {
+            match self.reader.read(&mut chunk) {
+                Ok(n) => break n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        if n == 0 {
+            self.eof = true;
+            // 末尾に残った不完全なバイト列は置換文字とする
+            if !self.bytes.is_empty() {
+                self.buf.push(char::REPLACEMENT_CHARACTER);
+                self.offsets.push(self.byte);
+                self.byte += self.bytes.len();
+                self.bytes.clear();
+            }
+            return Ok(());
+        }
+
+        self.bytes.extend_from_slice(&chunk[..n]);
+        let mut rest = &self.bytes[..];
+        let (buf, offsets, byte) = (&mut self.buf, &mut self.offsets, &mut self.byte);
+        // 文字をバッファに追加し、そのバイト位置を記録
+        let mut push = |c: char, len: usize| {
+            buf.push(c);
+            offsets.push(*byte);
+            *byte += len;
+        };
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(s) => {
+                    s.chars().for_each(|c| push(c, c.len_utf8()));
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // valid_up_toまではUTF-8として正しいことが保証されている
+                    let valid = std::str::from_utf8(valid).unwrap();
+                    valid.chars().for_each(|c| push(c, c.len_utf8()));
+                    match e.error_len() {
+                        // 不正なバイト列は置換文字とする
+                        Some(len) => {
+                            push(char::REPLACEMENT_CHARACTER, len);
+                            rest = &after[len..];
+                        }
+                        // 文字の途中で終わっている場合は、続きを読み込むまで保持
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let consumed = self.bytes.len() - rest.len();
+        self.bytes.drain(..consumed);
+        Ok(())
+    }
+
+    /// 探索開始位置の直前の1文字より前を破棄
+    ///
+    /// 毎回破棄するとコピーが多くなるため、CHUNK_SIZE文字以上たまった場合のみ行う。
+    fn compact(&mut self) {
+        let drop = (self.pos - self.base).saturating_sub(1);
+        if drop >= CHUNK_SIZE {
+            let drop = drop.min(self.buf.len());
+            self.buf.drain(..drop);
+            self.offsets.drain(..drop);
+            self.base += drop;
+        }
+    }
+
+    /// bufの添字iの文字の、ストリーム上のバイト位置。末尾の場合は読み込み済みのバイト数
+    fn byte_pos(&self, i: usize) -> usize {
+        self.offsets.get(i).copied().unwrap_or(self.byte)
+    }
+
+    /// 次のマッチを探す
+    fn find_next(&mut self) -> Result<Option<Range<usize>>, RegexError> {
+        loop {
+            let start = self.pos - self.base;
+
+            // 探索開始位置の文字が未読の場合は読み込む
+            if start >= self.buf.len() && !self.eof {
+                self.fill()?;
+                continue;
+            }
+            if start > self.buf.len() {
+                return Ok(None);
+            }
+
+            let ret = evaluator::eval_partial(
+                &self.regex.code,
+                &self.buf,
+                start,
+                self.regex.is_depth,
+                self.eof,
+                &mut self.stats,
+                &mut self.cache,
+            )?;
+            match ret {
+                Partial::Incomplete => {
+                    if self.buf.len() - start >= self.limit {
+                        let pos = self.byte_pos(start);
+                        return Err(StreamError::BufferOverFlow(pos).into());
+                    }
+                    self.fill()?;
+                }
+                Partial::Done(Some(end)) => {
+                    let m = self.byte_pos(start)..self.byte_pos(end);
+                    // 空文字列にマッチした場合は、同じ位置で再度マッチしないよう1文字進める
+                    self.pos = if end == start {
+                        self.base + end + 1
+                    } else {
+                        self.base + end
+                    };
+                    self.compact();
+                    return Ok(Some(m));
+                }
+                Partial::Done(None) => {
+                    self.pos += 1;
+                    self.compact();
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for StreamMatches<'_, R> {
+    type Item = Result<Range<usize>, RegexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.find_next() {
+            Ok(Some(m)) => Some(Ok(m)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[test]
+fn test() {
+    /// 1回の読み込みで、指定したバイト数ずつしか返さないリーダー
+    struct Trickle<'a>(&'a [u8], usize);
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.1.min(self.0.len()).min(buf.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let text = "abcあいうbc\nxbcbcy\n";
+    for expr in [
+        "bc", "(bc)+", "い|うb", "c\n", "^x", "y$", "a*", "\\bbc", "c\\b", "\\B\\w",
+    ] {
+        for is_depth in [true, false] {
+            let re = Regex::new(expr, is_depth).unwrap();
+            let expected = re.find_iter(text).collect::<Result<Vec<_>, _>>().unwrap();
+            for n in 1..5 {
+                let spans = re
+                    .stream_find(Trickle(text.as_bytes(), n))
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap();
+                assert_eq!(spans, expected, "expr = {expr}, n = {n}");
+            }
+        }
+    }
+
+    // 不正なUTF-8は置換文字となり、位置は元のバイト列での位置となる
+    let re = Regex::new("a..b", true).unwrap();
+    for n in 1..5 {
+        let spans = re
+            .stream_find(Trickle(b"\xe3\x81a\xe3\x81\xffb\xe3\x81\x82a\xe3", n))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(spans, vec![2..7], "n = {n}");
+    }
+    let re = Regex::new("a.$", true).unwrap();
+    let spans = re
+        .stream_find(&b"xa\xe3\x81"[..])
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(spans, vec![1..4]);
+
+    // マッチの確定にバッファの上限を超える文字数が必要な場合はエラー
+    let re = Regex::new("a(b|c)*d", true).unwrap();
+    let mut it = re.stream_find(Trickle(b"abcbcbcbcbcbd", 2)).buffer_limit(4);
+    assert!(it.next().unwrap().is_err());
+    assert!(it.next().is_none());
+}
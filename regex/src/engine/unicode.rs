@@ -0,0 +1,116 @@
+//! Unicodeのプロパティによる文字クラス
+//!
+//! `\p{...}`で指定する一般カテゴリと用字（Script）、および`\w`、`\d`、`\s`の範囲を扱う。
+//! 範囲の表は`scripts/unicode_tables.pl`で生成した[super::unicode_tables]を用いる。
+use super::{class::Class, unicode_tables as tables};
+
+/// プロパティ名に対応する文字クラス。不明な名前の場合はNoneを返す。
+///
+/// 名前は一般カテゴリ（`L`、`Letter`、`Lu`など）または用字（`Greek`、`Grek`など）で、
+/// `gc=`や`sc=`（`General_Category=`、`Script=`）を前に付けて種類を限定できる。
+/// このほか`Any`（全ての文字）と`ASCII`を指定できる。
+/// 名前の大文字と小文字、空白、`_`、`-`は区別しない。
+pub fn property(name: &str) -> Option<Class> {
+    let name = loose(name);
+    let (kind, value) = match name.split_once('=') {
+        Some((kind, value)) => (Some(kind), value),
+        None => (None, name.as_str()),
+    };
+    match (kind, value) {
+        (None, "any") => return Some(Class::new(vec![('\0', char::MAX)])),
+        (None, "ascii") => return Some(Class::new(vec![('\0', '\x7f')])),
+        _ => (),
+    }
+    let gc = matches!(kind, None | Some("gc" | "generalcategory"));
+    let sc = matches!(kind, None | Some("sc" | "script"));
+    [(gc, tables::GENERAL_CATEGORY), (sc, tables::SCRIPT)]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .flat_map(|(_, table)| table.iter())
+        .find(|(short, long, _)| loose(short) == value || loose(long) == value)
+        .map(|(_, _, ranges)| Class::new(ranges.to_vec()))
+}
+
+/// `\w`の文字クラス（英字、結合文字、10進数字、連結句読点など）
+pub fn word() -> Class {
+    Class::new(tables::WORD.to_vec())
+}
+
+/// `\d`の文字クラス（一般カテゴリNdの10進数字）
+pub fn digit() -> Class {
+    Class::new(tables::DIGIT.to_vec())
+}
+
+/// `\s`の文字クラス（White_Spaceプロパティを持つ文字）
+pub fn space() -> Class {
+    Class::new(tables::SPACE.to_vec())
+}
+
+/// 単語を構成する文字（`\w`にマッチする文字）か判定。
+///
+/// Unicode Technical Standard #18の定義に従い、`_`やアクセント記号などの結合文字も含む。
+///
+/// # 利用例
+///
+/// ```
+/// use regex::is_word_char;
+/// assert!(is_word_char('a') && is_word_char('_') && is_word_char('é') && is_word_char('漢'));
+/// assert!(!is_word_char(' ') && !is_word_char('-') && !is_word_char('。'));
+/// ```
+pub fn is_word_char(c: char) -> bool {
+    tables::WORD
+        .binary_search_by(|(lo, hi)| {
+            if *hi < c {
+                std::cmp::Ordering::Less
+            } else if *lo > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+/// 単語を構成する文字か判定。asciiがtrueの場合は、ASCIIの英数字と`_`のみを単語の文字とする
+pub fn is_word(c: char, ascii: bool) -> bool {
+    if ascii {
+        c.is_ascii_alphanumeric() || c == '_'
+    } else {
+        is_word_char(c)
+    }
+}
+
+/// 名前の比較のため、小文字にして空白、`_`、`-`を除く
+fn loose(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[test]
+fn test() {
+    let greek = property("Greek").unwrap();
+    assert!(greek.contains('α') && greek.contains('Ω') && !greek.contains('a'));
+    assert_eq!(property("sc=Grek"), Some(greek));
+    assert!(property("Script = greek").is_some());
+    assert!(property("gc=Greek").is_none());
+
+    let letter = property("L").unwrap();
+    assert!(letter.contains('a') && letter.contains('漢') && !letter.contains('1'));
+    assert_eq!(property("letter"), Some(letter));
+    let upper = property("Uppercase_Letter").unwrap();
+    assert!(upper.contains('A') && upper.contains('Ä') && !upper.contains('a'));
+    assert_eq!(property("Lu"), Some(upper));
+
+    // サロゲート領域は含まない
+    assert!(property("Cs").unwrap().ranges().is_empty());
+    assert!(property("Any").unwrap().contains(char::MAX));
+    assert!(property("Unknown").unwrap().contains('\u{10ffff}'));
+    assert!(property("Klingon").is_none());
+
+    assert!(digit().contains('٣') && !digit().contains('a'));
+    assert!(space().contains('\u{3000}') && !space().contains('_'));
+    assert_eq!(word().ranges(), tables::WORD);
+    assert!(is_word_char('\u{301}') && is_word_char('٣'));
+}
@@ -0,0 +1,91 @@
+//! examplesサブコマンド。パターンが受理する文字列の例を表示する
+use regex::{
+    generate::{Generator, DEFAULT_MAX_LEN, DEFAULT_MAX_REPEAT, DEFAULT_MAX_STEPS},
+    DynError, Flags,
+};
+use std::{
+    fmt::Write as _,
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// examplesサブコマンドの引数
+#[derive(clap::Args)]
+pub struct ExamplesArgs {
+    /// パターン
+    #[arg(short, long)]
+    regex: String,
+    /// 表示する文字列の数
+    #[arg(short = 'n', long, default_value_t = 10)]
+    count: usize,
+    /// 大文字と小文字を区別しない
+    #[arg(short, long)]
+    ignore_case: bool,
+    /// 短い順ではなく、ランダムに生成
+    #[arg(long)]
+    random: bool,
+    /// ランダムに生成する際の乱数のシード（省略時は現在時刻）
+    #[arg(long, requires = "random")]
+    seed: Option<u64>,
+    /// 文字列の長さの上限
+    #[arg(long, value_name = "NUM", default_value_t = DEFAULT_MAX_LEN)]
+    max_len: usize,
+    /// `*`と`+`の繰り返し回数の上限
+    #[arg(long, value_name = "NUM", default_value_t = DEFAULT_MAX_REPEAT)]
+    max_repeat: usize,
+    /// 短い順に列挙する際の、パターンの要素を展開する回数の上限
+    #[arg(long, value_name = "NUM", default_value_t = DEFAULT_MAX_STEPS)]
+    max_steps: usize,
+}
+
+/// 文字列の例を1行に1つずつ表示する。
+///
+/// 1つ以上表示した場合にtrueを返す。改行などの制御文字と`\`はエスケープして表示する。
+pub fn run(args: &ExamplesArgs) -> Result<bool, DynError> {
+    let flags = Flags {
+        ignore_case: args.ignore_case,
+        ..Flags::default()
+    };
+    let gen = Generator::with_flags(&args.regex, flags)?
+        .max_len(args.max_len)
+        .max_repeat(args.max_repeat)
+        .max_steps(args.max_steps);
+    let examples = if args.random {
+        let seed = args.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+        });
+        gen.sample(args.count, seed)
+    } else {
+        gen.shortest(args.count)
+    };
+
+    let mut out = io::stdout().lock();
+    for s in &examples {
+        writeln!(out, "{}", escape(s))?;
+    }
+    Ok(!examples.is_empty())
+}
+
+/// 制御文字と`\`をエスケープ
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{{{:x}}}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[test]
+fn test() {
+    assert_eq!(escape("a b"), "a b");
+    assert_eq!(escape("\\\n\t\0é"), "\\\\\\n\\t\\u{0}é");
+}
@@ -0,0 +1,180 @@
+//! --jsonで出力する、JSON Lines形式のメッセージ
+//!
+//! マッチごとに1行の`match`メッセージを出力し、最後に`summary`メッセージを出力する。
+//!
+//! ```text
+//! {"type":"match","file":"a.txt","line":2,"byte_start":4,"byte_end":7,"char_start":4,"char_end":7,"text":"foo","captures":[...]}
+//! {"type":"summary","files_searched":1,"files_matched":1,"matched_lines":1,"matches":1,"errors":0,"elapsed_secs":0.001}
+//! ```
+use regex::{Captures, DynError};
+use serde::Serialize;
+use std::{cell::OnceCell, io::Write, ops::Range};
+
+/// 出力するメッセージ
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message<'a> {
+    Match(Match<'a>),
+    Summary(Summary),
+}
+
+/// マッチ1件
+#[derive(Serialize)]
+struct Match<'a> {
+    file: &'a str,
+    line: usize, // マッチの開始位置の行番号（1始まり）
+    #[serde(flatten)]
+    span: Span,
+    text: String,
+    captures: Vec<Option<Capture<'a>>>, // 1番目以降のグループ。マッチに関与しないグループはnull
+}
+
+/// キャプチャグループ1つ分の範囲
+#[derive(Serialize)]
+struct Capture<'a> {
+    group: usize,
+    name: Option<&'a str>,
+    #[serde(flatten)]
+    span: Span,
+    text: String,
+}
+
+/// 入力の先頭からの範囲。いずれも0始まりで、終了位置は含まない
+#[derive(Serialize)]
+struct Span {
+    byte_start: usize,
+    byte_end: usize,
+    char_start: usize,
+    char_end: usize,
+}
+
+/// 検索全体の集計
+#[derive(Serialize, Default)]
+pub struct Summary {
+    pub files_searched: usize,
+    pub files_matched: usize,
+    pub matched_lines: usize, // -Uの場合はマッチの数
+    pub matches: usize,
+    pub errors: usize,
+    pub elapsed_secs: f64,
+}
+
+/// 検索対象の文字列と、その先頭の入力中の位置
+///
+/// 行ごとに検索する場合は1行分、-Uの場合は入力全体となる。
+/// バイト位置は入力のバイト列での位置とする。
+/// 文字位置は、不正なバイト列を置換文字1文字として数えた位置とする。
+pub struct Text<'a> {
+    pub text: &'a str, // rawの不正なバイト列を置換文字に変換した文字列
+    raw: &'a [u8],     // 入力のバイト列
+    byte: usize,       // 先頭のバイト位置
+    char: usize,       // 先頭の文字位置
+    offsets: OnceCell<Vec<(usize, usize)>>, // 文字位置ごとの、textとrawでのバイト位置
+}
+
+impl<'a> Text<'a> {
+    /// textはrawをString::from_utf8_lossyで変換した文字列
+    pub fn new(text: &'a str, raw: &'a [u8], byte: usize, char: usize) -> Self {
+        Text {
+            text,
+            raw,
+            byte,
+            char,
+            offsets: OnceCell::new(),
+        }
+    }
+
+    /// 各文字の先頭と末尾の、textとrawでのバイト位置
+    ///
+    /// マッチごとに先頭から数え直さないよう、最初に必要になった際に1度だけ求める。
+    fn offsets(&self) -> &[(usize, usize)] {
+        self.offsets.get_or_init(|| {
+            let mut offsets = Vec::new();
+            let (mut pos, mut raw_pos) = (0, 0);
+            for chunk in self.raw.utf8_chunks() {
+                for c in chunk.valid().chars() {
+                    offsets.push((pos, raw_pos));
+                    pos += c.len_utf8();
+                    raw_pos += c.len_utf8();
+                }
+                // 不正なバイト列は、from_utf8_lossyと同様に1つの置換文字となる
+                if !chunk.invalid().is_empty() {
+                    offsets.push((pos, raw_pos));
+                    pos += char::REPLACEMENT_CHARACTER.len_utf8();
+                    raw_pos += chunk.invalid().len();
+                }
+            }
+            offsets.push((pos, raw_pos));
+            offsets
+        })
+    }
+
+    /// textのバイト単位の範囲を、入力の先頭からの範囲に変換
+    fn span(&self, r: &Range<usize>) -> Span {
+        let offsets = self.offsets();
+        // (入力でのバイト位置, 文字位置)
+        let pos = |i: usize| {
+            let char = offsets.partition_point(|(pos, _)| *pos < i);
+            (self.byte + offsets[char].1, self.char + char)
+        };
+        let (byte_start, char_start) = pos(r.start);
+        let (byte_end, char_end) = pos(r.end);
+        Span {
+            byte_start,
+            byte_end,
+            char_start,
+            char_end,
+        }
+    }
+
+    fn slice(&self, r: &Range<usize>) -> String {
+        self.text[r.clone()].to_string()
+    }
+}
+
+/// マッチ1件を出力
+///
+/// namesはキャプチャグループの名前の一覧で、添字はグループの番号。
+/// 1番目からskip個のグループは出力せず、以降のグループの番号をskipだけ詰める。
+pub fn write_match(
+    out: &mut impl Write,
+    file: &str,
+    line: usize,
+    text: &Text,
+    caps: &Captures,
+    names: &[Option<String>],
+    skip: usize,
+) -> Result<(), DynError> {
+    // 0番目のグループはマッチ全体
+    let m = caps.get(0).unwrap_or_default();
+    let captures = caps
+        .iter()
+        .enumerate()
+        .skip(1 + skip)
+        .map(|(group, r)| {
+            r.map(|r| Capture {
+                group: group - skip,
+                name: names.get(group).and_then(|n| n.as_deref()),
+                span: text.span(&r),
+                text: text.slice(&r),
+            })
+        })
+        .collect();
+    let msg = Message::Match(Match {
+        file,
+        line,
+        span: text.span(&m),
+        text: text.slice(&m),
+        captures,
+    });
+    serde_json::to_writer(&mut *out, &msg)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+/// 集計を出力
+pub fn write_summary(out: &mut impl Write, summary: Summary) -> Result<(), DynError> {
+    serde_json::to_writer(&mut *out, &Message::Summary(summary))?;
+    writeln!(out)?;
+    Ok(())
+}
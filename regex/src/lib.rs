@@ -3,13 +3,22 @@
 //! ## 利用例
 //!
 //! ```
-//! use regex;
+//! use regex::{self, Flags};
 //! let expr = "a(bc)+|c(def)*"; // 正規表現
 //! let line = "cdefdefdef"; // マッチ対象文字列
-//! regex::do_matching(expr, line, true); // 幅優先探索でマッチング
-//! regex::print(expr); // 正規表現のASTと命令列を表示
+//! regex::do_matching(expr, line, true); // 深さ優先探索でマッチング
+//! let ast = regex::parse(expr, Flags::default()); // 正規表現のAST
+//! let code = regex::listing(expr, Flags::default()); // 命令列
+//! let caps = regex::find(expr, line, Flags::default(), false); // マッチ範囲とグループの範囲
 //! ```
+//!
+//! エラーは[Error]として返り、パースエラーの場合はパターン中の位置の範囲も得られる。
 mod engine;
 mod helper;
 
-pub use engine::{do_matching, print};
+pub use engine::{
+    aot, do_matching, find, generate, glob, is_word_char, lang, lexer, listing, parse, Assertion,
+    Captures, Class, CodeGenError, DfaError, Error, EvalError, Flags, Haystack, LoadError, Matches,
+    ParseError, Regex, Stats, StreamError, StreamMatches, Syntax, AST, FORMAT_VERSION,
+};
+pub use helper::DynError;
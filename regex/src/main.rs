@@ -1,101 +1,561 @@
-mod engine;
-mod helper;
+mod examples;
+mod json;
+mod parallel;
+mod repl;
+mod search;
+mod subst;
+mod walk;
 
-use clap::{Parser, ValueEnum};
-use helper::DynError;
+use clap::{Parser, Subcommand, ValueEnum};
+use examples::ExamplesArgs;
+use regex::{Flags, Syntax};
+use regex::DynError;
+use parallel::Pool;
+use search::Searcher;
+use subst::SubstArgs;
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
+    ffi::OsString,
     fmt::{Debug, Display, Formatter},
+    io::{self, IsTerminal, Write},
+    process::ExitCode,
+    thread,
+    time::Instant,
 };
+use walk::Walker;
 
 /// 正規表現を評価する
+///
+/// grepと同様に、1行以上選択された場合は0を、選択されなかった場合は1を、
+/// エラーの場合は2を終了コードとして返す。
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// 検索パターン
-    #[arg(short, long)]
-    regex: String,
-    /// 入力ファイル
-    #[arg(short, long)]
-    input: String,
+    #[arg(short, long, required = true)]
+    regex: Option<String>,
+    /// 入力ファイルまたはディレクトリ。省略時と`-`の場合は標準入力
+    input: Vec<String>,
     /// 深さ優先探索
     #[arg(short, long, value_enum, default_value_t = SearchMethod::Dfs, help = "Search Method")]
     method: SearchMethod,
+    /// ファイル全体を1つの文字列としてマッチング（^と$は行頭と行末にマッチ）
+    #[arg(short = 'U', long, conflicts_with_all = ["invert_match", "line_regexp"])]
+    multiline: bool,
+    /// マッチしない行を選択
+    #[arg(short = 'v', long)]
+    invert_match: bool,
+    /// 選択した行の数のみ表示
+    #[arg(short, long)]
+    count: bool,
+    /// 行番号を表示
+    #[arg(short = 'n', long)]
+    line_number: bool,
+    /// マッチした部分のみ表示
+    #[arg(short = 'o', long)]
+    only_matching: bool,
+    /// 選択した行を含むファイル名のみ表示
+    #[arg(short = 'l', long)]
+    files_with_matches: bool,
+    /// 大文字と小文字を区別しない
+    #[arg(short, long)]
+    ignore_case: bool,
+    /// 単語全体にマッチする場合のみ選択
+    #[arg(short, long)]
+    word_regexp: bool,
+    /// 行全体にマッチする場合のみ選択
+    #[arg(short = 'x', long)]
+    line_regexp: bool,
+    /// パターンをPOSIXの基本正規表現（BRE）として解釈
+    #[arg(short = 'G', long, conflicts_with = "extended_regexp")]
+    basic_regexp: bool,
+    /// パターンをPOSIXの拡張正規表現（ERE）として解釈
+    #[arg(short = 'E', long)]
+    extended_regexp: bool,
+    /// ディレクトリを再帰的に検索
+    #[arg(short = 'R', long)]
+    recursive: bool,
+    /// 再帰的に検索する際、globにマッチするファイルのみ検索（複数指定可）
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// 再帰的に検索する際、globにマッチするファイルとディレクトリを除外（複数指定可）
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+    /// 再帰的に検索する際、隠しファイルも検索
+    #[arg(long)]
+    hidden: bool,
+    /// 再帰的に検索する際、.gitignoreを無視
+    #[arg(long)]
+    no_ignore: bool,
+    /// バイナリファイルもテキストとして検索
+    #[arg(short = 'a', long)]
+    text: bool,
+    /// ファイル名を表示（複数のファイルを検索する場合は常に表示）
+    #[arg(short = 'H', long)]
+    with_filename: bool,
+    /// 選択した行の後のNUM行も表示
+    #[arg(short = 'A', long, value_name = "NUM")]
+    after_context: Option<usize>,
+    /// 選択した行の前のNUM行も表示
+    #[arg(short = 'B', long, value_name = "NUM")]
+    before_context: Option<usize>,
+    /// 選択した行の前後のNUM行も表示
+    #[arg(short = 'C', long, value_name = "NUM")]
+    context: Option<usize>,
+    /// マッチ部分などを色付けして表示
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = ColorChoice::Auto,
+          num_args = 0..=1, default_missing_value = "auto")]
+    color: ColorChoice,
+    /// マッチごとにJSONで出力し、最後に集計を出力（JSON Lines形式）
+    #[arg(long, conflicts_with_all = ["count", "files_with_matches", "invert_match",
+          "only_matching", "after_context", "before_context", "context", "debug"])]
+    json: bool,
+    /// 並列に検索するスレッドの数（省略時はCPU数）
+    #[arg(short = 'j', long, value_name = "NUM")]
+    threads: Option<usize>,
+    /// 複数の入力を並列に検索する際、検索が終わった順に表示
+    #[arg(long)]
+    unordered: bool,
+    /// ASTと命令列を表示
+    #[arg(long)]
+    debug: bool,
+    /// 実行した命令やスレッドの数など、マッチングの統計情報を標準エラー出力に表示
+    #[arg(long)]
+    stats: bool,
+}
+
+/// サブコマンド
+#[derive(Subcommand)]
+enum Command {
+    /// sed互換の置換を行う
+    Subst(SubstArgs),
+    /// パターンとテスト用の文字列を入力し、マッチの様子を対話的に確認する
+    Repl,
+    /// パターンが受理する文字列の例を、短い順またはランダムに表示する
+    Examples(ExamplesArgs),
+}
+
+impl Args {
+    /// 検索パターン（サブコマンドを指定しない場合は必須）
+    fn pattern(&self) -> &str {
+        self.regex.as_deref().unwrap_or_default()
+    }
+
+    /// パース時のフラグ
+    fn flags(&self) -> Flags {
+        Flags {
+            multi_line: self.multiline,
+            ignore_case: self.ignore_case,
+            syntax: self.syntax(),
+            ..Flags::default()
+        }
+    }
+
+    /// パターンの構文
+    fn syntax(&self) -> Syntax {
+        if self.basic_regexp {
+            Syntax::Basic
+        } else if self.extended_regexp {
+            Syntax::Extended
+        } else {
+            Syntax::Native
+        }
+    }
 }
+
 #[derive(Debug, Clone, ValueEnum, PartialEq)]
 enum SearchMethod {
     Dfs,
     Bfs,
 }
 
+/// 色付けの指定
+#[derive(Debug, Clone, ValueEnum, PartialEq)]
+enum ColorChoice {
+    Auto,   // 標準出力が端末の場合のみ色付け
+    Always, // 常に色付け
+    Never,  // 色付けしない
+}
+
 impl Display for SearchMethod {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         let raw = format!("{:?}", self);
         write!(f, "{}", raw)
     }
 }
-fn main() -> Result<(), DynError> {
-    let args = Args::parse();
-    let is_depth = if args.method == SearchMethod::Dfs {
-        true
-    } else {
-        false
+
+fn main() -> ExitCode {
+    let args = Args::parse_from(normalize_args(std::env::args_os()));
+    let mut had_error = false;
+    let result = match &args.command {
+        Some(Command::Subst(subst)) => subst::run(subst, &mut had_error).map(|_| true),
+        Some(Command::Repl) => repl::run().map(|_| true),
+        Some(Command::Examples(examples)) => examples::run(examples),
+        None => run(&args, &mut had_error),
     };
-    match_file(&args.regex, &args.input, is_depth)?;
-    Ok(())
+    match result {
+        Ok(_) if had_error => ExitCode::from(2),
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(e) => {
+            eprintln!("regex: {e}");
+            ExitCode::from(2)
+        }
+    }
 }
 
-/// ファイルをオープンし、行ごとにマッチングを行う。
+/// sedと同様に`subst -iSUFFIX`の形式を受け付けるため、`--in-place=SUFFIX`に変換
 ///
-/// マッチングはそれぞれの行頭から1文字ずつずらして行い、
-/// いずれかにマッチした場合に、その行がマッチしたものとみなす。
-///
-/// たとえば、abcdという文字列があった場合、以下の順にマッチが行われ、
-/// このいずれかにマッチした場合、与えられた正規表現にマッチする行と判定する。
-///
-/// - abcd
-/// - bcd
-/// - cd
-/// - d
-fn match_file(expr: &str, input: &str, breadth: bool) -> Result<(), DynError> {
-    let f = File::open(input)?;
-    let reader = BufReader::new(f);
-
-    engine::print(expr)?;
-    // ファイルを読み込み
-    for (idx, line) in reader.lines().enumerate() {
-        let line = line?;
-        for (i, _) in line.char_indices() {
-            let result = engine::do_matching(expr, &line[i..], breadth);
-            match result {
-                Ok(match_exp) => {
-                    println!("line={idx}:{line}");
-                    println!("match={match_exp}");
-                    break;
-                }
-                _ => {},
+/// サブコマンドは他の引数と同時に指定できないため、最初の引数が`subst`の場合のみ変換する。
+/// `--`以降の引数は変換しない。
+fn normalize_args(args: impl Iterator<Item = OsString>) -> Vec<OsString> {
+    let mut args = args.collect::<Vec<_>>();
+    if args.get(1).and_then(|arg| arg.to_str()) != Some("subst") {
+        return args;
+    }
+    for arg in args.iter_mut().skip(2) {
+        match arg.to_str() {
+            Some("--") => break,
+            Some(s) if s.starts_with("-i") && s.len() > 2 && !s.starts_with("-i=") => {
+                *arg = format!("--in-place={}", &s[2..]).into();
             }
+            _ => {}
         }
     }
-    Ok(())
+    args
 }
+
+/// 全ての入力を検索し、1行以上選択された場合にtrueを返す。
+///
+/// 個々の入力でのエラーは表示して検索を続け、had_errorをtrueにする。
+fn run(args: &Args, had_error: &mut bool) -> Result<bool, DynError> {
+    let start = Instant::now();
+    let mut searcher = Searcher::new(args)?;
+    if args.debug {
+        println!("expr: {}", args.pattern());
+        println!("AST: {:?}", regex::parse(args.pattern(), args.flags())?);
+        print!("code:\n{}", regex::listing(args.pattern(), args.flags())?);
+    }
+    let inputs = Walker::new(args)?.inputs();
+    searcher.with_filename = args.with_filename || args.recursive || inputs.len() > 1;
+    searcher.color = match args.color {
+        _ if args.json => false,
+        ColorChoice::Auto => io::stdout().is_terminal(),
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+
+    let threads = match args.threads {
+        Some(n) => n.max(1),
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let pool = Pool {
+        threads,
+        ordered: !args.unordered,
+        // 文脈行は分割の境界をまたぐため、文脈行を表示する場合は分割しない。
+        // --jsonの場合も、分割するとファイル先頭からの文字位置が分からないため分割しない
+        split: !args.multiline
+            && !args.json
+            && args.context.or(args.before_context).or(args.after_context).is_none(),
+    };
+    let mut out = io::stdout().lock();
+    let stats = pool.run(&searcher, inputs, &mut out, had_error)?;
+    if args.json {
+        let summary = json::Summary {
+            files_searched: stats.searched,
+            files_matched: stats.matched,
+            matched_lines: stats.selected,
+            matches: searcher.matches(),
+            errors: stats.errors,
+            elapsed_secs: start.elapsed().as_secs_f64(),
+        };
+        json::write_summary(&mut out, summary)?;
+    }
+    out.flush()?;
+    if args.stats {
+        eprintln!("regex: stats: {}", searcher.stats());
+    }
+    Ok(stats.matched > 0)
+}
+
 #[test]
 fn test() {
     _test(true);
     _test(false);
 }
-fn _test(is_depth: bool) -> () {
+fn _test(is_depth: bool) {
     // char
-    assert_eq!(engine::do_matching("a", "a", is_depth).unwrap(), String::from("a"));
+    assert_eq!(regex::do_matching("a", "a", is_depth).unwrap(), String::from("a"));
     // plus
-    assert_eq!(engine::do_matching("a+", "a", is_depth).unwrap(), String::from("a"));
-    assert_eq!(engine::do_matching("a+", "aa", is_depth).unwrap(), String::from("aa"));
+    assert_eq!(regex::do_matching("a+", "a", is_depth).unwrap(), String::from("a"));
+    assert_eq!(regex::do_matching("a+", "aa", is_depth).unwrap(), String::from("aa"));
     // star
-    assert_eq!(engine::do_matching("a*", "", is_depth).unwrap(), String::from(""));
-    assert_eq!(engine::do_matching("a*", "a", is_depth).unwrap(), String::from("a"));
-    assert_eq!(engine::do_matching("a*", "aa", is_depth).unwrap(), String::from("aa"));
+    assert_eq!(regex::do_matching("a*", "", is_depth).unwrap(), String::from(""));
+    assert_eq!(regex::do_matching("a*", "a", is_depth).unwrap(), String::from("a"));
+    assert_eq!(regex::do_matching("a*", "aa", is_depth).unwrap(), String::from("aa"));
     // or
-    assert_eq!(engine::do_matching("a|b", "a", is_depth).unwrap(), String::from("a"));
-    assert_eq!(engine::do_matching("a|b", "b", is_depth).unwrap(), String::from("b"));
-    assert_eq!(engine::do_matching("a|b|c", "c", is_depth).unwrap(), String::from("c"));
-}
\ No newline at end of file
+    assert_eq!(regex::do_matching("a|b", "a", is_depth).unwrap(), String::from("a"));
+    assert_eq!(regex::do_matching("a|b", "b", is_depth).unwrap(), String::from("b"));
+    assert_eq!(regex::do_matching("a|b|c", "c", is_depth).unwrap(), String::from("c"));
+}
+#[test]
+fn test_multiline() {
+    use regex::Regex;
+
+    let text = "ab\ncd\nab".chars().collect::<Vec<char>>();
+    let flags = Flags {
+        multi_line: true,
+        ..Flags::default()
+    };
+    for is_depth in [true, false] {
+        let re = Regex::with_flags("b$", flags, is_depth).unwrap();
+        let spans = re.find_iter(&text).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(spans, vec![1..2, 7..8]);
+
+        let re = Regex::with_flags("b\ncd", flags, is_depth).unwrap();
+        assert_eq!(re.find_at(&text, 0).unwrap(), Some(1..5));
+
+        let re = Regex::with_flags("b.c", flags, is_depth).unwrap();
+        assert_eq!(re.find_at(&text, 0).unwrap(), None);
+        let re = Regex::with_flags("(?s)b.c", flags, is_depth).unwrap();
+        assert_eq!(re.find_at(&text, 0).unwrap(), Some(1..4));
+
+        let re = Regex::with_flags("(?-m)^a", flags, is_depth).unwrap();
+        let spans = re.find_iter(&text).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(spans, vec![0..1]);
+    }
+}
+#[test]
+fn test_captures() {
+    use regex::Regex;
+
+    let text = "k=v, key=, x".chars().collect::<Vec<char>>();
+    for is_depth in [true, false] {
+        let re = Regex::new("(?<k>[a-z]+)=([a-z]*)(,)?", is_depth).unwrap();
+        let caps = re.find_captures_at(&text, 0).unwrap().unwrap();
+        assert_eq!(
+            caps.iter().collect::<Vec<_>>(),
+            vec![Some(0..4), Some(0..1), Some(2..3), Some(3..4)]
+        );
+        let caps = re.find_captures_at(&text, 4).unwrap().unwrap();
+        assert_eq!(
+            caps.iter().collect::<Vec<_>>(),
+            vec![Some(5..10), Some(5..8), Some(9..9), Some(9..10)]
+        );
+        assert_eq!(re.captures_at(&text, 11).unwrap(), None);
+        assert_eq!(re.capture_names(), &[None, Some("k".into()), None, None]);
+
+        // 繰り返しの場合は最後の繰り返しの範囲、マッチに関与しないグループはNone
+        let re = Regex::new("(a|(b))+c", is_depth).unwrap();
+        let line = "abac".chars().collect::<Vec<char>>();
+        let caps = re.captures_at(&line, 0).unwrap().unwrap();
+        assert_eq!(caps.get(0), Some(0..4));
+        assert_eq!(caps.get(1), Some(2..3));
+        assert_eq!(caps.get(2), Some(1..2));
+        let re = Regex::new("(x)?(?:a)", is_depth).unwrap();
+        let caps = re.captures_at(&line, 0).unwrap().unwrap();
+        assert_eq!(caps.iter().collect::<Vec<_>>(), vec![Some(0..1), None]);
+    }
+}
+#[test]
+fn test_stats() {
+    use regex::{Regex, Stats};
+
+    let line = "aaaaaaaaaa".chars().collect::<Vec<char>>();
+    for is_depth in [true, false] {
+        let re = Regex::new("(a*)*b", is_depth).unwrap();
+        let (m, stats) = re.find_at_with_stats(&line, 0).unwrap();
+        assert_eq!(m, None);
+        assert!(stats.instructions > line.len());
+        assert!(stats.threads > 0 && stats.peak_queue > 0);
+        // 同じ状態に何度も到達するパターンはキャッシュにヒットする
+        assert!(stats.cache_hits > 0);
+
+        let (end, one) = re.match_with_stats(&line, 0).unwrap();
+        assert_eq!(end, None);
+        assert!(one.instructions <= stats.instructions);
+        assert!(one.peak_queue <= stats.peak_queue);
+    }
+
+    let mut total = Stats {
+        instructions: 1,
+        threads: 1,
+        peak_queue: 3,
+        cache_hits: 1,
+        cache_misses: 1,
+    };
+    total += Stats {
+        peak_queue: 2,
+        cache_hits: 4,
+        ..total
+    };
+    assert_eq!(total.instructions, 2);
+    assert_eq!(total.peak_queue, 3);
+    assert_eq!(total.cache_hits, 5);
+}
+#[test]
+fn test_find() {
+    use regex::Regex;
+
+    // 入力を一度だけ辿る探索は、開始位置をずらしながらmatch_atを繰り返す場合と一致する
+    let patterns = [
+        "(x|y)*z",
+        "a|ab|abc",
+        "b*",
+        "(?m)^b|c$",
+        "\\bé+\\b",
+        "(a*)*b",
+        "x?y?$",
+        "(?>a|ab)c|b",
+    ];
+    let inputs = ["", "abcb", "xyxyz", "é ééb\nbc", "aab\nc", "xxxx", "yx"];
+    for is_depth in [true, false] {
+        for expr in patterns {
+            let Ok(re) = Regex::new(expr, is_depth) else {
+                continue;
+            };
+            for input in inputs {
+                for start in (0..=input.len()).filter(|i| input.is_char_boundary(*i)) {
+                    let expected = (start..=input.len())
+                        .filter(|i| input.is_char_boundary(*i))
+                        .find_map(|b| re.match_at(input, b).unwrap().map(|e| b..e));
+                    let m = re.find_at(input, start).unwrap();
+                    assert_eq!(m, expected, "{expr} {input:?} {start} {is_depth}");
+                }
+            }
+        }
+    }
+
+    // マッチしない長い行でも、計算量は文字数に比例する
+    let line = "x".repeat(5000);
+    for is_depth in [true, false] {
+        let re = Regex::new("(x|y)*z", is_depth).unwrap();
+        let (m, stats) = re.find_at_with_stats(line.as_str(), 0).unwrap();
+        assert_eq!(m, None);
+        assert!(stats.instructions < line.len() * 20, "{stats}");
+    }
+}
+#[test]
+fn test_atomic() {
+    use regex::Regex;
+
+    let find = |expr, text: &str| {
+        let line = text.chars().collect::<Vec<char>>();
+        Regex::new(expr, true).unwrap().find_at(&line, 0).unwrap()
+    };
+    // グループ内は優先度の最も高い経路のみ採用し、バックトラックしない
+    assert_eq!(find("(?>a|ab)c", "abc"), None);
+    assert_eq!(find("(?>ab|a)c", "abc"), Some(0..3));
+    assert_eq!(find("(?>x|xy)z|xyz", "xyz"), Some(0..3));
+    assert_eq!(find("a*+a", "aaa"), None);
+    assert_eq!(find("a++b", "xaab"), Some(1..4));
+    assert_eq!(find("a?+a", "a"), None);
+    assert_eq!(find("(?>a*)*b", "aab"), Some(0..3));
+    assert_eq!(find("(?>(?>a|ab)+)b", "abab"), Some(0..2));
+
+    let re = Regex::new("(?>(a+)|b)(a?)", true).unwrap();
+    let line = "aaa".chars().collect::<Vec<char>>();
+    let caps = re.captures_at(&line, 0).unwrap().unwrap();
+    assert_eq!(caps.iter().collect::<Vec<_>>(), vec![Some(0..3), Some(0..3), Some(3..3)]);
+
+    // 幅優先探索では評価できない
+    assert!(Regex::new("a*+", false).is_err());
+    assert!(regex::do_matching("(?>a)", "a", false).is_err());
+}
+
+#[test]
+fn test_error() {
+    use regex::{Error, EvalError, ParseError, Regex};
+
+    let e = Regex::new("ab)", true).unwrap_err();
+    assert!(matches!(e, Error::Parse(ParseError::InvalidRightParen(2), _)));
+    assert_eq!(e.span(), Some(2..3));
+    let e = Regex::new("(ab", true).unwrap_err();
+    assert!(matches!(e, Error::Parse(ParseError::NoRightParen, _)));
+    assert_eq!(e.span(), Some(3..3));
+    assert!(matches!(
+        regex::do_matching("(?>a)", "a", false),
+        Err(Error::Eval(EvalError::AtomicInWidth))
+    ));
+    assert!(matches!(
+        regex::do_matching("b", "a", true),
+        Err(Error::Eval(EvalError::NoMatch))
+    ));
+
+    let flags = Flags::default();
+    assert_eq!(
+        regex::listing("ab", flags).unwrap(),
+        "0000: char a\n0001: char b\n0002: match\n"
+    );
+    let caps = regex::find("(a)|b", "xb", flags, false).unwrap().unwrap();
+    assert_eq!(caps.iter().collect::<Vec<_>>(), vec![Some(1..2), None]);
+    assert_eq!(regex::find("c", "ab", flags, true).unwrap(), None);
+}
+
+#[test]
+fn test_haystack() {
+    use regex::Regex;
+
+    for is_depth in [true, false] {
+        // &strではバイト単位、&[char]では文字単位の範囲となる
+        let re = Regex::new("b+", is_depth).unwrap();
+        let spans = re.find_iter("äbbcb").collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(spans, vec![2..4, 5..6]);
+        let line = "äbbcb".chars().collect::<Vec<char>>();
+        let spans = re.find_iter(&line).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(spans, vec![1..3, 4..5]);
+
+        // 空文字列にマッチした場合は、次の文字の境界まで進む
+        let re = Regex::new("x*", is_depth).unwrap();
+        let spans = re.find_iter("äb").collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(spans, vec![0..0, 2..2, 3..3]);
+
+        // 表明は前後の文字をデコードして判定する
+        let re = Regex::new(r"\bé+\b", is_depth).unwrap();
+        assert_eq!(re.find_at("aé é", 0).unwrap(), Some(4..6));
+        let flags = Flags {
+            multi_line: true,
+            ..Default::default()
+        };
+        let re = Regex::with_flags("^b$", flags, is_depth).unwrap();
+        assert_eq!(re.find_at("ä\nb\né", 0).unwrap(), Some(3..4));
+
+        let re = Regex::new("([^=]+)=(.*)", is_depth).unwrap();
+        let caps = re.captures_at("ключ=値", 0).unwrap().unwrap();
+        assert_eq!(caps.get(1), Some(0..8));
+        assert_eq!(caps.get(2), Some(9..12));
+    }
+
+    let line = String::from("äbc");
+    let m = regex::do_matching("äb", &line, false).unwrap();
+    assert_eq!(m, "äb");
+    assert_eq!(m.as_ptr(), line.as_ptr());
+}
+
+#[test]
+fn test_normalize_args() {
+    let normalize = |args: &[&str]| -> Vec<String> {
+        normalize_args(args.iter().map(OsString::from))
+            .into_iter()
+            .map(|arg| arg.into_string().unwrap())
+            .collect()
+    };
+    assert_eq!(
+        normalize(&["regex", "subst", "-i.bak", "s/a/b/", "f"]),
+        ["regex", "subst", "--in-place=.bak", "s/a/b/", "f"]
+    );
+    assert_eq!(
+        normalize(&["regex", "subst", "-i", "-i=x", "s/a/b/"]),
+        ["regex", "subst", "-i", "-i=x", "s/a/b/"]
+    );
+    assert_eq!(
+        normalize(&["regex", "subst", "s/a/b/", "--", "-ix"]),
+        ["regex", "subst", "s/a/b/", "--", "-ix"]
+    );
+    // substがサブコマンドの位置にない場合は変換しない
+    assert_eq!(
+        normalize(&["regex", "-r", "subst", "-in", "f"]),
+        ["regex", "-r", "subst", "-in", "f"]
+    );
+}
@@ -0,0 +1,450 @@
+//! 複数の入力をワーカースレッドで並列に検索する
+use crate::{search::Searcher, walk::Input};
+use regex::DynError;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::{mpsc::channel, Mutex},
+    thread,
+};
+
+/// この大きさ（バイト）以上のファイルは、行単位で分割して検索する
+#[cfg(not(test))]
+const SPLIT_SIZE: u64 = 4 * 1024 * 1024;
+#[cfg(test)]
+const SPLIT_SIZE: u64 = 64 * 1024;
+
+/// ファイルの一部
+///
+/// 行の途中では分割しないため、startは行頭、endは次の行頭（またはファイル末尾）となる。
+#[derive(Debug, PartialEq)]
+pub struct Part {
+    pub start: u64,                // 開始位置（バイト）
+    pub end: u64,                  // 終了位置（バイト、含まない）
+    pub first_line: Option<usize>, // startの行番号。行番号を表示しない場合は数えずNone
+}
+
+/// ワーカースレッドが行う1つの検索
+struct Job<'a> {
+    index: usize,       // 入力の番号
+    part_no: usize,     // 入力中の分割の番号
+    input: &'a Input,   // 入力
+    part: Option<Part>, // 分割した場合の範囲
+}
+
+/// ワーカースレッドの検索結果。選択した行の数（バイナリファイルの場合はNone）と、表示内容
+type JobResult = Result<(Option<usize>, Vec<u8>), DynError>;
+
+/// 入力ごとの検索結果をまとめるための型
+struct Pending {
+    name: String,
+    parts: Vec<Option<JobResult>>,
+    received: usize,
+}
+
+/// 検索結果の集計
+#[derive(Debug, Default, PartialEq)]
+pub struct Stats {
+    pub searched: usize, // 検索した入力の数
+    pub matched: usize,  // 1行以上選択された入力の数
+    pub selected: usize, // 選択した行（-Uの場合は箇所）の数
+    pub errors: usize,   // エラーとなった入力の数
+}
+
+/// 並列検索の設定
+pub struct Pool {
+    pub threads: usize, // ワーカースレッドの数
+    pub ordered: bool,  // 入力の順に表示するか
+    pub split: bool,    // 大きなファイルを分割するか
+}
+
+impl Pool {
+    /// 全ての入力を検索し、結果を集計して返す。
+    ///
+    /// 各入力は1つのジョブとして、threads個のワーカースレッドで検索する。
+    /// 大きなファイルは行単位で分割し、複数のジョブとする。
+    /// 表示は入力ごとにまとめて行い、orderedがtrueの場合は入力の順に、
+    /// falseの場合は検索が終わった順に表示する。
+    ///
+    /// 個々の入力でのエラーは標準エラー出力に表示して検索を続け、had_errorをtrueにする。
+    pub fn run(
+        &self,
+        searcher: &Searcher,
+        inputs: Vec<Result<Input, DynError>>,
+        out: &mut impl Write,
+        had_error: &mut bool,
+    ) -> Result<Stats, DynError> {
+        let mut pending = Vec::new();
+        let mut errors = BTreeMap::new();
+        let mut jobs = Vec::new();
+        for (index, input) in inputs.iter().enumerate() {
+            let input = match input {
+                Ok(input) => input,
+                Err(e) => {
+                    errors.insert(index, e.to_string());
+                    pending.push(None);
+                    continue;
+                }
+            };
+            let parts = match input {
+                Input::File(path) if self.split && self.threads > 1 => {
+                    split_file(path, self.threads, searcher.line_number()).unwrap_or_default()
+                }
+                _ => Vec::new(),
+            };
+            pending.push(Some(Pending {
+                name: input.name(),
+                parts: (0..parts.len().max(1)).map(|_| None).collect(),
+                received: 0,
+            }));
+            if parts.is_empty() {
+                jobs.push(Job {
+                    index,
+                    part_no: 0,
+                    input,
+                    part: None,
+                });
+            }
+            for (part_no, part) in parts.into_iter().enumerate() {
+                jobs.push(Job {
+                    index,
+                    part_no,
+                    input,
+                    part: Some(part),
+                });
+            }
+        }
+
+        let queue = Mutex::new(jobs.into_iter());
+        let (tx, rx) = channel();
+        let mut stats = Stats::default();
+        let mut next = 0; // 次に表示する入力の番号
+        let mut done: BTreeMap<usize, Result<(usize, Vec<u8>), String>> = BTreeMap::new();
+
+        thread::scope(|s| -> Result<(), DynError> {
+            for _ in 0..self.threads.max(1) {
+                let tx = tx.clone();
+                let queue = &queue;
+                s.spawn(move || loop {
+                    let job = queue.lock().unwrap().next();
+                    let Some(job) = job else {
+                        break;
+                    };
+                    let mut buf = Vec::new();
+                    let result = searcher
+                        .search_input(job.input, job.part.as_ref(), &mut buf)
+                        .map(|count| (count, buf));
+                    if tx.send((job.index, job.part_no, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
+
+            // エラーとなった入力は、検索せずに完了とする
+            for (index, e) in errors {
+                done.insert(index, Err(e));
+            }
+            loop {
+                // 表示できる結果を表示
+                while let Some(result) = self.pop_ready(&mut done, next) {
+                    let (index, result) = result;
+                    match result {
+                        Ok((count, buf)) => {
+                            stats.searched += 1;
+                            stats.matched += (count > 0) as usize;
+                            stats.selected += count;
+                            out.write_all(&buf)?;
+                        }
+                        Err(e) => {
+                            out.flush()?;
+                            eprintln!("regex: {e}");
+                            stats.errors += 1;
+                            *had_error = true;
+                        }
+                    }
+                    next = index + 1;
+                }
+
+                let Ok((index, part_no, result)) = rx.recv() else {
+                    break;
+                };
+                let Some(p) = pending[index].as_mut() else {
+                    continue;
+                };
+                p.parts[part_no] = Some(result);
+                p.received += 1;
+                if p.received == p.parts.len() {
+                    let p = pending[index].take().unwrap();
+                    done.insert(index, merge(searcher, p));
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(stats)
+    }
+
+    /// 表示できる結果を1つ取り出す
+    fn pop_ready<T>(&self, done: &mut BTreeMap<usize, T>, next: usize) -> Option<(usize, T)> {
+        if self.ordered {
+            done.remove(&next).map(|r| (next, r))
+        } else {
+            done.pop_first()
+        }
+    }
+}
+
+/// 分割して検索した結果を結合し、-cと-lの表示を追加。
+///
+/// 選択した行の数と、表示内容を返す。
+fn merge(searcher: &Searcher, pending: Pending) -> Result<(usize, Vec<u8>), String> {
+    let mut count = 0;
+    let mut buf = Vec::new();
+    let mut binary = false;
+    for part in pending.parts {
+        // 全ての分割の結果を受信済み
+        let (c, b) = part.unwrap().map_err(|e| e.to_string())?;
+        match c {
+            Some(c) => {
+                count += c;
+                buf.extend(b);
+            }
+            // バイナリファイル。判定は分割時に行うため、分割しない場合のみ
+            None => binary = true,
+        }
+    }
+    if !binary {
+        searcher
+            .write_summary(&pending.name, count, &mut buf)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok((count, buf))
+}
+
+/// SPLIT_SIZE以上のファイルを、最大n個に行単位で分割する。
+///
+/// 分割しない場合は空のVecを返す。
+/// 境界はファイルをn等分した位置にシークし、次の行頭に合わせるため、ファイル全体は読み込まない。
+/// line_numberがtrueの場合は、各部分の改行をスレッドごとに並列に数え、
+/// その累積和から各部分の先頭の行番号を求める。
+/// バイナリファイルの判定はファイルの先頭部分で一度だけ行う。
+/// 先頭部分にNUL文字を含むファイルは分割せず、分割した各部分ではNUL文字の有無によらず検索する。
+fn split_file(path: &Path, n: usize, line_number: bool) -> io::Result<Vec<Part>> {
+    let size = path.metadata()?.len();
+    if size < SPLIT_SIZE || n < 2 {
+        return Ok(Vec::new());
+    }
+    // 各部分の大きさはSPLIT_SIZE / 2以上とする
+    let n = (n as u64).min(size / (SPLIT_SIZE / 2));
+
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.contains(&0) {
+        return Ok(Vec::new());
+    }
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for k in 1..n {
+        // k/nの位置の直前の文字を含む行の、次の行頭を境界とする
+        let pos = k * size / n - 1;
+        reader.seek(SeekFrom::Start(pos))?;
+        let end = pos + reader.skip_until(b'\n')? as u64;
+        // 長い行が次の境界をまたぐ場合は、その境界を省く
+        if end <= start || end >= size {
+            continue;
+        }
+        parts.push(Part {
+            start,
+            end,
+            first_line: None,
+        });
+        start = end;
+    }
+    parts.push(Part {
+        start,
+        end: size,
+        first_line: None,
+    });
+    if line_number {
+        number_lines(path, &mut parts)?;
+    }
+    Ok(parts)
+}
+
+/// 各部分の改行をスレッドごとに並列に数え、先頭の行番号を設定する
+fn number_lines(path: &Path, parts: &mut [Part]) -> io::Result<()> {
+    let counts = thread::scope(|s| {
+        let handles = parts
+            .iter()
+            .map(|part| s.spawn(move || count_newlines(path, part)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<io::Result<Vec<usize>>>()
+    })?;
+    let mut line = 1;
+    for (part, count) in parts.iter_mut().zip(counts) {
+        part.first_line = Some(line);
+        line += count;
+    }
+    Ok(())
+}
+
+/// ファイルの部分partに含まれる改行の数
+fn count_newlines(path: &Path, part: &Part) -> io::Result<usize> {
+    let mut f = File::open(path)?;
+    f.seek(SeekFrom::Start(part.start))?;
+    let mut reader = BufReader::new(f.take(part.end - part.start));
+    let mut count = 0;
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(count);
+        }
+        count += buf.iter().filter(|b| **b == b'\n').count();
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+#[test]
+fn test() {
+    use crate::Args;
+    use clap::Parser;
+
+    // 分割しても、分割しない場合と同じ結果となる
+    let dir = std::env::temp_dir().join(format!("regex-parallel-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let big = dir.join("big.txt");
+    let mut text = String::new();
+    let mut i = 0;
+    while (text.len() as u64) < SPLIT_SIZE + 1024 {
+        i += 1;
+        text.push_str(&format!(
+            "line {i} {}\n",
+            if i % 100 == 0 { "hit" } else { "-" }
+        ));
+    }
+    std::fs::write(&big, &text).unwrap();
+    let small = dir.join("small.txt");
+    std::fs::write(&small, "hit\nmiss\n").unwrap();
+
+    let parts = split_file(&big, 4, true).unwrap();
+    assert!(parts.len() > 1);
+    assert_eq!(parts[0].start, 0);
+    assert_eq!(parts[0].first_line, Some(1));
+    assert_eq!(parts.last().unwrap().end, text.len() as u64);
+    for w in parts.windows(2) {
+        assert_eq!(w[0].end, w[1].start);
+        assert_eq!(text.as_bytes()[w[1].start as usize - 1], b'\n');
+        let lines = text[..w[1].start as usize].matches('\n').count();
+        assert_eq!(w[1].first_line, Some(lines + 1));
+    }
+    // 行番号を表示しない場合は改行を数えない
+    let unnumbered = split_file(&big, 4, false).unwrap();
+    assert!(unnumbered.iter().all(|p| p.first_line.is_none()));
+    let bounds = |parts: &[Part]| parts.iter().map(|p| p.start..p.end).collect::<Vec<_>>();
+    assert_eq!(bounds(&unnumbered), bounds(&parts));
+
+    // 境界をまたぐ長い行は分割しない
+    let long = dir.join("long.txt");
+    let mut long_text = "x".repeat(SPLIT_SIZE as usize * 3 / 2);
+    long_text.push_str("\nhit\n");
+    std::fs::write(&long, &long_text).unwrap();
+    let long_parts = split_file(&long, 4, true).unwrap();
+    assert_eq!(
+        bounds(&long_parts),
+        vec![
+            0..long_text.len() as u64 - 4,
+            long_text.len() as u64 - 4..long_text.len() as u64
+        ]
+    );
+    assert_eq!(long_parts[1].first_line, Some(2));
+
+    let search = |extra: &[&str], pool: Pool| {
+        let mut argv = vec!["regex", "-r", "hit", "-n"];
+        argv.extend(extra);
+        let args = Args::try_parse_from(argv).unwrap();
+        let mut searcher = Searcher::new(&args).unwrap();
+        searcher.with_filename = true;
+        let inputs = vec![
+            Ok(Input::File(big.clone())),
+            Err("error".into()),
+            Ok(Input::File(small.clone())),
+        ];
+        let mut out = Vec::new();
+        let mut had_error = false;
+        let stats = pool
+            .run(&searcher, inputs, &mut out, &mut had_error)
+            .unwrap();
+        assert!(had_error);
+        assert_eq!((stats.searched, stats.matched, stats.errors), (2, 2, 1));
+        String::from_utf8(out).unwrap()
+    };
+    for extra in [&[][..], &["-c"], &["-l"], &["-v", "-c"]] {
+        let expected = search(
+            extra,
+            Pool {
+                threads: 1,
+                ordered: true,
+                split: false,
+            },
+        );
+        let actual = search(
+            extra,
+            Pool {
+                threads: 4,
+                ordered: true,
+                split: true,
+            },
+        );
+        assert_eq!(actual, expected);
+        let mut lines = search(
+            extra,
+            Pool {
+                threads: 4,
+                ordered: false,
+                split: true,
+            },
+        )
+        .lines()
+        .map(String::from)
+        .collect::<Vec<_>>();
+        lines.sort();
+        let mut expected = expected.lines().map(String::from).collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(lines, expected);
+    }
+
+    // 分割の境界の直後にNUL文字があっても、他の部分の結果は失われない
+    let nul = dir.join("nul.txt");
+    let mut bytes = text.clone().into_bytes();
+    bytes[parts[1].start as usize] = 0;
+    std::fs::write(&nul, &bytes).unwrap();
+    assert_eq!(split_file(&nul, 4, true).unwrap(), parts);
+    let search_nul = |pool: Pool| {
+        let args = Args::try_parse_from(["regex", "-r", "hit", "-c"]).unwrap();
+        let searcher = Searcher::new(&args).unwrap();
+        let mut out = Vec::new();
+        let inputs = vec![Ok(Input::File(nul.clone()))];
+        let stats = pool.run(&searcher, inputs, &mut out, &mut false).unwrap();
+        (stats.selected, String::from_utf8(out).unwrap())
+    };
+    let expected = search_nul(Pool {
+        threads: 1,
+        ordered: true,
+        split: false,
+    });
+    assert_eq!(expected.0, text.matches("hit").count());
+    let actual = search_nul(Pool {
+        threads: 4,
+        ordered: true,
+        split: true,
+    });
+    assert_eq!(actual, expected);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
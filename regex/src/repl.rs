@@ -0,0 +1,257 @@
+//! 正規表現を対話的に試すreplサブコマンド
+//!
+//! パターンを入力した後、テスト用の文字列を1行ずつ入力すると、
+//! ASTと命令列、マッチ範囲、深さ優先探索と幅優先探索の統計情報を表示する。
+use regex::{Captures, DynError, Flags, Haystack, Regex, Stats};
+use rustyline::{error::ReadlineError, DefaultEditor};
+use std::io::{self, Write};
+
+const HISTORY_FILE: &str = ".regex_history";
+
+const HELP: &str = "\
+パターンを入力した後、テスト用の文字列を1行ずつ入力してください。
+  :re PATTERN  パターンを変更（PATTERNを省略した場合は、次の行をパターンとする）
+  :help        このヘルプを表示
+  :quit        終了（Ctrl+Dでも終了）
+`:`で始まる文字列をテストする場合は、先頭に`\\`を付けてください。";
+
+/// 1つの探索方法での評価結果
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    spans: Vec<String>, // マッチ範囲（グループがある場合はその範囲も含む）
+    stats: Stats,       // 全ての開始位置での評価の統計情報
+}
+
+/// 入力中のパターン
+struct Pattern {
+    expr: String,
+    dfs: Regex,
+    bfs: Option<Regex>, // アトミックグループなど、幅優先探索で扱えない場合はNone
+}
+
+impl Pattern {
+    /// 深さ優先探索でコンパイルできない場合のみエラーとする
+    fn new(expr: &str) -> Result<Self, DynError> {
+        let dfs = Regex::new(expr, true)?;
+        Ok(Pattern {
+            expr: expr.to_string(),
+            dfs,
+            bfs: Regex::new(expr, false).ok(),
+        })
+    }
+}
+
+/// replサブコマンドを実行
+pub fn run() -> Result<(), DynError> {
+    let history = match dirs::home_dir() {
+        Some(home) => home.join(HISTORY_FILE),
+        None => HISTORY_FILE.into(),
+    };
+    let mut rl = DefaultEditor::new()?;
+    if history.is_file() {
+        if let Err(e) = rl.load_history(&history) {
+            eprintln!("regex: ヒストリファイルの読み込みに失敗: {e}");
+        }
+    }
+    println!("{HELP}");
+
+    let mut pattern: Option<Pattern> = None;
+    loop {
+        let prompt = if pattern.is_some() {
+            "test > "
+        } else {
+            "regex > "
+        };
+        let line = match rl.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                eprintln!("<<終了はCtrl+D>>");
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if line.is_empty() && pattern.is_none() {
+            continue;
+        }
+        rl.add_history_entry(line.as_str())?;
+
+        let (cmd, arg) = match line.strip_prefix(':') {
+            Some(cmd) => cmd.split_once(' ').unwrap_or((cmd, "")),
+            None => ("", line.as_str()),
+        };
+        match cmd {
+            "" if pattern.is_none() => pattern = set_pattern(arg),
+            "" => {
+                // 先頭の`\:`は`:`とする
+                let text = arg.strip_prefix('\\').filter(|t| t.starts_with(':'));
+                if let Some(p) = &pattern {
+                    if let Err(e) = show(p, text.unwrap_or(arg)) {
+                        eprintln!("regex: {e}");
+                    }
+                }
+            }
+            "re" | "r" if arg.is_empty() => pattern = None,
+            "re" | "r" => pattern = set_pattern(arg),
+            "help" | "h" => println!("{HELP}"),
+            "quit" | "q" => break,
+            _ => eprintln!("regex: 不明なコマンド: :{cmd}"),
+        }
+    }
+
+    if let Err(e) = rl.save_history(&history) {
+        eprintln!("regex: ヒストリファイルの書き込みに失敗: {e}");
+    }
+    Ok(())
+}
+
+/// パターンをコンパイルし、ASTと命令列を表示
+fn set_pattern(expr: &str) -> Option<Pattern> {
+    match Pattern::new(expr).and_then(|p| dump(expr).map(|_| p)) {
+        Ok(p) => {
+            if p.bfs.is_none() {
+                println!("BFS: unsupported");
+            }
+            Some(p)
+        }
+        Err(e) => {
+            eprintln!("regex: {e}");
+            None
+        }
+    }
+}
+
+/// パターンとそのAST、命令列を表示
+fn dump(expr: &str) -> Result<(), DynError> {
+    println!("expr: {expr}");
+    println!("AST: {:?}", regex::parse(expr, Flags::default())?);
+    print!("code:\n{}", regex::listing(expr, Flags::default())?);
+    Ok(())
+}
+
+/// ASTと命令列、および両方の探索方法での評価結果を並べて表示
+fn show(pattern: &Pattern, text: &str) -> Result<(), DynError> {
+    dump(&pattern.expr)?;
+    let dfs = evaluate(&pattern.dfs, text)?;
+    let bfs = match &pattern.bfs {
+        Some(re) => Some(evaluate(re, text)?),
+        None => None,
+    };
+
+    let dfs_spans = format_spans(&dfs.spans);
+    let width = dfs_spans.chars().count().max(8) + 2;
+    let mut out = io::stdout().lock();
+    writeln!(out, "{:<8}{:<width$}BFS", "", "DFS")?;
+    let Some(bfs) = bfs else {
+        writeln!(out, "{:<8}{dfs_spans:<width$}unsupported", "spans")?;
+        for (name, d) in stats_rows(&dfs.stats) {
+            writeln!(out, "{name:<8}{d:<width$}-")?;
+        }
+        return Ok(());
+    };
+    writeln!(
+        out,
+        "{:<8}{dfs_spans:<width$}{}",
+        "spans",
+        format_spans(&bfs.spans)
+    )?;
+    for ((name, d), (_, b)) in stats_rows(&dfs.stats).zip(stats_rows(&bfs.stats)) {
+        writeln!(out, "{name:<8}{d:<width$}{b}")?;
+    }
+    Ok(())
+}
+
+/// 表示する統計情報の項目と値
+fn stats_rows(stats: &Stats) -> impl Iterator<Item = (&'static str, usize)> {
+    [
+        ("insts", stats.instructions),
+        ("threads", stats.threads),
+        ("queue", stats.peak_queue),
+        ("hits", stats.cache_hits),
+        ("misses", stats.cache_misses),
+    ]
+    .into_iter()
+}
+
+/// マッチ範囲の一覧を表示用の文字列に変換
+fn format_spans(spans: &[String]) -> String {
+    if spans.is_empty() {
+        "no match".to_string()
+    } else {
+        spans.join(" ")
+    }
+}
+
+/// 先頭から順に全ての開始位置で評価し、重ならないマッチ範囲と統計情報を求める
+///
+/// マッチ範囲はバイト単位の位置で表す。
+fn evaluate(re: &Regex, line: &str) -> Result<Outcome, DynError> {
+    let mut spans = Vec::new();
+    let mut total = Stats::default();
+    let mut pos = Some(0);
+    while let Some(start) = pos {
+        let (end, stats) = re.match_with_stats(line, start)?;
+        total += stats;
+        // 1文字進めた位置。行末の場合はNone
+        let next = line.char_at(start).map(|(_, next)| next);
+        pos = match end {
+            Some(end) => {
+                if let Some(caps) = re.captures_at(line, start)? {
+                    spans.push(format_captures(&caps));
+                }
+                // 空文字列にマッチした場合は、同じ位置で再度マッチしないよう1文字進める
+                if end == start {
+                    next
+                } else {
+                    Some(end)
+                }
+            }
+            None => next,
+        };
+    }
+    Ok(Outcome {
+        spans,
+        stats: total,
+    })
+}
+
+/// マッチ範囲を`0..3`、グループがある場合は`0..3(0..1,-)`の形式で表す
+fn format_captures(caps: &Captures) -> String {
+    let mut spans = caps.iter().map(|r| match r {
+        Some(r) => format!("{}..{}", r.start, r.end),
+        None => "-".to_string(),
+    });
+    let mut s = spans.next().unwrap_or_default();
+    let groups = spans.collect::<Vec<_>>();
+    if !groups.is_empty() {
+        s.push_str(&format!("({})", groups.join(",")));
+    }
+    s
+}
+
+#[test]
+fn test() {
+    let pattern = Pattern::new("(a|b(c))+").unwrap();
+    let line = "xabcab";
+    let dfs = evaluate(&pattern.dfs, line).unwrap();
+    let bfs = evaluate(pattern.bfs.as_ref().unwrap(), line).unwrap();
+    assert_eq!(dfs.spans, vec!["1..5(4..5,3..4)"]);
+    assert_eq!(bfs.spans, dfs.spans);
+    assert!(dfs.stats.instructions > 0 && bfs.stats.instructions > 0);
+    assert!(dfs.stats.threads > 0 && bfs.stats.peak_queue > 0);
+
+    let re = Regex::new("x?", true).unwrap();
+    let outcome = evaluate(&re, &line[..2]).unwrap();
+    assert_eq!(outcome.spans, vec!["0..1", "1..1", "2..2"]);
+    let outcome = evaluate(&re, "あx").unwrap();
+    assert_eq!(outcome.spans, vec!["0..0", "3..4", "4..4"]);
+    assert_eq!(format_spans(&[]), "no match");
+
+    // 幅優先探索で扱えないパターンも、深さ優先探索のみで評価する
+    for expr in ["(?>a)b", "a*+"] {
+        let pattern = Pattern::new(expr).unwrap();
+        assert!(pattern.bfs.is_none(), "{expr}");
+        assert!(evaluate(&pattern.dfs, "aab").is_ok());
+    }
+    assert!(Pattern::new("(a").is_err());
+}
@@ -0,0 +1,660 @@
+//! grep互換の検索処理
+use crate::{
+    json::{self, Text},
+    parallel::Part,
+    walk::Input,
+    Args, SearchMethod,
+};
+use regex::{DynError, Haystack, Regex, Stats, Syntax};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// 検索を行う型
+///
+/// 正規表現は生成時に一度だけコンパイルする。
+pub struct Searcher<'a> {
+    re: Regex,
+    args: &'a Args,
+    pub with_filename: bool, // 各行の先頭にファイル名を表示するか
+    pub color: bool,         // 色付けして表示するか
+    matches: AtomicUsize,    // --jsonで出力したマッチの数
+    stats: Mutex<Stats>,     // --statsで表示する統計情報
+    word_group: bool,        // -wでパターンを囲むキャプチャグループを追加したか
+}
+
+/// 色付けに用いるエスケープシーケンス
+mod color {
+    pub const MATCH: &str = "\x1b[01;31m"; // マッチ部分 : 赤の太字
+    pub const FILE: &str = "\x1b[35m"; // ファイル名 : マゼンタ
+    pub const LINE: &str = "\x1b[32m"; // 行番号 : 緑
+    pub const SEP: &str = "\x1b[36m"; // 区切り文字 : シアン
+    pub const RESET: &str = "\x1b[m";
+}
+
+impl<'a> Searcher<'a> {
+    /// -wの場合は、パターンを単語境界の表明で囲んでコンパイルする。
+    ///
+    /// 前後が単語の文字でない範囲のうち最長のものにマッチするため、
+    /// たとえば`-w 'foo|foobar'`は`foobar`の全体にマッチする。
+    /// POSIXの構文では非キャプチャグループがないため、キャプチャグループで囲む。
+    pub fn new(args: &'a Args) -> Result<Self, DynError> {
+        let is_depth = args.method == SearchMethod::Dfs;
+        // エラーの位置が元のパターンでの位置となるよう、先にそのままコンパイルする
+        let mut re = Regex::with_flags(args.pattern(), args.flags(), is_depth)?;
+        let word_group = args.word_regexp && args.syntax() != Syntax::Native;
+        if args.word_regexp {
+            let pattern = match args.syntax() {
+                Syntax::Native => format!("\\b(?:{})\\b", args.pattern()),
+                Syntax::Basic => format!("\\b\\({}\\)\\b", args.pattern()),
+                Syntax::Extended => format!("\\b({})\\b", args.pattern()),
+            };
+            re = Regex::with_flags(&pattern, args.flags(), is_depth)?;
+        }
+        Ok(Searcher {
+            re,
+            args,
+            with_filename: false,
+            color: false,
+            matches: AtomicUsize::new(0),
+            stats: Mutex::new(Stats::default()),
+            word_group,
+        })
+    }
+
+    /// 行番号を表示するか（-n）
+    pub fn line_number(&self) -> bool {
+        self.args.line_number
+    }
+
+    /// --statsで表示する、全ての入力でのマッチングの統計情報
+    pub fn stats(&self) -> Stats {
+        *self.stats.lock().unwrap()
+    }
+
+    /// --jsonで出力したマッチの数
+    pub fn matches(&self) -> usize {
+        self.matches.load(Ordering::Relaxed)
+    }
+
+    /// 入力を検索し、結果をoutに書き込む。
+    ///
+    /// partが指定された場合は、ファイルのその範囲のみ検索する。
+    /// -cと-lの表示は行わず、選択した行（-Uの場合は箇所）の数を返す。
+    /// 表示は[Searcher::write_summary]で行う。
+    /// バイナリファイルのため検索しなかった場合はNoneを返す。
+    pub fn search_input(
+        &self,
+        input: &Input,
+        part: Option<&Part>,
+        out: &mut impl Write,
+    ) -> Result<Option<usize>, DynError> {
+        let name = input.name();
+        let path = match input {
+            Input::Stdin => {
+                return self.search_count(io::stdin().lock(), &name, 1, self.args.text, out)
+            }
+            Input::File(path) => path,
+        };
+        let mut f = File::open(path).map_err(|e| format!("{name}: {e}"))?;
+        match part {
+            Some(part) => {
+                // バイナリファイルの判定は分割時にファイルの先頭で行うため、各部分では行わない
+                f.seek(SeekFrom::Start(part.start))?;
+                let reader = BufReader::new(f.take(part.end - part.start));
+                // 行番号を表示しない場合は数えていないため、仮に1とする
+                let first_line = part.first_line.unwrap_or(1);
+                self.search_count(reader, &name, first_line, true, out)
+            }
+            None => self.search_count(BufReader::new(f), &name, 1, self.args.text, out),
+        }
+    }
+
+    /// readerから読み込んで検索し、結果をoutに書き込む。
+    ///
+    /// nameはファイル名の表示に用いる。
+    /// 1行（-Uの場合は1箇所）以上選択された場合にtrueを返す。
+    #[cfg(test)]
+    fn search_reader(
+        &self,
+        reader: impl BufRead,
+        name: &str,
+        out: &mut impl Write,
+    ) -> Result<bool, DynError> {
+        match self.search_count(reader, name, 1, self.args.text, out)? {
+            Some(count) => self.write_summary(name, count, out),
+            None => Ok(false),
+        }
+    }
+
+    /// readerから読み込んで検索し、選択した行の数を返す。
+    ///
+    /// first_lineはreaderの先頭の行番号。
+    /// 先頭部分にNUL文字を含む場合はバイナリファイルとみなし、textがfalseの場合は検索せずにNoneを返す。
+    fn search_count(
+        &self,
+        mut reader: impl BufRead,
+        name: &str,
+        first_line: usize,
+        text: bool,
+        out: &mut impl Write,
+    ) -> Result<Option<usize>, DynError> {
+        if !text && reader.fill_buf()?.contains(&0) {
+            return Ok(None);
+        }
+        let count = if self.args.multiline {
+            let mut buf = Vec::new();
+            reader.read_to_end(&mut buf)?;
+            self.search_whole(&buf, name, out)?
+        } else {
+            self.search_lines(reader, name, first_line, out)?
+        };
+        Ok(Some(count))
+    }
+
+    /// 行ごとにマッチングを行う。
+    ///
+    /// マッチングはそれぞれの行頭から1文字ずつずらして行い、
+    /// いずれかにマッチした場合に、その行がマッチしたものとみなす。
+    ///
+    /// たとえば、abcdという文字列があった場合、以下の順にマッチが行われ、
+    /// このいずれかにマッチした場合、与えられた正規表現にマッチする行と判定する。
+    ///
+    /// - abcd
+    /// - bcd
+    /// - cd
+    /// - d
+    fn search_lines(
+        &self,
+        mut reader: impl BufRead,
+        path: &str,
+        first_line: usize,
+        out: &mut impl Write,
+    ) -> Result<usize, DynError> {
+        let args = self.args;
+        let (before_n, after_n) = self.context();
+        let mut before: VecDeque<(usize, String)> = VecDeque::new(); // 前方の文脈行
+        let mut after_left = 0; // 残りの後方の文脈行数
+        let mut last_printed: Option<usize> = None; // 最後に表示した行番号
+        let mut count = 0;
+        let mut buf = Vec::new();
+        let mut lineno = first_line - 1;
+        let (mut byte_pos, mut char_pos) = (0, 0); // 行頭の位置（--json用）
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+            lineno += 1;
+            let nl = buf.last() == Some(&b'\n');
+            if nl {
+                buf.pop();
+            }
+            let line = String::from_utf8_lossy(&buf);
+            let text = Text::new(&line, &buf, byte_pos, char_pos);
+            byte_pos += buf.len() + nl as usize;
+            char_pos += line.chars().count() + nl as usize;
+
+            let first = self.find(&line, 0)?;
+            if first.is_some() == args.invert_match {
+                if after_left > 0 {
+                    after_left -= 1;
+                    let prefix = self.prefix(path, Some(lineno), '-');
+                    writeln!(out, "{prefix}{line}")?;
+                    last_printed = Some(lineno);
+                } else if before_n > 0 {
+                    before.push_back((lineno, line.into_owned()));
+                    if before.len() > before_n {
+                        before.pop_front();
+                    }
+                }
+                continue;
+            }
+            count += 1;
+            if args.files_with_matches {
+                break;
+            }
+            if args.count {
+                continue;
+            }
+            if args.json {
+                for m in self.find_all(&line, first)? {
+                    self.write_json(path, lineno, &text, m, out)?;
+                }
+                continue;
+            }
+
+            let prefix = self.prefix(path, Some(lineno), ':');
+            // -vの場合はfirstがNoneのため、マッチ部分は空となる
+            let matches = self.find_all(&line, first)?;
+            if args.only_matching {
+                for m in matches {
+                    writeln!(out, "{prefix}{}", self.paint(&line[m], color::MATCH))?;
+                }
+                continue;
+            }
+
+            // 文脈行を表示
+            if before_n > 0 || after_n > 0 {
+                let first_line = before.front().map_or(lineno, |(n, _)| *n);
+                if last_printed.is_some_and(|n| n + 1 < first_line) {
+                    writeln!(out, "{}", self.paint("--", color::SEP))?;
+                }
+                for (n, l) in before.drain(..) {
+                    let prefix = self.prefix(path, Some(n), '-');
+                    writeln!(out, "{prefix}{l}")?;
+                }
+                after_left = after_n;
+            }
+            writeln!(out, "{prefix}{}", self.highlight(&line, &matches))?;
+            last_printed = Some(lineno);
+        }
+
+        Ok(count)
+    }
+
+    /// -A、-B、-Cで指定された前方と後方の文脈行数
+    ///
+    /// -o、-c、-lの場合は文脈行を表示しないため0とする。
+    fn context(&self) -> (usize, usize) {
+        let args = self.args;
+        if args.only_matching || args.count || args.files_with_matches {
+            return (0, 0);
+        }
+        let context = args.context.unwrap_or(0);
+        (
+            args.before_context.unwrap_or(context),
+            args.after_context.unwrap_or(context),
+        )
+    }
+
+    /// firstから始まる、空でないマッチ範囲を全て返す
+    fn find_all(
+        &self,
+        line: &str,
+        first: Option<Range<usize>>,
+    ) -> Result<Vec<Range<usize>>, DynError> {
+        let mut matches = Vec::new();
+        let mut m = first;
+        while let Some(r) = m {
+            // 空文字列にマッチした場合は1文字進め、行末の場合は終了
+            let next = if r.is_empty() {
+                line.char_at(r.end).map(|(_, next)| next)
+            } else {
+                Some(r.end)
+            };
+            if !r.is_empty() {
+                matches.push(r);
+            }
+            m = match next {
+                Some(next) => self.find(line, next)?,
+                None => None,
+            };
+        }
+        Ok(matches)
+    }
+
+    /// マッチ範囲を色付けした行
+    fn highlight(&self, line: &str, matches: &[Range<usize>]) -> String {
+        let mut s = String::new();
+        let mut pos = 0;
+        for m in matches {
+            s.push_str(&line[pos..m.start]);
+            s.push_str(&self.paint(&line[m.clone()], color::MATCH));
+            pos = m.end;
+        }
+        s.push_str(&line[pos..]);
+        s
+    }
+
+    /// 色付けが有効な場合、textをエスケープシーケンスで囲む
+    fn paint(&self, text: &str, color: &str) -> String {
+        if self.color {
+            format!("{color}{text}{}", color::RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// ファイル全体を1つの文字列として、改行をまたいでマッチングを行う。
+    ///
+    /// マッチごとに、開始位置と終了位置を「行:桁」（いずれも1始まり）で表示する。
+    /// 終了位置はマッチした範囲の直後を指す。
+    fn search_whole(
+        &self,
+        raw: &[u8],
+        path: &str,
+        out: &mut impl Write,
+    ) -> Result<usize, DynError> {
+        let args = self.args;
+        let text = &*String::from_utf8_lossy(raw);
+        let json_text = Text::new(text, raw, 0, 0);
+        let positions = LinePositions::new(text);
+        let mut count = 0;
+        let mut pos = Some(0);
+        while let Some(start) = pos {
+            let Some(m) = self.find(text, start)? else {
+                break;
+            };
+            pos = if m.is_empty() {
+                text.char_at(m.end).map(|(_, next)| next)
+            } else {
+                Some(m.end)
+            };
+            count += 1;
+            if args.files_with_matches {
+                break;
+            }
+            if args.count {
+                continue;
+            }
+            let (l1, c1) = positions.line_col(m.start);
+            if args.json {
+                self.write_json(path, l1, &json_text, m, out)?;
+                continue;
+            }
+            let (l2, c2) = positions.line_col(m.end);
+            let prefix = self.prefix(path, None, ':');
+            let text = self.paint(&text[m], color::MATCH);
+            writeln!(out, "{prefix}{l1}:{c1}-{l2}:{c2}:{text}")?;
+        }
+
+        Ok(count)
+    }
+
+    /// マッチ範囲mを、キャプチャグループの範囲とともにJSONで出力
+    fn write_json(
+        &self,
+        path: &str,
+        lineno: usize,
+        text: &Text,
+        m: Range<usize>,
+        out: &mut impl Write,
+    ) -> Result<(), DynError> {
+        // 同じ開始位置での最長のマッチのため、終了位置はmと一致する
+        if let Some(caps) = self.re.captures_at(text.text, m.start)? {
+            let names = self.re.capture_names();
+            let skip = usize::from(self.word_group);
+            json::write_match(out, path, lineno, text, &caps, names, skip)?;
+            self.matches.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// -cと-lの場合の表示を行い、選択した数が1以上の場合にtrueを返す
+    pub fn write_summary(
+        &self,
+        path: &str,
+        count: usize,
+        out: &mut impl Write,
+    ) -> Result<bool, DynError> {
+        if self.args.files_with_matches {
+            if count > 0 {
+                writeln!(out, "{}", self.paint(path, color::FILE))?;
+            }
+        } else if self.args.count {
+            let prefix = self.prefix(path, None, ':');
+            writeln!(out, "{prefix}{count}")?;
+        }
+        Ok(count > 0)
+    }
+
+    /// 各行の先頭に表示する、ファイル名と行番号
+    ///
+    /// sepは区切り文字で、選択した行では`:`、文脈行では`-`とする。
+    fn prefix(&self, path: &str, lineno: Option<usize>, sep: char) -> String {
+        let sep = self.paint(&sep.to_string(), color::SEP);
+        let mut prefix = String::new();
+        if self.with_filename {
+            prefix.push_str(&self.paint(path, color::FILE));
+            prefix.push_str(&sep);
+        }
+        if let Some(n) = lineno.filter(|_| self.args.line_number) {
+            prefix.push_str(&self.paint(&n.to_string(), color::LINE));
+            prefix.push_str(&sep);
+        }
+        prefix
+    }
+
+    /// バイト位置start以降で、-xの条件を満たす最初のマッチ範囲を返す
+    fn find(&self, line: &str, start: usize) -> Result<Option<Range<usize>>, DynError> {
+        if self.args.line_regexp {
+            // 最長のマッチが行末まで達するかで判定
+            if start == 0 && self.match_at(line, 0)? == Some(line.len()) {
+                return Ok(Some(0..line.len()));
+            }
+            return Ok(None);
+        }
+        self.find_at(line, start)
+    }
+
+    /// [Regex::find_at]を呼び出し、--statsの場合は統計情報を集計する
+    fn find_at(&self, line: &str, start: usize) -> Result<Option<Range<usize>>, DynError> {
+        if !self.args.stats {
+            return Ok(self.re.find_at(line, start)?);
+        }
+        let (m, stats) = self.re.find_at_with_stats(line, start)?;
+        *self.stats.lock().unwrap() += stats;
+        Ok(m)
+    }
+
+    /// [Regex::match_at]を呼び出し、--statsの場合は統計情報を集計する
+    fn match_at(&self, line: &str, start: usize) -> Result<Option<usize>, DynError> {
+        if !self.args.stats {
+            return Ok(self.re.match_at(line, start)?);
+        }
+        let (end, stats) = self.re.match_with_stats(line, start)?;
+        *self.stats.lock().unwrap() += stats;
+        Ok(end)
+    }
+}
+
+/// バイト位置から行と桁を求めるための、各行の先頭位置の一覧
+struct LinePositions<'a> {
+    text: &'a str,
+    starts: Vec<usize>, // 各行の先頭のバイト位置
+}
+
+impl<'a> LinePositions<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        LinePositions { text, starts }
+    }
+
+    /// バイト位置posの行と桁を、1始まりで返す。桁は文字単位とする
+    fn line_col(&self, pos: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|s| *s <= pos) - 1;
+        let col = self.text[self.starts[line]..pos].chars().count();
+        (line + 1, col + 1)
+    }
+}
+
+#[test]
+fn test() {
+    use crate::ColorChoice;
+    use clap::Parser;
+
+    fn grep(argv: &[&str], text: &str) -> (bool, String) {
+        let args = Args::try_parse_from(["regex"].iter().chain(argv)).unwrap();
+        let mut searcher = Searcher::new(&args).unwrap();
+        searcher.with_filename = args.with_filename;
+        searcher.color = args.color == ColorChoice::Always;
+        let mut out = Vec::new();
+        let found = searcher
+            .search_reader(text.as_bytes(), "-", &mut out)
+            .unwrap();
+        (found, String::from_utf8(out).unwrap())
+    }
+
+    let text = "foo bar\nFoo_bar\nbaz\nfoofoo\n";
+    assert_eq!(
+        grep(&["-r", "foo"], text),
+        (true, "foo bar\nfoofoo\n".into())
+    );
+    assert_eq!(grep(&["-r", "qux"], text), (false, "".into()));
+    assert_eq!(
+        grep(&["-r", "foo", "-v"], text),
+        (true, "Foo_bar\nbaz\n".into())
+    );
+    assert_eq!(grep(&["-r", "foo", "-c"], text), (true, "2\n".into()));
+    assert_eq!(
+        grep(&["-r", "foo", "-n"], text),
+        (true, "1:foo bar\n4:foofoo\n".into())
+    );
+    assert_eq!(
+        grep(&["-r", "foo", "-o", "-n"], text),
+        (true, "1:foo\n4:foo\n4:foo\n".into())
+    );
+    assert_eq!(grep(&["-r", "foo", "-l"], text), (true, "-\n".into()));
+    assert_eq!(grep(&["-r", "foo", "-i", "-c"], text), (true, "3\n".into()));
+    assert_eq!(grep(&["-r", "foo", "-w"], text), (true, "foo bar\n".into()));
+    assert_eq!(
+        grep(&["-r", "foo|foofoo", "-w", "-o"], text),
+        (true, "foo\nfoofoo\n".into())
+    );
+    assert_eq!(
+        grep(&["-r", "é", "-w"], "caf é\ncafé\n"),
+        (true, "caf é\n".into())
+    );
+    // POSIXの構文
+    assert_eq!(
+        grep(&["-r", "\\(fo\\{1,2\\}\\)\\{2\\}$", "-G"], text),
+        (true, "foofoo\n".into())
+    );
+    assert_eq!(
+        grep(&["-r", "(fo{2}){2}|a+?", "-G", "-c"], text),
+        (false, "0\n".into())
+    );
+    assert_eq!(
+        grep(&["-r", "^[[:upper:]]o+|z$", "-E"], text),
+        (true, "Foo_bar\nbaz\n".into())
+    );
+    assert_eq!(
+        grep(&["-r", "foo|bar", "-E", "-w", "-o"], text),
+        (true, "foo\nbar\n".into())
+    );
+    assert_eq!(
+        grep(&["-r", "foo\\|baz", "-G", "-w", "-c"], text),
+        (true, "2\n".into())
+    );
+    assert_eq!(grep(&["-r", "ba.", "-x"], text), (true, "baz\n".into()));
+    assert_eq!(
+        grep(&["-r", "(foo)+", "-x", "-m", "bfs"], text),
+        (true, "foofoo\n".into())
+    );
+    assert_eq!(
+        grep(&["-r", "r\nF", "-U"], text),
+        (true, "1:7-2:2:r\nF\n".into())
+    );
+    assert_eq!(grep(&["-r", "^f", "-U", "-c"], text), (true, "2\n".into()));
+    assert_eq!(
+        grep(&["-r", "baz", "-H", "-n"], text),
+        (true, "-:3:baz\n".into())
+    );
+    assert_eq!(grep(&["-r", "a", "-c"], "a\0"), (false, "".into()));
+    assert_eq!(grep(&["-r", "a", "-c", "-a"], "a\0"), (true, "1\n".into()));
+
+    // context
+    let text = "1\n2\nx3\n4\n5\n6\nx7\n8\nx9\n10\n";
+    assert_eq!(
+        grep(&["-r", "x", "-n", "-C", "1"], text),
+        (
+            true,
+            "2-2\n3:x3\n4-4\n--\n6-6\n7:x7\n8-8\n9:x9\n10-10\n".into()
+        )
+    );
+    assert_eq!(
+        grep(&["-r", "x", "-A", "1", "-B", "0"], text),
+        (true, "x3\n4\n--\nx7\n8\nx9\n10\n".into())
+    );
+    assert_eq!(
+        grep(&["-r", "x", "-B", "3"], "x1\nx2\n"),
+        (true, "x1\nx2\n".into())
+    );
+
+    // color
+    assert_eq!(
+        grep(&["-r", "b+", "--color=always", "-H"], "abcb\n"),
+        (
+            true,
+            "\x1b[35m-\x1b[m\x1b[36m:\x1b[ma\x1b[01;31mb\x1b[mc\x1b[01;31mb\x1b[m\n".into()
+        )
+    );
+
+    // json
+    let json = |argv: &[&str], text: &str| {
+        let (_, out) = grep(argv, text);
+        out.lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .collect::<Vec<_>>()
+    };
+    let v = json(&["-r", "(x)?(é+)", "--json"], "a\nbéé c é\n");
+    assert_eq!(v.len(), 2);
+    assert_eq!(
+        v[0],
+        serde_json::json!({
+            "type": "match", "file": "-", "line": 2,
+            "byte_start": 3, "byte_end": 7, "char_start": 3, "char_end": 5, "text": "éé",
+            "captures": [
+                null,
+                {"group": 2, "name": null, "byte_start": 3, "byte_end": 7,
+                 "char_start": 3, "char_end": 5, "text": "éé"}
+            ]
+        })
+    );
+    assert_eq!(
+        (&v[1]["line"], &v[1]["byte_start"], &v[1]["char_start"]),
+        (&2.into(), &10.into(), &8.into())
+    );
+    // -wで追加したグループは出力しない
+    let v = json(&["-r", "(b)|c", "-E", "-w", "--json"], "a b");
+    assert_eq!(
+        v[0]["captures"],
+        serde_json::json!([{"group": 1, "name": null,
+        "byte_start": 2, "byte_end": 3, "char_start": 2, "char_end": 3, "text": "b"}])
+    );
+    let v = json(&["-r", "b\nc", "-U", "--json"], "ab\ncd");
+    assert_eq!(
+        (&v[0]["line"], &v[0]["char_start"], &v[0]["text"]),
+        (&1.into(), &1.into(), &"b\nc".into())
+    );
+
+    // 不正なバイト列を含む場合も、バイト位置は入力での位置となる
+    for multiline in [false, true] {
+        let mut argv = vec!["regex", "-r", "b", "--json"];
+        if multiline {
+            argv.push("-U");
+        }
+        let args = Args::try_parse_from(&argv).unwrap();
+        let searcher = Searcher::new(&args).unwrap();
+        let mut out = Vec::new();
+        let input = &b"\xff\xffb\n\xe3\x81b\n"[..];
+        searcher.search_reader(input, "-", &mut out).unwrap();
+        let spans = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap())
+            .filter(|v| v["type"] == "match")
+            .map(|v| (v["byte_start"].clone(), v["char_start"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [(2.into(), 2.into()), (6.into(), 5.into())],
+            "{argv:?}"
+        );
+    }
+
+    let positions = LinePositions::new("ab\ncd\néb");
+    assert_eq!(positions.line_col(0), (1, 1));
+    assert_eq!(positions.line_col(2), (1, 3));
+    assert_eq!(positions.line_col(3), (2, 1));
+    assert_eq!(positions.line_col(9), (3, 3));
+}
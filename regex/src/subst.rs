@@ -0,0 +1,615 @@
+//! sed互換の置換を行うsubstサブコマンド
+//!
+//! `[ADDR[,ADDR]]s/PATTERN/REPLACEMENT/FLAGS`の形式のスクリプトを、入力の各行に適用する。
+use crate::SearchMethod;
+use regex::{DynError, Flags, Haystack, Regex};
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/// substサブコマンドの引数
+#[derive(clap::Args)]
+pub struct SubstArgs {
+    /// 置換スクリプト（[ADDR[,ADDR]]s/PATTERN/REPLACEMENT/FLAGS）
+    ///
+    /// ADDRは行番号、最終行を表す`$`、または`/REGEX/`。
+    /// REPLACEMENTでは`&`と`\0`でマッチ全体を、`\1`〜`\9`と`\g<name>`でグループを参照できる。
+    /// FLAGSは`g`（全て置換）、`i`（大文字と小文字を区別しない）、数値N（N番目のマッチを置換）。
+    script: String,
+    /// 入力ファイル。省略時と`-`の場合は標準入力
+    input: Vec<String>,
+    /// ファイルを直接編集。SUFFIXを指定した場合は、元のファイルをバックアップ
+    /// （`*`はファイル名に置き換える）
+    #[arg(short = 'i', long = "in-place", value_name = "SUFFIX", num_args = 0..=1,
+          require_equals = true, default_missing_value = "")]
+    in_place: Option<String>,
+    /// 深さ優先探索
+    #[arg(short, long, value_enum, default_value_t = SearchMethod::Dfs, help = "Search Method")]
+    method: SearchMethod,
+}
+
+/// スクリプトのエラーを表す型。位置はスクリプト中の文字位置
+#[derive(Debug)]
+pub enum ScriptError {
+    InvalidAddress(usize),    // 不正なアドレス
+    NoCommand(usize),         // sコマンドがない
+    Unterminated(usize),      // 区切り文字で終わっていない
+    InvalidFlag(usize, char), // 不明なフラグ
+    InvalidGroup(usize),      // 存在しないグループの参照
+    InvalidInPlace(String),   // 標準入力は直接編集できない
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::InvalidAddress(pos) => {
+                write!(f, "ScriptError: invalid address: pos = {pos}")
+            }
+            ScriptError::NoCommand(pos) => {
+                write!(f, "ScriptError: expected s command: pos = {pos}")
+            }
+            ScriptError::Unterminated(pos) => {
+                write!(f, "ScriptError: unterminated s command: pos = {pos}")
+            }
+            ScriptError::InvalidFlag(pos, c) => {
+                write!(f, "ScriptError: invalid flag: pos = {pos}, char = '{c}'")
+            }
+            ScriptError::InvalidGroup(pos) => {
+                write!(f, "ScriptError: invalid group reference: pos = {pos}")
+            }
+            ScriptError::InvalidInPlace(name) => {
+                write!(f, "ScriptError: cannot edit in place: {name}")
+            }
+        }
+    }
+}
+
+impl Error for ScriptError {}
+
+/// 置換を適用する行の指定
+enum Address {
+    Line(usize),  // 行番号（1始まり）
+    Last,         // 最終行
+    Regex(Regex), // 正規表現にマッチする行
+}
+
+impl Address {
+    fn matches(&self, lineno: usize, line: &str, is_last: bool) -> Result<bool, DynError> {
+        match self {
+            Address::Line(n) => Ok(*n == lineno),
+            Address::Last => Ok(is_last),
+            Address::Regex(re) => Ok(re.find_at(line, 0)?.is_some()),
+        }
+    }
+}
+
+/// 置換後の文字列の要素
+#[derive(Debug, PartialEq)]
+enum Piece {
+    Text(String),
+    Group(usize), // グループの番号。0はマッチ全体
+}
+
+/// パース済みのスクリプト
+pub struct Script {
+    range: Option<(Address, Option<Address>)>,
+    re: Regex,
+    repl: Vec<Piece>,
+    global: bool, // g : nth番目以降のマッチを全て置換
+    nth: usize,   // N : N番目のマッチを置換
+}
+
+impl Script {
+    /// スクリプトをパース
+    pub fn parse(script: &str, is_depth: bool) -> Result<Self, DynError> {
+        let chars = script.chars().collect::<Vec<char>>();
+        let mut pos = 0;
+
+        let range = match parse_address(&chars, &mut pos, is_depth)? {
+            Some(a1) => {
+                let a2 = if chars.get(pos) == Some(&',') {
+                    pos += 1;
+                    match parse_address(&chars, &mut pos, is_depth)? {
+                        Some(a2) => Some(a2),
+                        None => return Err(Box::new(ScriptError::InvalidAddress(pos))),
+                    }
+                } else {
+                    None
+                };
+                Some((a1, a2))
+            }
+            None => None,
+        };
+
+        if chars.get(pos) != Some(&'s') {
+            return Err(Box::new(ScriptError::NoCommand(pos)));
+        }
+        pos += 1;
+        let delim = match chars.get(pos) {
+            Some(c) if *c != '\\' && *c != '\n' => *c,
+            _ => return Err(Box::new(ScriptError::Unterminated(pos))),
+        };
+        pos += 1;
+        let pattern = read_until(&chars, &mut pos, delim, true)?;
+        let repl_pos = pos;
+        let repl = read_until(&chars, &mut pos, delim, false)?;
+
+        let mut flags = Flags::default();
+        let mut global = false;
+        let mut nth = None;
+        while let Some(c) = chars.get(pos) {
+            match c {
+                'g' => global = true,
+                'i' => flags.ignore_case = true,
+                '0'..='9' => {
+                    let n = read_number(&chars, &mut pos);
+                    if n == 0 || nth.is_some() {
+                        return Err(Box::new(ScriptError::InvalidFlag(pos - 1, '0')));
+                    }
+                    nth = Some(n);
+                    continue;
+                }
+                _ => return Err(Box::new(ScriptError::InvalidFlag(pos, *c))),
+            }
+            pos += 1;
+        }
+
+        let re = Regex::with_flags(&pattern, flags, is_depth)?;
+        let repl = parse_replacement(&repl, repl_pos, re.capture_names())?;
+        Ok(Script {
+            range,
+            re,
+            repl,
+            global,
+            nth: nth.unwrap_or(1),
+        })
+    }
+
+    /// 1行を置換し、置換した場合は置換後の行を返す
+    fn replace(&self, line: &str) -> Result<Option<String>, DynError> {
+        let mut result = String::new();
+        let mut pos = 0; // 置換後の文字列に未追加の位置
+        let mut search = Some(0); // 次の探索開始位置
+        let mut count = 0;
+        let mut last_end = None; // 直前のマッチの終了位置
+        let mut replaced = false;
+        while let Some(start) = search {
+            let Some(caps) = self.re.find_captures_at(line, start)? else {
+                break;
+            };
+            let m = caps.get(0).unwrap_or_default();
+            // 空文字列にマッチした場合は1文字進め、行末の場合は終了
+            search = if m.is_empty() {
+                line.char_at(m.end).map(|(_, next)| next)
+            } else {
+                Some(m.end)
+            };
+            // sedと同様に、直前のマッチに隣接する空文字列へのマッチは数えない
+            if m.is_empty() && last_end == Some(m.start) {
+                continue;
+            }
+            last_end = Some(m.end);
+            count += 1;
+            if count < self.nth {
+                continue;
+            }
+
+            result.push_str(&line[pos..m.start]);
+            for piece in &self.repl {
+                match piece {
+                    Piece::Text(s) => result.push_str(s),
+                    Piece::Group(n) => {
+                        if let Some(r) = caps.get(*n) {
+                            result.push_str(&line[r]);
+                        }
+                    }
+                }
+            }
+            pos = m.end;
+            replaced = true;
+            if !self.global {
+                break;
+            }
+        }
+        if !replaced {
+            return Ok(None);
+        }
+        result.push_str(&line[pos..]);
+        Ok(Some(result))
+    }
+
+    /// readerの各行にスクリプトを適用し、outに書き込む。
+    ///
+    /// 置換しなかった行は、読み込んだバイト列をそのまま書き込む。
+    /// 不正なUTF-8を含む行は、置換すると元のバイト列が失われるため置換せず、
+    /// その行番号の一覧を返す。
+    pub fn edit(
+        &self,
+        mut reader: impl BufRead,
+        out: &mut impl Write,
+    ) -> Result<Vec<usize>, DynError> {
+        let mut active = false; // 範囲指定の途中か
+        let mut invalid = Vec::new(); // 不正なUTF-8のため置換しなかった行
+        let mut lineno = 0;
+        let mut line = Vec::new();
+        let mut next = Vec::new();
+        reader.read_until(b'\n', &mut line)?;
+        while !line.is_empty() {
+            // $の判定のため、1行先読みする
+            next.clear();
+            reader.read_until(b'\n', &mut next)?;
+            lineno += 1;
+
+            let (body, nl) = match line.strip_suffix(b"\n") {
+                Some(body) => (body, &b"\n"[..]),
+                None => (&line[..], &b""[..]),
+            };
+            // アドレスの判定は、不正なバイト列を置換文字としたうえで行う
+            let text = String::from_utf8_lossy(body);
+            let is_last = next.is_empty();
+            let replaced = if self.selected(&mut active, lineno, &text, is_last)? {
+                self.replace(&text)?
+            } else {
+                None
+            };
+            match replaced {
+                Some(_) if std::str::from_utf8(body).is_err() => {
+                    invalid.push(lineno);
+                    out.write_all(&line)?;
+                }
+                Some(s) => {
+                    out.write_all(s.as_bytes())?;
+                    out.write_all(nl)?;
+                }
+                None => out.write_all(&line)?,
+            }
+            std::mem::swap(&mut line, &mut next);
+        }
+        Ok(invalid)
+    }
+
+    /// アドレスの指定により、行を置換の対象とするか判定
+    ///
+    /// `ADDR1,ADDR2`の場合、ADDR1にマッチした行からADDR2にマッチする行までを対象とする。
+    /// ADDR2が正規表現の場合は、ADDR1にマッチした次の行から判定する。
+    /// ADDR2がADDR1にマッチした行以前の行番号の場合は、1行のみを対象とする。
+    fn selected(
+        &self,
+        active: &mut bool,
+        lineno: usize,
+        line: &str,
+        is_last: bool,
+    ) -> Result<bool, DynError> {
+        let Some((a1, a2)) = &self.range else {
+            return Ok(true);
+        };
+        let Some(a2) = a2 else {
+            return a1.matches(lineno, line, is_last);
+        };
+        if *active {
+            if a2.matches(lineno, line, is_last)? {
+                *active = false;
+            }
+            return Ok(true);
+        }
+        if !a1.matches(lineno, line, is_last)? {
+            return Ok(false);
+        }
+        *active = match a2 {
+            Address::Line(n) => *n > lineno,
+            _ => true,
+        };
+        Ok(true)
+    }
+
+    /// ファイルを直接編集する。
+    ///
+    /// 同じディレクトリの一時ファイルに書き込んだ後、元のファイルに上書きするようrenameするため、
+    /// 途中でエラーとなっても元のファイルは変更されない。
+    /// suffixが空でない場合は、元のファイルをバックアップする。
+    /// 戻り値は[Script::edit]と同じ。
+    pub fn edit_in_place(&self, path: &Path, suffix: &str) -> Result<Vec<usize>, DynError> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| ScriptError::InvalidInPlace(path.display().to_string()))?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let reader = BufReader::new(File::open(path)?);
+        let permissions = fs::metadata(path)?.permissions();
+        let tmp_path = dir.join(format!(
+            ".{}.regex-{}",
+            name.to_string_lossy(),
+            process::id()
+        ));
+        let tmp = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)?;
+
+        let result = (|| -> Result<Vec<usize>, DynError> {
+            let mut writer = BufWriter::new(tmp);
+            let invalid = self.edit(reader, &mut writer)?;
+            let tmp = writer.into_inner().map_err(|e| e.into_error())?;
+            tmp.set_permissions(permissions)?;
+            tmp.sync_all()?;
+            if !suffix.is_empty() {
+                backup(path, &backup_path(path, suffix))?;
+            }
+            fs::rename(&tmp_path, path)?;
+            Ok(invalid)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+}
+
+/// 行番号、`$`、`/REGEX/`のいずれかのアドレスをパース
+fn parse_address(
+    chars: &[char],
+    pos: &mut usize,
+    is_depth: bool,
+) -> Result<Option<Address>, DynError> {
+    match chars.get(*pos) {
+        Some('0'..='9') => match read_number(chars, pos) {
+            0 => Err(Box::new(ScriptError::InvalidAddress(*pos - 1))),
+            n => Ok(Some(Address::Line(n))),
+        },
+        Some('$') => {
+            *pos += 1;
+            Ok(Some(Address::Last))
+        }
+        Some('/') => {
+            *pos += 1;
+            let pattern = read_until(chars, pos, '/', true)?;
+            Ok(Some(Address::Regex(Regex::new(&pattern, is_depth)?)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// 10進数を読み込む
+fn read_number(chars: &[char], pos: &mut usize) -> usize {
+    let mut n: usize = 0;
+    while let Some(d) = chars.get(*pos).and_then(|c| c.to_digit(10)) {
+        n = n.saturating_mul(10).saturating_add(d as usize);
+        *pos += 1;
+    }
+    n
+}
+
+/// 区切り文字delimの直前までを読み込み、区切り文字の次に進む。
+///
+/// `\`に続く区切り文字は区切り文字そのものとし、それ以外のエスケープはそのまま残す。
+/// is_regexがtrueで、区切り文字が正規表現の特殊文字の場合は、エスケープしたまま残す。
+fn read_until(
+    chars: &[char],
+    pos: &mut usize,
+    delim: char,
+    is_regex: bool,
+) -> Result<String, ScriptError> {
+    let start = *pos;
+    let mut s = String::new();
+    while let Some(c) = chars.get(*pos) {
+        *pos += 1;
+        match c {
+            '\\' => match chars.get(*pos) {
+                Some(d) if *d == delim => {
+                    if is_regex && "+*?|()\\.^$[]-".contains(delim) {
+                        s.push('\\');
+                    }
+                    s.push(delim);
+                    *pos += 1;
+                }
+                Some(d) => {
+                    s.push('\\');
+                    s.push(*d);
+                    *pos += 1;
+                }
+                None => break,
+            },
+            c if *c == delim => return Ok(s),
+            c => s.push(*c),
+        }
+    }
+    Err(ScriptError::Unterminated(start))
+}
+
+/// 置換後の文字列をパース
+///
+/// startはスクリプト中の開始位置で、エラーの表示に用いる。
+fn parse_replacement(
+    repl: &str,
+    start: usize,
+    names: &[Option<String>],
+) -> Result<Vec<Piece>, ScriptError> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = repl.chars().enumerate().peekable();
+    let push = |pieces: &mut Vec<Piece>, text: &mut String, piece| {
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(text)));
+        }
+        pieces.push(piece);
+    };
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '&' => push(&mut pieces, &mut text, Piece::Group(0)),
+            '\\' => match chars.next() {
+                Some((_, d @ '0'..='9')) => {
+                    let n = d.to_digit(10).unwrap() as usize;
+                    if n >= names.len() {
+                        return Err(ScriptError::InvalidGroup(start + i));
+                    }
+                    push(&mut pieces, &mut text, Piece::Group(n));
+                }
+                Some((_, 'g')) if chars.next_if(|(_, c)| *c == '<').is_some() => {
+                    let name = chars
+                        .by_ref()
+                        .map(|(_, c)| c)
+                        .take_while(|c| *c != '>')
+                        .collect::<String>();
+                    let n = names
+                        .iter()
+                        .position(|n| n.as_deref() == Some(name.as_str()))
+                        .ok_or(ScriptError::InvalidGroup(start + i))?;
+                    push(&mut pieces, &mut text, Piece::Group(n));
+                }
+                Some((_, 'n')) => text.push('\n'),
+                Some((_, 't')) => text.push('\t'),
+                Some((_, d)) => text.push(d),
+                None => text.push('\\'),
+            },
+            _ => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// バックアップのパス
+///
+/// suffixに`*`を含む場合は`*`をファイル名に置き換え、
+/// 含まない場合はファイル名の末尾にsuffixを追加する。
+/// `*`を含み、かつ`/`も含む場合は、ファイルのディレクトリからの相対パスとなる。
+fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if suffix.contains('*') {
+        path.with_file_name(suffix.replace('*', &name))
+    } else {
+        path.with_file_name(format!("{name}{suffix}"))
+    }
+}
+
+/// 元のファイルをバックアップ
+///
+/// ハードリンクを作成し、できない場合はコピーする。
+fn backup(path: &Path, backup: &Path) -> io::Result<()> {
+    match fs::remove_file(backup) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => (),
+    }
+    fs::hard_link(path, backup).or_else(|_| fs::copy(path, backup).map(|_| ()))
+}
+
+/// substサブコマンドを実行。
+///
+/// 個々の入力でのエラーは表示して処理を続け、had_errorをtrueにする。
+pub fn run(args: &SubstArgs, had_error: &mut bool) -> Result<(), DynError> {
+    let script = Script::parse(&args.script, args.method == SearchMethod::Dfs)?;
+    let inputs = if args.input.is_empty() {
+        vec!["-".to_string()]
+    } else {
+        args.input.clone()
+    };
+
+    let mut out = io::stdout().lock();
+    for input in &inputs {
+        let result = match (input.as_str(), &args.in_place) {
+            ("-", Some(_)) => Err(ScriptError::InvalidInPlace(input.clone()).into()),
+            ("-", None) => script.edit(io::stdin().lock(), &mut out),
+            (path, Some(suffix)) => script.edit_in_place(Path::new(path), suffix),
+            (path, None) => File::open(path)
+                .map_err(|e| e.into())
+                .and_then(|f| script.edit(BufReader::new(f), &mut out)),
+        };
+        match result {
+            Ok(invalid) => {
+                out.flush()?;
+                for lineno in invalid {
+                    eprintln!("regex: {input}:{lineno}: invalid UTF-8, line left unchanged");
+                }
+            }
+            Err(e) => {
+                out.flush()?;
+                eprintln!("regex: {input}: {e}");
+                *had_error = true;
+            }
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+#[test]
+fn test() {
+    let subst = |script: &str, text: &str| -> String {
+        let script = Script::parse(script, true).unwrap();
+        let mut out = Vec::new();
+        script.edit(text.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(subst("s/o/0/", "foo\nbar\n"), "f0o\nbar\n");
+    assert_eq!(subst("s/o/0/g", "foo\nbar"), "f00\nbar");
+    assert_eq!(subst("s/o/0/2", "fooo\n"), "fo0o\n");
+    assert_eq!(subst("s/o/0/2g", "fooo\n"), "fo00\n");
+    assert_eq!(subst("s/O/0/i", "fo\n"), "f0\n");
+    assert_eq!(subst("s/x*/-/g", "abc\n"), "-a-b-c-\n");
+    assert_eq!(subst("s/a*/x/g", "baaac\n"), "xbxcx\n");
+    assert_eq!(subst("s/い*/-/g", "あいう\n"), "-あ-う-\n");
+
+    // グループの参照
+    assert_eq!(subst("s/(a+)(b+)/\\2\\1[&]/", "xaabb\n"), "xbbaa[aabb]\n");
+    assert_eq!(subst("s/(?<k>[a-z])=/\\g<k>:/", "a=1\n"), "a:1\n");
+    assert_eq!(subst("s/(x)?a/[\\1]\\n\\&/", "a\n"), "[]\n&\n");
+    // 区切り文字
+    assert_eq!(subst("s|/|\\||g", "a/b/c\n"), "a|b|c\n");
+    assert_eq!(subst("s,a\\,b,x,", "a,b\n"), "x\n");
+    assert_eq!(subst("s|a\\|b|x|g", "a|b\n"), "x\n");
+
+    // アドレス
+    let text = "a1\na2\na3\na4\na5\n";
+    assert_eq!(subst("2s/a/b/", text), "a1\nb2\na3\na4\na5\n");
+    assert_eq!(subst("$s/a/b/", text), "a1\na2\na3\na4\nb5\n");
+    assert_eq!(subst("2,4s/a/b/", text), "a1\nb2\nb3\nb4\na5\n");
+    assert_eq!(subst("3,1s/a/b/", text), "a1\na2\nb3\na4\na5\n");
+    assert_eq!(subst("/2/,/4/s/a/b/", text), "a1\nb2\nb3\nb4\na5\n");
+    assert_eq!(subst("/4/,$s/a/b/", text), "a1\na2\na3\nb4\nb5\n");
+    assert_eq!(subst("/3/s/a/b/", text), "a1\na2\nb3\na4\na5\n");
+
+    // 不正なUTF-8を含む行は置換しない
+    let script = Script::parse("s/a/b/", true).unwrap();
+    let mut out = Vec::new();
+    let invalid = script.edit(&b"a\xff\na\n\xfe\n"[..], &mut out).unwrap();
+    assert_eq!(out, b"a\xff\nb\n\xfe\n");
+    assert_eq!(invalid, vec![1]);
+
+    // abnormal case
+    for script in [
+        "", "s", "s/a", "s/a/b", "s/a/b/x", "0s/a/b/", "1,s/a/b/", "p", "s/a/\\1/",
+    ] {
+        assert!(Script::parse(script, true).is_err(), "{script}");
+    }
+
+    // 直接編集
+    let dir = std::env::temp_dir().join(format!("regex-subst-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("a.txt");
+    fs::write(&path, "foo\nbar\n").unwrap();
+    let script = Script::parse("s/o+/0/", true).unwrap();
+    script.edit_in_place(&path, ".bak").unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "f0\nbar\n");
+    assert_eq!(
+        fs::read_to_string(dir.join("a.txt.bak")).unwrap(),
+        "foo\nbar\n"
+    );
+    script.edit_in_place(&path, "old_*").unwrap();
+    assert_eq!(
+        fs::read_to_string(dir.join("old_a.txt")).unwrap(),
+        "f0\nbar\n"
+    );
+    script.edit_in_place(&path, "").unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+    fs::remove_dir_all(&dir).unwrap();
+}
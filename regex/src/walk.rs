@@ -0,0 +1,329 @@
+//! 検索対象のファイルを列挙する
+use crate::Args;
+use regex::{glob::Glob, DynError};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// 検索対象の入力
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    /// 表示用の名前
+    pub fn name(&self) -> String {
+        match self {
+            Input::Stdin => String::from("(standard input)"),
+            Input::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+/// 引数に指定されたパスから、検索対象のファイルを列挙する型
+pub struct Walker<'a> {
+    args: &'a Args,
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+}
+
+impl<'a> Walker<'a> {
+    /// --includeと--excludeのglobをコンパイル
+    pub fn new(args: &'a Args) -> Result<Self, DynError> {
+        let compile = |globs: &[String]| {
+            globs
+                .iter()
+                .map(|g| Glob::new(g))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Walker {
+            args,
+            include: compile(&args.include)?,
+            exclude: compile(&args.exclude)?,
+        })
+    }
+
+    /// 検索対象の入力を、引数の順に列挙する。
+    ///
+    /// パスが指定されていない場合と`-`の場合は標準入力を対象とする。
+    /// ディレクトリは-Rが指定された場合のみ、再帰的に辿る。
+    /// 途中で発生したエラーも、発生した位置に含めて返す。
+    pub fn inputs(&self) -> Vec<Result<Input, DynError>> {
+        let mut inputs = Vec::new();
+        if self.args.input.is_empty() {
+            inputs.push(Ok(Input::Stdin));
+        }
+        for path in &self.args.input {
+            let path = Path::new(path);
+            if path == Path::new("-") {
+                inputs.push(Ok(Input::Stdin));
+            } else if !path.is_dir() {
+                inputs.push(Ok(Input::File(path.to_path_buf())));
+            } else if self.args.recursive {
+                // .gitignoreとの比較は絶対パスで行う
+                let abs = match path.canonicalize() {
+                    Ok(abs) => abs,
+                    Err(e) => {
+                        inputs.push(Err(format!("{}: {e}", path.display()).into()));
+                        continue;
+                    }
+                };
+                let mut ignore = Ignore::default();
+                if !self.args.no_ignore {
+                    ignore.load_parents(&abs);
+                }
+                self.walk(path, path, &abs, &mut ignore, &mut inputs);
+            } else {
+                let msg = format!("{}: Is a directory", path.display());
+                inputs.push(Err(msg.into()));
+            }
+        }
+        inputs
+    }
+
+    /// ディレクトリdirを再帰的に辿る
+    ///
+    /// 引数に指定されたディレクトリはシンボリックリンクでも辿るが、
+    /// 辿る途中で見つけたディレクトリへのシンボリックリンクは辿らない。
+    ///
+    /// rootは引数に指定されたディレクトリで、--includeと--excludeの判定に用いる。
+    /// absはdirの絶対パスで、.gitignoreの判定に用いる。
+    fn walk(
+        &self,
+        root: &Path,
+        dir: &Path,
+        abs: &Path,
+        ignore: &mut Ignore,
+        inputs: &mut Vec<Result<Input, DynError>>,
+    ) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                inputs.push(Err(format!("{}: {e}", dir.display()).into()));
+                return;
+            }
+        };
+        // 種類はシンボリックリンクを辿らずに取得する
+        let mut names = Vec::new();
+        for entry in entries {
+            match entry.and_then(|entry| Ok((entry.file_name(), entry.file_type()?))) {
+                Ok(name) => names.push(name),
+                Err(e) => inputs.push(Err(format!("{}: {e}", dir.display()).into())),
+            }
+        }
+        names.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        // このディレクトリの.gitignoreを読み込み、辿り終えたら取り除く
+        let saved = ignore.rules.len();
+        if !self.args.no_ignore {
+            ignore.load(abs);
+        }
+
+        for (file_name, file_type) in names {
+            let path = dir.join(&file_name);
+            let abs_path = abs.join(&file_name);
+            let name = file_name.to_string_lossy();
+            if name == ".git" || (name.starts_with('.') && !self.args.hidden) {
+                continue;
+            }
+            // grep -rと同様に、ディレクトリへのシンボリックリンクは辿らない（循環する場合があるため）
+            if file_type.is_symlink() && path.is_dir() {
+                continue;
+            }
+            let is_dir = file_type.is_dir();
+            if ignore.is_ignored(&abs_path, is_dir) {
+                continue;
+            }
+            let rel = path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            if self.exclude.iter().any(|g| filter_match(g, &rel, &name)) {
+                continue;
+            }
+            if is_dir {
+                self.walk(root, &path, &abs_path, ignore, inputs);
+            } else if self.include.is_empty()
+                || self.include.iter().any(|g| filter_match(g, &rel, &name))
+            {
+                inputs.push(Ok(Input::File(path)));
+            }
+        }
+
+        ignore.rules.truncate(saved);
+    }
+}
+
+/// --includeと--excludeのglobにマッチするか判定
+///
+/// globが`/`を含む場合は引数のディレクトリからの相対パスと、
+/// それ以外の場合はファイル名と比較する。
+fn filter_match(glob: &Glob, rel: &str, name: &str) -> bool {
+    if glob.as_str().contains('/') {
+        glob.is_match(rel)
+    } else {
+        glob.is_match(name)
+    }
+}
+
+/// .gitignoreの1行を表す型
+#[derive(Debug)]
+struct IgnoreRule {
+    base: PathBuf,  // .gitignoreがあるディレクトリ
+    glob: Glob,     // パターン
+    negated: bool,  // !で始まる場合はtrue
+    dir_only: bool, // /で終わる場合はtrue
+    anchored: bool, // 途中に/を含む場合はtrue。baseからの相対パスと比較する
+}
+
+impl IgnoreRule {
+    /// .gitignoreの1行をパース。空行とコメントの場合はNoneを返す
+    fn parse(base: &Path, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let glob = line.strip_prefix('/').unwrap_or(line);
+        if glob.is_empty() {
+            return None;
+        }
+        // gitと同様に、`{a,b}`は展開しない
+        let glob = Glob::without_braces(glob).ok()?;
+        Some(IgnoreRule {
+            base: base.to_path_buf(),
+            glob,
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let Ok(rel) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        if self.anchored {
+            self.glob
+                .is_match(&rel.to_string_lossy().replace('\\', "/"))
+        } else {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            self.glob.is_match(&name)
+        }
+    }
+}
+
+/// 適用中の.gitignoreの規則
+#[derive(Debug, Default)]
+struct Ignore {
+    rules: Vec<IgnoreRule>,
+}
+
+impl Ignore {
+    /// dirにある.gitignoreを読み込む
+    fn load(&mut self, dir: &Path) {
+        if let Ok(text) = fs::read_to_string(dir.join(".gitignore")) {
+            self.rules
+                .extend(text.lines().filter_map(|line| IgnoreRule::parse(dir, line)));
+        }
+    }
+
+    /// dirの親ディレクトリを、リポジトリのルート（.gitがあるディレクトリ）まで遡り、
+    /// 上位から順に各.gitignoreを読み込む。
+    ///
+    /// dir自身の.gitignoreは辿る際に読み込むため対象外。
+    /// リポジトリ外の場合は何も読み込まない。
+    fn load_parents(&mut self, dir: &Path) {
+        if dir.join(".git").exists() {
+            return;
+        }
+        let parents = dir.ancestors().skip(1).collect::<Vec<_>>();
+        let Some(top) = parents.iter().position(|p| p.join(".git").exists()) else {
+            return;
+        };
+        for parent in parents[..=top].iter().rev() {
+            self.load(parent);
+        }
+    }
+
+    /// 最後にマッチした規則で、無視するかを判定
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if rule.matches(path, is_dir) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+#[test]
+fn test() {
+    let base = Path::new("repo");
+    let rule = IgnoreRule::parse(base, "target/").unwrap();
+    assert!(rule.matches(Path::new("repo/a/target"), true));
+    assert!(!rule.matches(Path::new("repo/a/target"), false));
+    let rule = IgnoreRule::parse(base, "/build").unwrap();
+    assert!(rule.matches(Path::new("repo/build"), false));
+    assert!(!rule.matches(Path::new("repo/a/build"), false));
+    assert!(IgnoreRule::parse(base, "# comment").is_none());
+    let rule = IgnoreRule::parse(base, "*.{log,tmp}").unwrap();
+    assert!(!rule.matches(Path::new("repo/a/x.tmp"), false));
+    assert!(rule.matches(Path::new("repo/a/x.{log,tmp}"), false));
+    // gitと同様に、`*`は先頭の`.`にもマッチする
+    let rule = IgnoreRule::parse(base, "*").unwrap();
+    assert!(rule.matches(Path::new("repo/a/.env"), false));
+
+    let mut ignore = Ignore::default();
+    ignore.rules.extend(
+        ["*.log", "!keep.log"]
+            .iter()
+            .filter_map(|l| IgnoreRule::parse(base, l)),
+    );
+    assert!(ignore.is_ignored(Path::new("repo/x/a.log"), false));
+    assert!(!ignore.is_ignored(Path::new("repo/x/keep.log"), false));
+    assert!(!ignore.is_ignored(Path::new("repo/x/a.txt"), false));
+
+    // ディレクトリへのシンボリックリンクは辿らないため、循環していても終了する
+    #[cfg(unix)]
+    {
+        use clap::Parser;
+        let dir = std::env::temp_dir().join(format!("regex-walk-{}", std::process::id()));
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::write(dir.join("a/x.txt"), "x\n").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("a/loop")).unwrap();
+        std::os::unix::fs::symlink("x.txt", dir.join("a/link.txt")).unwrap();
+        let path = dir.to_string_lossy().to_string();
+        let args = Args::try_parse_from(["regex", "-r", "x", "-R", "--no-ignore", &path]).unwrap();
+        let inputs = Walker::new(&args)
+            .unwrap()
+            .inputs()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inputs,
+            [
+                Input::File(dir.join("a/link.txt")),
+                Input::File(dir.join("a/x.txt"))
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}